@@ -0,0 +1,267 @@
+use std::collections::{HashMap, HashSet};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{mpsc, Arc};
+
+use notify::Watcher;
+
+use crate::gfx::Gfx;
+use crate::texture::{self, Texture, TextureHandle};
+
+const PLACEHOLDER_SIZE: u32 = 8;
+
+// Reading the file covers the first half of `progress`, decoding and converting it the rest.
+// At 1.0 the texture is uploaded by the next `Assets::update`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum LoadState {
+    Loading { progress: f32 },
+    Loaded,
+    Failed(String),
+}
+
+#[derive(Debug)]
+pub enum AssetError {
+    Io(std::io::Error),
+    Image(image::ImageError),
+    // The image can't be converted to the requested texture format.
+    Format(String),
+}
+impl std::fmt::Display for AssetError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "{}", err),
+            Self::Image(err) => write!(f, "{}", err),
+            Self::Format(err) => write!(f, "{}", err),
+        }
+    }
+}
+impl std::error::Error for AssetError {}
+
+struct TextureAsset {
+    handle: TextureHandle,
+    format: wgpu::TextureFormat,
+}
+
+struct DecodedTexture {
+    handle: TextureHandle,
+    result: Result<(u32, u32, Vec<u8>), String>,
+}
+
+pub struct Assets {
+    textures: HashMap<PathBuf, TextureAsset>,
+    watched_dirs: HashSet<PathBuf>,
+    watcher: notify::RecommendedWatcher,
+    events: mpsc::Receiver<notify::Result<notify::Event>>,
+    // Progress is stored as the bits of an f32 so the decoding task can update it.
+    pending: HashMap<TextureHandle, (wgpu::TextureFormat, Arc<AtomicU32>)>,
+    failed: HashMap<TextureHandle, String>,
+    decoded_sender: mpsc::Sender<DecodedTexture>,
+    decoded: mpsc::Receiver<DecodedTexture>,
+}
+impl Assets {
+    pub fn new() -> notify::Result<Self> {
+        let (sender, events) = mpsc::channel();
+        let watcher = notify::recommended_watcher(sender)?;
+        let (decoded_sender, decoded) = mpsc::channel();
+        Ok(Self {
+            textures: HashMap::new(),
+            watched_dirs: HashSet::new(),
+            watcher,
+            events,
+            pending: HashMap::new(),
+            failed: HashMap::new(),
+            decoded_sender,
+            decoded,
+        })
+    }
+    pub fn load_texture(
+        &mut self,
+        gfx: &mut Gfx,
+        path: impl AsRef<Path>,
+    ) -> Result<TextureHandle, AssetError> {
+        let format = texture::default_format(path.as_ref());
+        self.load_texture_with_format(gfx, path, format)
+    }
+    pub fn load_texture_with_format(
+        &mut self,
+        gfx: &mut Gfx,
+        path: impl AsRef<Path>,
+        format: wgpu::TextureFormat,
+    ) -> Result<TextureHandle, AssetError> {
+        let path = path.as_ref().canonicalize().map_err(AssetError::Io)?;
+        if let Some(asset) = self.textures.get(&path) {
+            return Ok(asset.handle);
+        }
+        let image = image::open(&path).map_err(AssetError::Image)?;
+        let texture = Texture::from_image(gfx, &image, format).map_err(AssetError::Format)?;
+        let handle = gfx.add_texture(texture);
+        self.watch(&path);
+        self.textures.insert(path, TextureAsset { handle, format });
+        Ok(handle)
+    }
+    // Returns immediately with a handle that renders a placeholder until the file has been
+    // decoded on the blocking pool and uploaded by `update`. Must be called from within a
+    // tokio runtime.
+    pub fn load_texture_async(&mut self, gfx: &mut Gfx, path: impl AsRef<Path>) -> TextureHandle {
+        let format = texture::default_format(path.as_ref());
+        self.load_texture_async_with_format(gfx, path, format)
+    }
+    pub fn load_texture_async_with_format(
+        &mut self,
+        gfx: &mut Gfx,
+        path: impl AsRef<Path>,
+        format: wgpu::TextureFormat,
+    ) -> TextureHandle {
+        let path = path
+            .as_ref()
+            .canonicalize()
+            .unwrap_or_else(|_| path.as_ref().to_path_buf());
+        if let Some(asset) = self.textures.get(&path) {
+            return asset.handle;
+        }
+        let placeholder = placeholder(gfx);
+        let handle = gfx.add_texture(placeholder);
+        let progress = Arc::new(AtomicU32::new(0.0f32.to_bits()));
+        self.pending.insert(handle, (format, progress.clone()));
+        self.watch(&path);
+        self.textures.insert(path.clone(), TextureAsset { handle, format });
+
+        let sender = self.decoded_sender.clone();
+        tokio::task::spawn_blocking(move || {
+            let result = read_with_progress(&path, &progress)
+                .map_err(|err| err.to_string())
+                .and_then(|bytes| image::load_from_memory(&bytes).map_err(|err| err.to_string()))
+                .and_then(|image| {
+                    progress.store(0.75f32.to_bits(), Ordering::Relaxed);
+                    let bytes = texture::image_bytes(&image, format)?;
+                    Ok((image.width(), image.height(), bytes))
+                });
+            progress.store(1.0f32.to_bits(), Ordering::Relaxed);
+            // The receiver is gone only if the asset server was dropped.
+            let _ = sender.send(DecodedTexture { handle, result });
+        });
+        handle
+    }
+    pub fn load_state(&self, handle: TextureHandle) -> LoadState {
+        if let Some((_, progress)) = self.pending.get(&handle) {
+            LoadState::Loading {
+                progress: f32::from_bits(progress.load(Ordering::Relaxed)),
+            }
+        } else if let Some(err) = self.failed.get(&handle) {
+            LoadState::Failed(err.clone())
+        } else {
+            LoadState::Loaded
+        }
+    }
+    pub fn path(&self, handle: TextureHandle) -> Option<&Path> {
+        self.textures
+            .iter()
+            .find(|(_, asset)| asset.handle == handle)
+            .map(|(path, _)| path.as_path())
+    }
+    // Uploads textures that finished decoding and reloads every watched texture whose file
+    // changed since the last call.
+    pub fn update(&mut self, gfx: &mut Gfx) {
+        for decoded in self.decoded.try_iter() {
+            let Some((format, _)) = self.pending.remove(&decoded.handle) else {
+                continue;
+            };
+            match decoded.result {
+                Ok((width, height, bytes)) => {
+                    let mut data = gfx.data.borrow_mut();
+                    let texture = Texture::create(&data, width, height, format, &bytes);
+                    *data.texture_mut(decoded.handle) = texture;
+                }
+                Err(err) => {
+                    eprintln!("Failed to load texture: {}", err);
+                    self.failed.insert(decoded.handle, err);
+                }
+            }
+        }
+
+        let mut changed = HashSet::new();
+        for event in self.events.try_iter() {
+            match event {
+                Ok(event) if event.kind.is_modify() || event.kind.is_create() => {
+                    changed.extend(event.paths);
+                }
+                Ok(_) => (),
+                Err(err) => eprintln!("Asset watcher error: {}", err),
+            }
+        }
+        let mut data = gfx.data.borrow_mut();
+        for path in changed {
+            let Some(asset) = self.textures.get(&path) else {
+                continue;
+            };
+            if self.pending.contains_key(&asset.handle) {
+                continue;
+            }
+            let replaced = image::open(&path)
+                .map_err(|err| err.to_string())
+                .and_then(|image| Texture::replace(&mut data, asset.handle, &image, asset.format));
+            match replaced {
+                Ok(()) => {
+                    self.failed.remove(&asset.handle);
+                }
+                Err(err) => eprintln!("Failed to reload {}: {}", path.display(), err),
+            }
+        }
+    }
+
+    // Editors usually save by replacing the file, so the parent directory is watched
+    // rather than the file itself.
+    fn watch(&mut self, path: &Path) {
+        let Some(dir) = path.parent() else {
+            return;
+        };
+        if self.watched_dirs.contains(dir) {
+            return;
+        }
+        match self.watcher.watch(dir, notify::RecursiveMode::NonRecursive) {
+            Ok(()) => {
+                self.watched_dirs.insert(dir.to_path_buf());
+            }
+            Err(err) => eprintln!("Failed to watch {}: {}", dir.display(), err),
+        }
+    }
+}
+
+fn placeholder(gfx: &mut Gfx) -> Texture {
+    let bytes = (0..PLACEHOLDER_SIZE * PLACEHOLDER_SIZE)
+        .flat_map(|i| {
+            let (x, y) = (i % PLACEHOLDER_SIZE, i / PLACEHOLDER_SIZE);
+            if (x + y) % 2 == 0 {
+                [255, 0, 255, 255]
+            } else {
+                [0, 0, 0, 255]
+            }
+        })
+        .collect::<Vec<u8>>();
+    Texture::from_raw(
+        gfx,
+        PLACEHOLDER_SIZE,
+        PLACEHOLDER_SIZE,
+        wgpu::TextureFormat::Rgba8UnormSrgb,
+        &bytes,
+    )
+}
+
+// Reports the fraction read as progress from 0.0 to 0.5.
+fn read_with_progress(path: &Path, progress: &AtomicU32) -> std::io::Result<Vec<u8>> {
+    let mut file = std::fs::File::open(path)?;
+    let len = file.metadata()?.len().max(1);
+    let mut bytes = Vec::with_capacity(len as usize);
+    let mut chunk = [0; 64 * 1024];
+    loop {
+        let read = file.read(&mut chunk)?;
+        if read == 0 {
+            break;
+        }
+        bytes.extend_from_slice(&chunk[..read]);
+        let fraction = (bytes.len() as f32 / len as f32).min(1.0) * 0.5;
+        progress.store(fraction.to_bits(), Ordering::Relaxed);
+    }
+    Ok(bytes)
+}
@@ -0,0 +1,668 @@
+use std::cell::Cell;
+use std::ops::Range;
+use std::rc::Rc;
+
+use crate::clip::{Clip, ClipStack, Rect};
+use crate::color::Color;
+use crate::gfx::{Gfx, GfxRenderData, Renderer};
+use crate::pipeline::{PipelineDescriptor, StencilMode};
+use crate::quad::{
+    PipelinePair, Quad, QuadRaw, QuadRenderer, TexturedQuad, TexturedQuadRaw,
+    TexturedQuadRenderer, FILL_QUAD_SHADER, TEXTURED_QUAD_SHADER, instance_buffer,
+    write_instances,
+};
+use crate::shader::Shader;
+use crate::shape::{Shape, ShapeRaw, ShapeRenderer, SHAPE_SHADER};
+use crate::texture::{Texture, TextureHandle};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BatchStats {
+    pub primitives: usize,
+    // Runs of consecutive primitives that share a pipeline and bind groups.
+    pub batches: usize,
+    // Draw calls actually issued, mask passes included. Batches clipped away entirely are
+    // skipped.
+    pub draw_calls: usize,
+}
+
+// Shapes that can be drawn into the stencil buffer to mask other primitives. Colored quads
+// cover their whole rect unless their alpha is below 0.5, textured quads cover the texels whose
+// alpha is at least 0.5, and shapes cover up to the middle of their anti-aliased edge.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mask {
+    Quad(Quad),
+    Textured(TexturedQuad),
+    Shape(Shape),
+}
+
+enum Primitive {
+    Quad(Quad),
+    Textured(TexturedQuad),
+    Shape(Shape),
+    // A colored quad covering the whole view, resolved against the camera at draw time.
+    FullScreen,
+}
+impl From<&Mask> for Primitive {
+    fn from(mask: &Mask) -> Self {
+        match mask {
+            Mask::Quad(quad) => Self::Quad(*quad),
+            Mask::Textured(quad) => Self::Textured(*quad),
+            Mask::Shape(shape) => Self::Shape(*shape),
+        }
+    }
+}
+
+// How a primitive uses the stencil buffer and the mask level it is compared against.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Stencil {
+    mode: StencilMode,
+    reference: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BatchKind {
+    Colored,
+    Textured(TextureHandle),
+    Shape,
+}
+
+struct Batch {
+    kind: BatchKind,
+    // Index into the clip states the batch was submitted under.
+    clip: usize,
+    stencil: Stencil,
+    instances: Range<u32>,
+}
+
+// Stencil-only pipelines that raise or lower the mask level wherever a mask is drawn.
+struct MaskPipelines {
+    increment: Rc<wgpu::RenderPipeline>,
+    decrement: Rc<wgpu::RenderPipeline>,
+}
+impl MaskPipelines {
+    fn new(gfx: &GfxRenderData, desc: PipelineDescriptor) -> Self {
+        Self {
+            increment: gfx.pipelines.get(&gfx.device, &desc.with_stencil(StencilMode::Increment)),
+            decrement: gfx.pipelines.get(&gfx.device, &desc.with_stencil(StencilMode::Decrement)),
+        }
+    }
+    fn get(&self, mode: StencilMode) -> &wgpu::RenderPipeline {
+        match mode {
+            StencilMode::Decrement => &self.decrement,
+            _ => &self.increment,
+        }
+    }
+}
+
+// Draws colored quads, textured quads and shapes in exactly the order they were submitted, merging
+// consecutive primitives of the same kind and texture into one instanced draw. Everything is
+// alpha blended and drawn with the transparent renderers, depth tested but without writing
+// depth, so `z` only decides visibility against other renderers' opaque quads. Primitives are
+// clipped by whatever clips were pushed when they were submitted, and masked by the masks of
+// every `with_mask` scope they were submitted in.
+pub struct Batcher {
+    quad_shader: Shader,
+    textured_shader: Shader,
+    quad_mask_shader: Shader,
+    textured_mask_shader: Shader,
+    shape_shader: Shader,
+    shape_mask_shader: Shader,
+    quad_pipelines: PipelinePair,
+    textured_pipelines: PipelinePair,
+    unfilterable_pipelines: PipelinePair,
+    quad_masks: MaskPipelines,
+    textured_masks: MaskPipelines,
+    unfilterable_masks: MaskPipelines,
+    shape_pipeline: Rc<wgpu::RenderPipeline>,
+    shape_masks: MaskPipelines,
+    quad_instances: wgpu::Buffer,
+    textured_instances: wgpu::Buffer,
+    shape_instances: wgpu::Buffer,
+    // Stands in for normal maps, set when quads are lit.
+    flat_normal: Option<Texture>,
+    // Each primitive with the index of the clip state it was submitted under and how it uses
+    // the stencil buffer.
+    primitives: Vec<(Primitive, usize, Stencil)>,
+    // Masks need the stencil aspect of the depth buffer.
+    stencil_enabled: bool,
+    // Number of masks the current primitives are inside.
+    mask_level: u32,
+    clip_stack: ClipStack,
+    // Every clip stack primitives were submitted under this frame, and their screen rects.
+    clip_states: Vec<ClipStack>,
+    scissors: Vec<Option<Rect>>,
+    batches: Vec<Batch>,
+    stats: BatchStats,
+    // Counted while rendering, which only borrows the batcher.
+    draw_calls: Cell<usize>,
+}
+impl Batcher {
+    pub fn new(gfx: &mut Gfx) -> Self {
+        let gfx = gfx.data.borrow_mut();
+        let quad_shader = Shader::new(&gfx, "fill_quad.wgsl", FILL_QUAD_SHADER, &[]);
+        let textured_shader = Shader::new(&gfx, "textured_quad.wgsl", TEXTURED_QUAD_SHADER, &[]);
+        let quad_pipelines = QuadRenderer::create_pipelines(&gfx, &quad_shader)
+            .unwrap_or_else(|err| panic!("{}: {}", quad_shader.name(), err));
+        let (textured_pipelines, unfilterable_pipelines) =
+            TexturedQuadRenderer::create_pipelines(&gfx, &textured_shader)
+                .unwrap_or_else(|err| panic!("{}: {}", textured_shader.name(), err));
+        let mask_defines = [("ALPHA_MASK", "")];
+        let quad_mask_shader = Shader::new(&gfx, "fill_quad.wgsl", FILL_QUAD_SHADER, &mask_defines);
+        let textured_mask_shader =
+            Shader::new(&gfx, "textured_quad.wgsl", TEXTURED_QUAD_SHADER, &mask_defines);
+        let quad_masks =
+            QuadRenderer::create_pipelines_with(&gfx, &quad_mask_shader, false, MaskPipelines::new)
+                .unwrap_or_else(|err| panic!("{}: {}", quad_mask_shader.name(), err));
+        let (textured_masks, unfilterable_masks) = TexturedQuadRenderer::create_pipelines_with(
+            &gfx,
+            &textured_mask_shader,
+            false,
+            MaskPipelines::new,
+        )
+        .unwrap_or_else(|err| panic!("{}: {}", textured_mask_shader.name(), err));
+        let shape_shader = Shader::new(&gfx, "shape.wgsl", SHAPE_SHADER, &[]);
+        let shape_mask_shader = Shader::new(&gfx, "shape.wgsl", SHAPE_SHADER, &mask_defines);
+        let shape_pipeline = ShapeRenderer::create_pipeline(&gfx, &shape_shader)
+            .unwrap_or_else(|err| panic!("{}: {}", shape_shader.name(), err));
+        let shape_masks =
+            ShapeRenderer::create_pipelines_with(&gfx, &shape_mask_shader, MaskPipelines::new)
+                .unwrap_or_else(|err| panic!("{}: {}", shape_mask_shader.name(), err));
+        let stencil_enabled = gfx
+            .depth
+            .as_ref()
+            .is_some_and(|depth| depth.format.has_stencil_aspect());
+        let quad_instances = instance_buffer(&gfx.device, "batcher_instances", 0);
+        let textured_instances = instance_buffer(&gfx.device, "batcher_instances", 0);
+        let shape_instances = instance_buffer(&gfx.device, "batcher_instances", 0);
+        Self {
+            quad_shader,
+            textured_shader,
+            quad_mask_shader,
+            textured_mask_shader,
+            shape_shader,
+            shape_mask_shader,
+            quad_pipelines,
+            textured_pipelines,
+            unfilterable_pipelines,
+            quad_masks,
+            textured_masks,
+            unfilterable_masks,
+            shape_pipeline,
+            shape_masks,
+            quad_instances,
+            textured_instances,
+            shape_instances,
+            flat_normal: None,
+            primitives: vec![],
+            stencil_enabled,
+            mask_level: 0,
+            clip_stack: ClipStack::default(),
+            clip_states: vec![ClipStack::default()],
+            scissors: vec![],
+            batches: vec![],
+            stats: BatchStats::default(),
+            draw_calls: Cell::new(0),
+        }
+    }
+    // Shades colored and textured quads with the lights from `Gfx::lighting_mut`, as flat
+    // surfaces facing the viewer. Normal maps and shapes are left unlit.
+    pub fn with_lighting(mut self, gfx: &mut Gfx) -> Self {
+        let data = gfx.data.borrow();
+        let defines = [("LIGHTING", "")];
+        self.quad_shader = Shader::new(&data, "fill_quad.wgsl", FILL_QUAD_SHADER, &defines);
+        self.textured_shader =
+            Shader::new(&data, "textured_quad.wgsl", TEXTURED_QUAD_SHADER, &defines);
+        let format = wgpu::TextureFormat::Rgba8Unorm;
+        self.flat_normal = Some(Texture::create(&data, 1, 1, format, &[128, 128, 255, 255]));
+        let quad_shader = &self.quad_shader;
+        self.quad_pipelines =
+            QuadRenderer::create_pipelines_with(&data, quad_shader, true, PipelinePair::new)
+                .unwrap_or_else(|err| panic!("{}: {}", quad_shader.name(), err));
+        let textured_shader = &self.textured_shader;
+        (self.textured_pipelines, self.unfilterable_pipelines) =
+            TexturedQuadRenderer::create_pipelines_with(
+                &data,
+                textured_shader,
+                true,
+                PipelinePair::new,
+            )
+            .unwrap_or_else(|err| panic!("{}: {}", textured_shader.name(), err));
+        self
+    }
+    pub fn quad(&mut self, quad: Quad) {
+        self.push(Primitive::Quad(quad), StencilMode::Test, self.mask_level);
+    }
+    pub fn textured_quad(&mut self, quad: TexturedQuad) {
+        self.push(Primitive::Textured(quad), StencilMode::Test, self.mask_level);
+    }
+    pub fn shape(&mut self, shape: Shape) {
+        self.push(Primitive::Shape(shape), StencilMode::Test, self.mask_level);
+    }
+    // Masks need the depth buffer, with a stencil aspect, enabled before the batcher is
+    // created.
+    pub fn masks_supported(&self) -> bool {
+        self.stencil_enabled
+    }
+    // Only draws primitives submitted inside `f` where they overlap `mask`. Masks nest, so
+    // primitives inside several scopes are drawn where every mask overlaps. Panics unless
+    // `masks_supported`.
+    pub fn with_mask(&mut self, mask: &[Mask], f: impl FnOnce(&mut Self)) {
+        self.assert_masks_supported();
+        let level = self.mask_level;
+        let clip = self.clip_states.len() - 1;
+        self.push_masks(mask, StencilMode::Increment, level, clip);
+        self.mask_level += 1;
+        f(self);
+        self.mask_level -= 1;
+        self.push_masks(mask, StencilMode::Decrement, level + 1, clip);
+    }
+    // Like `with_mask`, but only draws primitives where they don't overlap `mask`.
+    pub fn with_inverted_mask(&mut self, mask: &[Mask], f: impl FnOnce(&mut Self)) {
+        self.assert_masks_supported();
+        // Raise the whole view a level, then lower it back again under the mask.
+        let level = self.mask_level;
+        let clip = self.clip_states.len() - 1;
+        self.push_at(Primitive::FullScreen, StencilMode::Increment, level, clip);
+        self.push_masks(mask, StencilMode::Decrement, level + 1, clip);
+        self.mask_level += 1;
+        f(self);
+        self.mask_level -= 1;
+        self.push_at(Primitive::FullScreen, StencilMode::Decrement, level + 1, clip);
+    }
+    // Clips primitives submitted until the matching `pop_clip`. Nested clips intersect.
+    pub fn push_clip(&mut self, clip: Clip) {
+        self.clip_stack.push(clip);
+        self.clip_states.push(self.clip_stack.clone());
+    }
+    pub fn pop_clip(&mut self) {
+        self.clip_stack.pop();
+        self.clip_states.push(self.clip_stack.clone());
+    }
+    // Also drops any clips still pushed.
+    pub fn clear(&mut self) {
+        self.primitives.clear();
+        self.mask_level = 0;
+        self.clip_stack = ClipStack::default();
+        self.clip_states = vec![ClipStack::default()];
+    }
+    // Counts for the most recently drawn frame.
+    pub fn stats(&self) -> BatchStats {
+        BatchStats {
+            draw_calls: self.draw_calls.get(),
+            ..self.stats
+        }
+    }
+
+    fn assert_masks_supported(&self) {
+        assert!(
+            self.stencil_enabled,
+            "Batcher masks need a depth buffer with a stencil aspect"
+        );
+    }
+    fn push(&mut self, primitive: Primitive, mode: StencilMode, reference: u32) {
+        self.push_at(primitive, mode, reference, self.clip_states.len() - 1);
+    }
+    fn push_at(&mut self, primitive: Primitive, mode: StencilMode, reference: u32, clip: usize) {
+        self.primitives.push((primitive, clip, Stencil { mode, reference }));
+    }
+    // Masks are drawn under the clip their scope was opened in, so the stencil is restored
+    // exactly where it was changed even if clips are left pushed inside the scope.
+    fn push_masks(&mut self, mask: &[Mask], mode: StencilMode, reference: u32, clip: usize) {
+        for mask in mask {
+            self.push_at(mask.into(), mode, reference, clip);
+        }
+    }
+    fn pipeline<'a>(&'a self, data: &'a GfxRenderData, batch: &Batch) -> &'a wgpu::RenderPipeline {
+        let mode = batch.stencil.mode;
+        let filterable = match batch.kind {
+            BatchKind::Colored if mode == StencilMode::Test => return self.quad_pipelines.get(true),
+            BatchKind::Colored => return self.quad_masks.get(mode),
+            BatchKind::Shape if mode == StencilMode::Test => return &self.shape_pipeline,
+            BatchKind::Shape => return self.shape_masks.get(mode),
+            BatchKind::Textured(handle) => data.texture(handle).filterable,
+        };
+        match (mode, filterable) {
+            (StencilMode::Test, true) => self.textured_pipelines.get(true),
+            (StencilMode::Test, false) => self.unfilterable_pipelines.get(true),
+            (_, true) => self.textured_masks.get(mode),
+            (_, false) => self.unfilterable_masks.get(mode),
+        }
+    }
+    fn reload_shaders(&mut self, data: &GfxRenderData) {
+        let lit = self.flat_normal.is_some();
+        if self.quad_shader.poll(data) {
+            let shader = &self.quad_shader;
+            match QuadRenderer::create_pipelines_with(data, shader, lit, PipelinePair::new) {
+                Ok(pipelines) => self.quad_pipelines = pipelines,
+                Err(err) => eprintln!("{}: {}", self.quad_shader.name(), err),
+            }
+        }
+        if self.textured_shader.poll(data) {
+            let shader = &self.textured_shader;
+            let pipelines =
+                TexturedQuadRenderer::create_pipelines_with(data, shader, lit, PipelinePair::new);
+            match pipelines {
+                Ok(pipelines) => (self.textured_pipelines, self.unfilterable_pipelines) = pipelines,
+                Err(err) => eprintln!("{}: {}", self.textured_shader.name(), err),
+            }
+        }
+        if self.quad_mask_shader.poll(data) {
+            match QuadRenderer::create_pipelines_with(
+                data,
+                &self.quad_mask_shader,
+                false,
+                MaskPipelines::new,
+            ) {
+                Ok(pipelines) => self.quad_masks = pipelines,
+                Err(err) => eprintln!("{}: {}", self.quad_mask_shader.name(), err),
+            }
+        }
+        if self.textured_mask_shader.poll(data) {
+            match TexturedQuadRenderer::create_pipelines_with(
+                data,
+                &self.textured_mask_shader,
+                false,
+                MaskPipelines::new,
+            ) {
+                Ok(pipelines) => (self.textured_masks, self.unfilterable_masks) = pipelines,
+                Err(err) => eprintln!("{}: {}", self.textured_mask_shader.name(), err),
+            }
+        }
+        if self.shape_shader.poll(data) {
+            match ShapeRenderer::create_pipeline(data, &self.shape_shader) {
+                Ok(pipeline) => self.shape_pipeline = pipeline,
+                Err(err) => eprintln!("{}: {}", self.shape_shader.name(), err),
+            }
+        }
+        if self.shape_mask_shader.poll(data) {
+            match ShapeRenderer::create_pipelines_with(
+                data,
+                &self.shape_mask_shader,
+                MaskPipelines::new,
+            ) {
+                Ok(pipelines) => self.shape_masks = pipelines,
+                Err(err) => eprintln!("{}: {}", self.shape_mask_shader.name(), err),
+            }
+        }
+    }
+}
+impl Renderer for Batcher {
+    fn occluders(&self, occluders: &mut Vec<Rect>) {
+        for (primitive, _, stencil) in self.primitives.iter() {
+            match primitive {
+                Primitive::Quad(quad) if quad.casts_shadow && stencil.mode == StencilMode::Test => {
+                    occluders.push(quad.rect())
+                }
+                _ => {}
+            }
+        }
+    }
+    fn prepare(&mut self, data: &GfxRenderData) {
+        self.reload_shaders(data);
+        let size = [data.size.width as f32, data.size.height as f32];
+        let screen = covering_quad(data.camera.view_rect(size));
+        let (batches, quads, textured, shapes) = build_batches(&self.primitives, &screen);
+        self.batches = batches;
+        self.scissors = self
+            .clip_states
+            .iter()
+            .map(|clips| clips.resolve(&data.camera))
+            .collect();
+        let label = "batcher_instances";
+        write_instances(data, &mut self.quad_instances, label, bytemuck::cast_slice(&quads));
+        let textured = bytemuck::cast_slice(&textured);
+        write_instances(data, &mut self.textured_instances, label, textured);
+        write_instances(data, &mut self.shape_instances, label, bytemuck::cast_slice(&shapes));
+        self.stats = BatchStats {
+            primitives: self.primitives.len(),
+            batches: self.batches.len(),
+            draw_calls: 0,
+        };
+    }
+    fn render<'a, 'b>(
+        &'a self,
+        _data: &'a GfxRenderData,
+        _render_pass: &mut wgpu::RenderPass<'b>,
+    )
+    where
+        'a: 'b
+    {
+    }
+    fn render_transparent<'a, 'b>(
+        &'a self,
+        data: &'a GfxRenderData,
+        render_pass: &mut wgpu::RenderPass<'b>,
+    )
+    where
+        'a: 'b
+    {
+        self.draw_calls.set(0);
+        if self.batches.is_empty() {
+            return;
+        }
+        data.quad_geometry.set_buffers(render_pass);
+        render_pass.set_bind_group(0, data.view.bind_group(), &[]);
+        // Lit textured quads take the flat normal and lights in groups 2 and 3, lit colored quads
+        // the lights in group 1.
+        if let Some(flat_normal) = self.flat_normal.as_ref() {
+            render_pass.set_bind_group(2, &flat_normal.bind_group, &[]);
+            render_pass.set_bind_group(3, data.lights.bind_group(), &[]);
+        }
+        let mut clip = None;
+        let mut visible = true;
+        let mut reference = 0;
+        for batch in self.batches.iter() {
+            if clip != Some(batch.clip) {
+                clip = Some(batch.clip);
+                visible = data.set_scissor(render_pass, self.scissors[batch.clip]);
+            }
+            if !visible {
+                continue;
+            }
+            if batch.stencil.reference != reference {
+                reference = batch.stencil.reference;
+                render_pass.set_stencil_reference(reference);
+            }
+            render_pass.set_pipeline(self.pipeline(data, batch));
+            match batch.kind {
+                BatchKind::Colored => {
+                    render_pass.set_vertex_buffer(1, self.quad_instances.slice(..));
+                    if self.flat_normal.is_some() {
+                        render_pass.set_bind_group(1, data.lights.bind_group(), &[]);
+                    }
+                }
+                BatchKind::Textured(handle) => {
+                    render_pass.set_vertex_buffer(1, self.textured_instances.slice(..));
+                    render_pass.set_bind_group(1, &data.texture(handle).bind_group, &[]);
+                }
+                BatchKind::Shape => {
+                    render_pass.set_vertex_buffer(1, self.shape_instances.slice(..));
+                }
+            }
+            render_pass.draw_indexed(
+                0..data.quad_geometry.index_count(),
+                0,
+                batch.instances.clone(),
+            );
+            self.draw_calls.set(self.draw_calls.get() + 1);
+        }
+    }
+}
+
+// A quad covering `rect` exactly, for full screen primitives.
+fn covering_quad(rect: Rect) -> Quad {
+    Quad {
+        pos: [rect.x + rect.width * 0.5, rect.y + rect.height * 0.5],
+        z: 0.0,
+        width: rect.width,
+        height: rect.height,
+        color: Color::WHITE,
+        casts_shadow: false,
+        box_shadow: None,
+    }
+}
+
+// Splits primitives into runs of the same kind, clip and stencil use, with the instance data
+// of each kind in submission order. Full screen primitives are drawn as `screen`.
+fn build_batches(
+    primitives: &[(Primitive, usize, Stencil)],
+    screen: &Quad,
+) -> (Vec<Batch>, Vec<QuadRaw>, Vec<TexturedQuadRaw>, Vec<ShapeRaw>) {
+    let mut batches: Vec<Batch> = vec![];
+    let mut quads = vec![];
+    let mut textured = vec![];
+    let mut shapes = vec![];
+    for (primitive, clip, stencil) in primitives {
+        let (kind, index) = match primitive {
+            Primitive::Quad(quad) => {
+                quads.push(QuadRaw::from(quad));
+                (BatchKind::Colored, quads.len() as u32 - 1)
+            }
+            Primitive::FullScreen => {
+                quads.push(QuadRaw::from(screen));
+                (BatchKind::Colored, quads.len() as u32 - 1)
+            }
+            Primitive::Textured(quad) => {
+                textured.push(TexturedQuadRaw::from(quad));
+                (BatchKind::Textured(quad.texture), textured.len() as u32 - 1)
+            }
+            Primitive::Shape(shape) => {
+                shapes.push(ShapeRaw::from(shape));
+                (BatchKind::Shape, shapes.len() as u32 - 1)
+            }
+        };
+        match batches.last_mut() {
+            Some(batch)
+                if batch.kind == kind && batch.clip == *clip && batch.stencil == *stencil =>
+            {
+                batch.instances.end = index + 1
+            }
+            _ => batches.push(Batch {
+                kind,
+                clip: *clip,
+                stencil: *stencil,
+                instances: index..index + 1,
+            }),
+        }
+    }
+    (batches, quads, textured, shapes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera::Camera;
+
+    fn quad() -> Quad {
+        Quad {
+            pos: [0.0, 0.0],
+            z: 0.0,
+            width: 1.0,
+            height: 1.0,
+            color: Color::WHITE,
+            casts_shadow: false,
+            box_shadow: None,
+        }
+    }
+    fn textured(texture: usize) -> Primitive {
+        Primitive::Textured(TexturedQuad {
+            pos: [0.0, 0.0],
+            z: 0.0,
+            width: 1.0,
+            height: 1.0,
+            texture: TextureHandle(texture),
+            normal_map: None,
+        })
+    }
+    fn stencil(mode: StencilMode, reference: u32) -> Stencil {
+        Stencil { mode, reference }
+    }
+    fn batches(primitives: &[(Primitive, usize, Stencil)]) -> Vec<(BatchKind, usize, Range<u32>)> {
+        build_batches(primitives, &quad())
+            .0
+            .iter()
+            .map(|batch| (batch.kind, batch.clip, batch.instances.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn merges_consecutive_primitives_in_order() {
+        let test = stencil(StencilMode::Test, 0);
+        let primitives = [
+            (Primitive::Quad(quad()), 0, test),
+            (Primitive::Quad(quad()), 0, test),
+            (textured(0), 0, test),
+            (textured(0), 0, test),
+            (textured(1), 0, test),
+            (Primitive::Quad(quad()), 0, test),
+            (textured(1), 0, test),
+            (textured(1), 1, test),
+        ];
+        assert_eq!(
+            batches(&primitives),
+            [
+                (BatchKind::Colored, 0, 0..2),
+                (BatchKind::Textured(TextureHandle(0)), 0, 0..2),
+                (BatchKind::Textured(TextureHandle(1)), 0, 2..3),
+                (BatchKind::Colored, 0, 2..3),
+                (BatchKind::Textured(TextureHandle(1)), 0, 3..4),
+                (BatchKind::Textured(TextureHandle(1)), 1, 4..5),
+            ]
+        );
+        let (_, quads, textured, _) = build_batches(&primitives, &quad());
+        assert_eq!((quads.len(), textured.len()), (3, 5));
+    }
+
+    #[test]
+    fn batches_shapes_apart_from_quads() {
+        let test = stencil(StencilMode::Test, 0);
+        let shape = || Primitive::Shape(Shape::circle([0.0, 0.0], 1.0, Color::WHITE));
+        let primitives = [
+            (shape(), 0, test),
+            (shape(), 0, test),
+            (Primitive::Quad(quad()), 0, test),
+            (shape(), 0, test),
+        ];
+        assert_eq!(
+            batches(&primitives),
+            [
+                (BatchKind::Shape, 0, 0..2),
+                (BatchKind::Colored, 0, 0..1),
+                (BatchKind::Shape, 0, 2..3),
+            ]
+        );
+    }
+
+    #[test]
+    fn full_screen_quad_covers_the_view() {
+        let camera = Camera {
+            position: [100.0, -50.0],
+            zoom: 2.0,
+        };
+        let view = camera.view_rect([800.0, 600.0]);
+        assert_eq!(view, Rect::new(100.0, -50.0, 400.0, 300.0));
+        assert_eq!(covering_quad(view).rect(), view);
+    }
+
+    #[test]
+    fn splits_batches_at_mask_boundaries() {
+        let primitives = [
+            (Primitive::Quad(quad()), 0, stencil(StencilMode::Test, 0)),
+            (Primitive::FullScreen, 0, stencil(StencilMode::Increment, 0)),
+            (Primitive::Quad(quad()), 0, stencil(StencilMode::Decrement, 1)),
+            (Primitive::Quad(quad()), 0, stencil(StencilMode::Test, 1)),
+            (Primitive::Quad(quad()), 0, stencil(StencilMode::Test, 1)),
+            (Primitive::FullScreen, 0, stencil(StencilMode::Decrement, 1)),
+        ];
+        assert_eq!(
+            batches(&primitives),
+            [
+                (BatchKind::Colored, 0, 0..1),
+                (BatchKind::Colored, 0, 1..2),
+                (BatchKind::Colored, 0, 2..3),
+                (BatchKind::Colored, 0, 3..5),
+                (BatchKind::Colored, 0, 5..6),
+            ]
+        );
+    }
+}
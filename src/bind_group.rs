@@ -0,0 +1,128 @@
+// Builds bind group layouts with bindings numbered in the order they are added.
+#[derive(Clone, Default)]
+pub struct BindGroupLayoutBuilder {
+    entries: Vec<wgpu::BindGroupLayoutEntry>,
+}
+impl BindGroupLayoutBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn entry(mut self, visibility: wgpu::ShaderStages, ty: wgpu::BindingType) -> Self {
+        self.entries.push(wgpu::BindGroupLayoutEntry {
+            binding: self.entries.len() as u32,
+            visibility,
+            ty,
+            count: None,
+        });
+        self
+    }
+    pub fn uniform(self, visibility: wgpu::ShaderStages) -> Self {
+        self.entry(
+            visibility,
+            wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+        )
+    }
+    pub fn storage(self, visibility: wgpu::ShaderStages, read_only: bool) -> Self {
+        self.entry(
+            visibility,
+            wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+        )
+    }
+    pub fn texture(
+        self,
+        visibility: wgpu::ShaderStages,
+        sample_type: wgpu::TextureSampleType,
+        view_dimension: wgpu::TextureViewDimension,
+    ) -> Self {
+        self.entry(
+            visibility,
+            wgpu::BindingType::Texture {
+                multisampled: false,
+                view_dimension,
+                sample_type,
+            },
+        )
+    }
+    pub fn texture_2d(self, visibility: wgpu::ShaderStages, filterable: bool) -> Self {
+        self.texture(
+            visibility,
+            wgpu::TextureSampleType::Float { filterable },
+            wgpu::TextureViewDimension::D2,
+        )
+    }
+    pub fn sampler(self, visibility: wgpu::ShaderStages, filtering: bool) -> Self {
+        let ty = if filtering {
+            wgpu::SamplerBindingType::Filtering
+        } else {
+            wgpu::SamplerBindingType::NonFiltering
+        };
+        self.entry(visibility, wgpu::BindingType::Sampler(ty))
+    }
+    // Turns the last added binding into a binding array of `count` resources.
+    pub fn count(mut self, count: u32) -> Self {
+        if let Some(entry) = self.entries.last_mut() {
+            entry.count = std::num::NonZeroU32::new(count);
+        }
+        self
+    }
+    pub fn entries(&self) -> &[wgpu::BindGroupLayoutEntry] {
+        &self.entries
+    }
+    pub fn build(&self, device: &wgpu::Device, label: Option<&str>) -> wgpu::BindGroupLayout {
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label,
+            entries: &self.entries,
+        })
+    }
+}
+
+// Builds bind groups with bindings numbered in the order resources are added, matching a
+// layout made with `BindGroupLayoutBuilder`.
+#[derive(Default)]
+pub struct BindGroupBuilder<'a> {
+    entries: Vec<wgpu::BindGroupEntry<'a>>,
+}
+impl<'a> BindGroupBuilder<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn resource(mut self, resource: wgpu::BindingResource<'a>) -> Self {
+        self.entries.push(wgpu::BindGroupEntry {
+            binding: self.entries.len() as u32,
+            resource,
+        });
+        self
+    }
+    pub fn buffer(self, buffer: &'a wgpu::Buffer) -> Self {
+        self.resource(buffer.as_entire_binding())
+    }
+    pub fn texture_view(self, view: &'a wgpu::TextureView) -> Self {
+        self.resource(wgpu::BindingResource::TextureView(view))
+    }
+    pub fn sampler(self, sampler: &'a wgpu::Sampler) -> Self {
+        self.resource(wgpu::BindingResource::Sampler(sampler))
+    }
+    pub fn texture_view_array(self, views: &'a [&'a wgpu::TextureView]) -> Self {
+        self.resource(wgpu::BindingResource::TextureViewArray(views))
+    }
+    pub fn build(
+        &self,
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        label: Option<&str>,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label,
+            layout,
+            entries: &self.entries,
+        })
+    }
+}
@@ -0,0 +1,46 @@
+#include "post_process"
+
+struct Bloom {
+    // Brightness above which colors glow.
+    threshold: f32,
+    // Width of the soft transition below the threshold.
+    knee: f32,
+};
+
+@group(1) @binding(0)
+var<uniform> effect_uniforms: Bloom;
+
+#ifdef UPSAMPLE
+// 3x3 tent filter over the smaller level, added onto the next larger one.
+fn effect(in: PostInput) -> vec4<f32> {
+    let t = source_texel();
+    var sum = sample_source(in.uv).rgb * 4.0;
+    sum += sample_source(in.uv + vec2<f32>(-t.x, 0.0)).rgb * 2.0;
+    sum += sample_source(in.uv + vec2<f32>(t.x, 0.0)).rgb * 2.0;
+    sum += sample_source(in.uv + vec2<f32>(0.0, -t.y)).rgb * 2.0;
+    sum += sample_source(in.uv + vec2<f32>(0.0, t.y)).rgb * 2.0;
+    sum += sample_source(in.uv + vec2<f32>(-t.x, -t.y)).rgb;
+    sum += sample_source(in.uv + vec2<f32>(t.x, -t.y)).rgb;
+    sum += sample_source(in.uv + vec2<f32>(-t.x, t.y)).rgb;
+    sum += sample_source(in.uv + vec2<f32>(t.x, t.y)).rgb;
+    return vec4<f32>(sum / 16.0, 1.0);
+}
+#else
+// Four bilinear taps averaging the 4x4 source texels around the target texel.
+fn effect(in: PostInput) -> vec4<f32> {
+    let t = source_texel();
+    var color = (sample_source(in.uv + vec2<f32>(-t.x, -t.y)).rgb
+        + sample_source(in.uv + vec2<f32>(t.x, -t.y)).rgb
+        + sample_source(in.uv + vec2<f32>(-t.x, t.y)).rgb
+        + sample_source(in.uv + vec2<f32>(t.x, t.y)).rgb) * 0.25;
+#ifdef PREFILTER
+    let brightness = max(color.r, max(color.g, color.b));
+    let threshold = effect_uniforms.threshold;
+    let knee = max(effect_uniforms.knee, 0.0001);
+    let soft = clamp(brightness - threshold + knee, 0.0, 2.0 * knee);
+    let contribution = max(soft * soft / (4.0 * knee), brightness - threshold);
+    color *= contribution / max(brightness, 0.0001);
+#endif
+    return vec4<f32>(color, 1.0);
+}
+#endif
@@ -0,0 +1,78 @@
+use crate::clip::Rect;
+
+// Maps world coordinates to pixels: `position` is the world point shown at the top-left corner
+// and one world unit covers `zoom` pixels. The default camera leaves quad positions in pixels.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Camera {
+    pub position: [f32; 2],
+    pub zoom: f32,
+}
+impl Default for Camera {
+    fn default() -> Self {
+        Self {
+            position: [0.0, 0.0],
+            zoom: 1.0,
+        }
+    }
+}
+impl Camera {
+    pub fn world_to_screen(&self, point: [f32; 2]) -> [f32; 2] {
+        [
+            (point[0] - self.position[0]) * self.zoom,
+            (point[1] - self.position[1]) * self.zoom,
+        ]
+    }
+    pub fn screen_to_world(&self, point: [f32; 2]) -> [f32; 2] {
+        [
+            point[0] / self.zoom + self.position[0],
+            point[1] / self.zoom + self.position[1],
+        ]
+    }
+    // The world rect shown on a target `size` pixels large.
+    pub fn view_rect(&self, size: [f32; 2]) -> Rect {
+        Rect::new(
+            self.position[0],
+            self.position[1],
+            size[0] / self.zoom,
+            size[1] / self.zoom,
+        )
+    }
+}
+
+// Matches `View` in quad_transform.wgsl, padded to 32 bytes.
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct ViewUniform {
+    size: [f32; 2],
+    camera: [f32; 2],
+    zoom: f32,
+    snap: u32,
+    _padding: [f32; 2],
+}
+impl ViewUniform {
+    // `snap` rounds quad edges to whole pixels, see `Gfx::set_virtual_resolution`.
+    pub fn new(size: winit::dpi::PhysicalSize<u32>, camera: &Camera, snap: bool) -> Self {
+        Self {
+            size: [size.width as f32, size.height as f32],
+            camera: camera.position,
+            zoom: camera.zoom,
+            snap: snap as u32,
+            _padding: [0.0; 2],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn screen_and_world_round_trip() {
+        let camera = Camera {
+            position: [100.0, -50.0],
+            zoom: 2.0,
+        };
+        assert_eq!(camera.world_to_screen([110.0, -40.0]), [20.0, 20.0]);
+        assert_eq!(camera.screen_to_world([20.0, 20.0]), [110.0, -40.0]);
+    }
+}
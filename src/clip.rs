@@ -0,0 +1,111 @@
+use crate::camera::Camera;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rect {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+impl Rect {
+    pub fn new(x: f32, y: f32, width: f32, height: f32) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+    // Rects that don't overlap intersect to an empty rect.
+    pub fn intersect(&self, other: &Rect) -> Rect {
+        let x = self.x.max(other.x);
+        let y = self.y.max(other.y);
+        let right = (self.x + self.width).min(other.x + other.width);
+        let bottom = (self.y + self.height).min(other.y + other.height);
+        Rect::new(x, y, (right - x).max(0.0), (bottom - y).max(0.0))
+    }
+    pub fn is_empty(&self) -> bool {
+        self.width <= 0.0 || self.height <= 0.0
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Clip {
+    // Pixels from the top-left of the render target.
+    Screen(Rect),
+    // World coordinates, moving with the camera.
+    World(Rect),
+}
+impl Clip {
+    pub fn to_screen(&self, camera: &Camera) -> Rect {
+        match self {
+            Self::Screen(rect) => *rect,
+            Self::World(rect) => {
+                let [x, y] = camera.world_to_screen([rect.x, rect.y]);
+                Rect::new(x, y, rect.width * camera.zoom, rect.height * camera.zoom)
+            }
+        }
+    }
+}
+
+// Nested clips. What's visible is the intersection of every clip on the stack, resolved
+// against the camera at draw time so world clips follow it.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ClipStack {
+    clips: Vec<Clip>,
+}
+impl ClipStack {
+    pub fn push(&mut self, clip: Clip) {
+        self.clips.push(clip);
+    }
+    pub fn pop(&mut self) -> Option<Clip> {
+        self.clips.pop()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.clips.is_empty()
+    }
+    // None when nothing is clipped.
+    pub fn resolve(&self, camera: &Camera) -> Option<Rect> {
+        self.clips
+            .iter()
+            .map(|clip| clip.to_screen(camera))
+            .reduce(|a, b| a.intersect(&b))
+    }
+}
+
+// Whole pixels covering `rect`, clamped to a target of `size`.
+pub(crate) fn scissor_rect(rect: Rect, size: winit::dpi::PhysicalSize<u32>) -> [u32; 4] {
+    let clamp = |value: f32, max: u32| (value.max(0.0) as u32).min(max);
+    let x = clamp(rect.x.floor(), size.width);
+    let y = clamp(rect.y.floor(), size.height);
+    let right = clamp((rect.x + rect.width).ceil(), size.width);
+    let bottom = clamp((rect.y + rect.height).ceil(), size.height);
+    [x, y, right.saturating_sub(x), bottom.saturating_sub(y)]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nested_clips_intersect() {
+        let camera = Camera {
+            position: [10.0, 0.0],
+            zoom: 2.0,
+        };
+        let mut stack = ClipStack::default();
+        assert_eq!(stack.resolve(&camera), None);
+        stack.push(Clip::Screen(Rect::new(0.0, 0.0, 100.0, 100.0)));
+        stack.push(Clip::World(Rect::new(30.0, 20.0, 50.0, 10.0)));
+        assert_eq!(stack.resolve(&camera), Some(Rect::new(40.0, 40.0, 60.0, 20.0)));
+        stack.push(Clip::Screen(Rect::new(200.0, 0.0, 10.0, 10.0)));
+        assert!(stack.resolve(&camera).unwrap().is_empty());
+    }
+
+    #[test]
+    fn scissor_covers_partial_pixels() {
+        let size = winit::dpi::PhysicalSize::new(100, 50);
+        assert_eq!(scissor_rect(Rect::new(1.5, -4.0, 10.0, 100.0), size), [1, 0, 11, 50]);
+        assert_eq!(scissor_rect(Rect::new(120.0, 0.0, 10.0, 10.0), size), [100, 0, 0, 10]);
+    }
+}
@@ -0,0 +1,46 @@
+#include "post_process"
+
+struct Grading {
+    // Input range of each LUT in xyz.
+    domain_min: vec4<f32>,
+    domain_max: vec4<f32>,
+    blend_domain_min: vec4<f32>,
+    blend_domain_max: vec4<f32>,
+    // 0 leaves the frame unchanged, 1 applies the LUTs fully.
+    strength: f32,
+    // 0 uses the first LUT, 1 the second.
+    blend: f32,
+};
+
+@group(1) @binding(0)
+var<uniform> effect_uniforms: Grading;
+
+@group(2) @binding(0)
+var lut: texture_3d<f32>;
+@group(2) @binding(1)
+var blend_lut: texture_3d<f32>;
+@group(2) @binding(2)
+var lut_sampler: sampler;
+
+// Samples between texel centers, so the domain bounds map exactly onto the outermost entries.
+fn apply_lut(
+    table: texture_3d<f32>,
+    color: vec3<f32>,
+    domain_min: vec3<f32>,
+    domain_max: vec3<f32>,
+) -> vec3<f32> {
+    let size = vec3<f32>(textureDimensions(table));
+    let coord = saturate((color - domain_min) / (domain_max - domain_min));
+    return textureSample(table, lut_sampler, (coord * (size - 1.0) + 0.5) / size).rgb;
+}
+
+fn effect(in: PostInput) -> vec4<f32> {
+    let color = sample_source(in.uv);
+    let u = effect_uniforms;
+    let graded = mix(
+        apply_lut(lut, color.rgb, u.domain_min.xyz, u.domain_max.xyz),
+        apply_lut(blend_lut, color.rgb, u.blend_domain_min.xyz, u.blend_domain_max.xyz),
+        u.blend,
+    );
+    return vec4<f32>(mix(color.rgb, graded, u.strength), color.a);
+}
@@ -0,0 +1,56 @@
+#include "post_process"
+
+struct Crt {
+    // Barrel distortion, 0 keeps the image flat.
+    curvature: f32,
+    // How dark the gaps between scanlines get, from 0 to 1.
+    scanline_intensity: f32,
+    // Scanlines over the height of the screen. 0 darkens every other pixel row, ignoring
+    // curvature, the finest scanlines that don't vanish between pixel centers.
+    scanline_count: f32,
+    // Red and blue offset at the edges of the screen, in pixels.
+    chromatic_aberration: f32,
+    // How dark the other two colors of each phosphor column get, from 0 to 1.
+    mask_intensity: f32,
+    // Width of one phosphor column in pixels.
+    mask_size: f32,
+    // Multiplies the result to make up for the darkening.
+    brightness: f32,
+    // Rounding of the screen's corners in uv units.
+    corner_radius: f32,
+};
+
+@group(1) @binding(0)
+var<uniform> effect_uniforms: Crt;
+
+const PI: f32 = 3.14159265;
+
+fn effect(in: PostInput) -> vec4<f32> {
+    let crt = effect_uniforms;
+    let centered = in.uv - 0.5;
+    let uv = centered * (1.0 + dot(centered, centered) * crt.curvature) + 0.5;
+
+    let shift = (uv - 0.5) * 2.0 * crt.chromatic_aberration * source_texel();
+    let color = sample_source(uv);
+    var rgb = vec3<f32>(sample_source(uv + shift).r, color.g, sample_source(uv - shift).b);
+
+    // A period of one pixel row would sample every row at the same phase, so rows alternate.
+    var phase = uv.y * crt.scanline_count * 2.0 * PI;
+    if crt.scanline_count <= 0.0 {
+        phase = (in.frag_coord.y - 0.5) * PI;
+    }
+    let scanline = 0.5 - 0.5 * cos(phase);
+    rgb *= mix(1.0, scanline, crt.scanline_intensity);
+
+    let column = u32(in.frag_coord.x / max(crt.mask_size, 1.0)) % 3u;
+    var mask = vec3<f32>(1.0 - crt.mask_intensity);
+    mask[column] = 1.0;
+    rgb *= mask * crt.brightness;
+
+    // Black outside the rounded screen, antialiased over a pixel.
+    let radius = min(crt.corner_radius, 0.5);
+    let corner = abs(uv - 0.5) - (0.5 - radius);
+    let distance = length(max(corner, vec2<f32>(0.0))) + min(max(corner.x, corner.y), 0.0);
+    let inside = saturate((radius - distance) / source_texel().y);
+    return vec4<f32>(rgb * inside, color.a);
+}
@@ -1,33 +1,86 @@
-struct VertexOutput {
-    @builtin(position) pos: vec4<f32>,
-    @location(0) color: vec4<f32>,
-};
+#define INSTANCE_COLOR
+#define INSTANCE_SHADOW
+#include "quad_transform"
+#ifdef LIGHTING
+#define LIGHTING_GROUP 1
+#include "lighting"
+#endif
 
-struct Instance {
-    @location(4) pos: vec2<f32>,
-    @location(5) size: vec2<f32>,
-    @location(6) color: vec4<f32>,
-};
-
-@group(0) @binding(0)
-var<uniform> aspect_ratio: vec2<u32>;
+// How far a box shadow reaches past the quad on each side.
+fn shadow_margin(shadow: vec4<f32>, shadow_color: vec4<f32>) -> vec2<f32> {
+    if shadow_color.a <= 0.0 {
+        return vec2<f32>(0.0);
+    }
+    // Three standard deviations, half the blur radius each, cover all but a sliver.
+    return max(abs(shadow.xy) + shadow.w + shadow.z * 1.5, vec2<f32>(0.0));
+}
 
 @vertex
 fn vs_main(
     @location(0) vin: vec2<f32>,
+    @location(1) uv: vec2<f32>,
     instance: Instance,
 ) -> VertexOutput {
-    var scale: vec2<f32>;
-    var pos: vec2<f32>;
+    // The quad is grown to cover its shadow, and the fragment shader cuts the quad back out.
+    let half_size = instance.size * 0.5;
+    let margin = shadow_margin(instance.shadow, instance.shadow_color);
     var out: VertexOutput;
-    scale = instance.size / vec2<f32>(aspect_ratio);
-    pos = (instance.pos / vec2<f32>(aspect_ratio) * vec2<f32>(2.0, -2.0)) + vec2<f32>(-1.0, 1.0);
-    out.pos = vec4<f32>(vin, 0.0, 1.0) * vec4<f32>(scale, 0.0, 1.0) + vec4<f32>(pos, 0.0, 0.0);
+    out.pos = quad_to_clip(vin, instance.pos, instance.size + margin * 2.0);
     out.color = instance.color;
+    out.uv = uv;
+    out.local = vec2<f32>(vin.x, -vin.y) * (half_size + margin);
+    out.half_size = half_size;
+    out.shadow = instance.shadow;
+    out.shadow_color = instance.shadow_color;
+#ifdef LIGHTING
+    out.world = instance.pos.xy + out.local;
+#endif
     return out;
 }
 
+// Approximates erf to within about 5e-4.
+fn erf(x: vec2<f32>) -> vec2<f32> {
+    let s = sign(x);
+    let a = abs(x);
+    var r = 1.0 + (0.278393 + (0.230389 + 0.078108 * (a * a)) * a) * a;
+    r *= r;
+    return s - s / (r * r);
+}
+
+// Coverage of a Gaussian blurred rect, the product of its blurred edges along each axis.
+fn box_shadow(local: vec2<f32>, half_size: vec2<f32>, shadow: vec4<f32>) -> f32 {
+    let half_shadow = max(half_size + shadow.w, vec2<f32>(0.0));
+    let p = local - shadow.xy;
+    let sigma = max(shadow.z * 0.5, 1e-4);
+    let scale = 1.0 / (sqrt(2.0) * sigma);
+    let edges = 0.5 * (erf((p + half_shadow) * scale) - erf((p - half_shadow) * scale));
+    return edges.x * edges.y;
+}
+
 @fragment
 fn fs_main(vin: VertexOutput) -> @location(0) vec4<f32> {
-    return vin.color;
+    let inside = all(abs(vin.local) <= vin.half_size);
+#ifdef ALPHA_MASK
+    // Drawn into the stencil buffer only, covering the quad without its shadow.
+    if !inside || vin.color.a < 0.5 {
+        discard;
+    }
+#endif
+    var color = vin.color;
+#ifdef LIGHTING
+    // Quads are lit as flat surfaces facing the viewer. Box shadows aren't lit.
+    color = vec4<f32>(color.rgb * light_at(vin.world, vec3<f32>(0.0, 0.0, 1.0)), color.a);
+#endif
+    if vin.shadow_color.a <= 0.0 {
+        return color;
+    }
+    let quad = select(0.0, color.a, inside);
+    let shadow = vin.shadow_color.a * box_shadow(vin.local, vin.half_size, vin.shadow);
+    // The quad over its shadow.
+    let alpha = quad + shadow * (1.0 - quad);
+    if alpha <= 0.0 {
+        discard;
+    }
+    let rgb = color.rgb * quad + vin.shadow_color.rgb * shadow * (1.0 - quad);
+    return vec4<f32>(rgb / alpha, alpha);
 }
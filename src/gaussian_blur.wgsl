@@ -0,0 +1,29 @@
+#include "post_process"
+
+// One direction of a separable Gaussian blur.
+struct Blur {
+    // (1, 0) for the horizontal pass, (0, 1) for the vertical one.
+    direction: vec2<f32>,
+    // Standard deviation in pixels.
+    sigma: f32,
+};
+
+@group(1) @binding(0)
+var<uniform> effect_uniforms: Blur;
+
+const MAX_RADIUS: i32 = 32;
+
+fn effect(in: PostInput) -> vec4<f32> {
+    let sigma = max(effect_uniforms.sigma, 0.001);
+    let radius = min(i32(ceil(sigma * 3.0)), MAX_RADIUS);
+    let step = effect_uniforms.direction * source_texel();
+    var sum = vec4<f32>(0.0);
+    var total = 0.0;
+    for (var i = -radius; i <= radius; i++) {
+        let x = f32(i);
+        let weight = exp(-x * x / (2.0 * sigma * sigma));
+        sum += sample_source(in.uv + step * x) * weight;
+        total += weight;
+    }
+    return sum / total;
+}
@@ -1,24 +1,158 @@
-use std::cell::RefCell;
-use wgpu::util::DeviceExt;
+use std::cell::{Cell, RefCell};
+use std::path::PathBuf;
+use crate::bind_group::BindGroupLayoutBuilder;
+use crate::camera::{Camera, ViewUniform};
+use crate::clip::{scissor_rect, Clip, Rect};
+use crate::color::Color;
+use crate::hdr::{HdrOutput, HdrSettings, HDR_FORMAT};
+use crate::lighting::{LightBuffer, Lighting};
+use crate::lut::ColorGrading;
+use crate::pipeline::PipelineCache;
+use crate::post::{PostChain, PostEffect};
+use crate::preprocessor::Preprocessor;
+use crate::quad::QuadGeometry;
+use crate::render_target::RenderTarget;
+use crate::texture::{Texture, TextureHandle};
+use crate::texture_array::BINDLESS_FEATURES;
+use crate::uniform::UniformBuffer;
+use crate::virtual_resolution::{Letterbox, VirtualScreen};
 
-pub trait Renderer {
+pub trait Renderer: std::any::Any {
+    // Called once per frame before the render pass begins.
+    fn prepare(&mut self, _data: &GfxRenderData) {}
+    // Adds world rects that cast shadows from lights, see `Shadow`. Called once per frame
+    // before anything is prepared.
+    fn occluders(&self, _occluders: &mut Vec<Rect>) {}
     fn render<'a, 'b>(&'a self, data: &'a GfxRenderData, render_pass: &mut wgpu::RenderPass<'b>)
     where
         'a: 'b;
+    // Blended instances go here, sorted back to front. With a depth buffer this is called after
+    // every renderer's `render`, so they are composited over all opaque geometry. Without one
+    // it follows the renderer's own `render`, and renderers should draw everything blended here
+    // in submission order.
+    fn render_transparent<'a, 'b>(
+        &'a self,
+        _data: &'a GfxRenderData,
+        _render_pass: &mut wgpu::RenderPass<'b>,
+    ) where
+        'a: 'b,
+    {
+    }
+}
+pub struct DepthBuffer {
+    pub texture: wgpu::Texture,
+    pub view: wgpu::TextureView,
+    pub format: wgpu::TextureFormat,
+}
+impl DepthBuffer {
+    pub fn new(device: &wgpu::Device, size: winit::dpi::PhysicalSize<u32>) -> Self {
+        // The stencil aspect holds mask levels, see `Batcher::with_mask`.
+        let format = wgpu::TextureFormat::Depth24PlusStencil8;
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("depth_buffer"),
+            size: wgpu::Extent3d {
+                width: size.width,
+                height: size.height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        Self {
+            texture,
+            view,
+            format,
+        }
+    }
 }
 pub struct GfxRenderData<'a> {
+    // What renderers draw at: the window size, or the virtual resolution if one is set.
     pub size: winit::dpi::PhysicalSize<u32>,
+    pub window_size: winit::dpi::PhysicalSize<u32>,
     pub device: wgpu::Device,
     pub queue: wgpu::Queue,
     pub surface: wgpu::Surface<'a>,
-    pub aspect_ratio_bind_group: wgpu::BindGroup,
-    pub aspect_ratio_bind_group_layout: wgpu::BindGroupLayout,
+    pub surface_format: wgpu::TextureFormat,
+    // What renderers draw into: the surface format, or `HDR_FORMAT` with HDR enabled.
+    pub format: wgpu::TextureFormat,
+    pub sample_count: u32,
+    pub depth: Option<DepthBuffer>,
+    pub camera: Camera,
+    pub view: UniformBuffer<ViewUniform>,
+    // Rounds quad edges to whole pixels, set along with a virtual resolution.
+    pub snap_to_pixels: bool,
+    // Screen clip of the renderer being drawn.
+    clip: Cell<Option<Rect>>,
     pub texture_bind_group_layout: wgpu::BindGroupLayout,
+    pub unfilterable_texture_bind_group_layout: wgpu::BindGroupLayout,
+    pub textures: Vec<Texture>,
+    // When set, renderers load their shaders from this directory and reload them on change.
+    pub shader_dir: Option<PathBuf>,
+    pub preprocessor: Preprocessor,
+    pub pipelines: PipelineCache,
+    pub quad_geometry: QuadGeometry,
+    pub lights: LightBuffer,
+}
+impl<'a> GfxRenderData<'a> {
+    pub fn texture_bind_group_layout(&self, filterable: bool) -> &wgpu::BindGroupLayout {
+        if filterable {
+            &self.texture_bind_group_layout
+        } else {
+            &self.unfilterable_texture_bind_group_layout
+        }
+    }
+    pub fn view_uniform(&self) -> ViewUniform {
+        ViewUniform::new(self.size, &self.camera, self.snap_to_pixels)
+    }
+    pub fn texture(&self, handle: TextureHandle) -> &Texture {
+        &self.textures[handle.0]
+    }
+    pub fn texture_mut(&mut self, handle: TextureHandle) -> &mut Texture {
+        &mut self.textures[handle.0]
+    }
+    // Restricts drawing to `clip`, in screen pixels, within the clip of the renderer being
+    // drawn. Returns false if nothing would be visible, in which case drawing should be skipped.
+    pub fn set_scissor(&self, render_pass: &mut wgpu::RenderPass, clip: Option<Rect>) -> bool {
+        let target = Rect::new(0.0, 0.0, self.size.width as f32, self.size.height as f32);
+        let rect = [self.clip.get(), clip]
+            .into_iter()
+            .flatten()
+            .fold(target, |rect, clip| rect.intersect(&clip));
+        let [x, y, width, height] = scissor_rect(rect, self.size);
+        if width == 0 || height == 0 {
+            return false;
+        }
+        render_pass.set_scissor_rect(x, y, width, height);
+        true
+    }
+}
+
+pub fn view_layout() -> BindGroupLayoutBuilder {
+    UniformBuffer::<ViewUniform>::layout_builder(wgpu::ShaderStages::VERTEX)
+}
+pub fn texture_layout(filterable: bool) -> BindGroupLayoutBuilder {
+    BindGroupLayoutBuilder::new()
+        .texture_2d(wgpu::ShaderStages::FRAGMENT, filterable)
+        .sampler(wgpu::ShaderStages::FRAGMENT, filterable)
 }
 
 pub struct Gfx<'a> {
     pub data: RefCell<GfxRenderData<'a>>,
     renderers: Vec<Box<dyn Renderer>>,
+    // Clip of each renderer, by index.
+    clips: Vec<Option<Clip>>,
+    // Drawn in order before the screen.
+    targets: Vec<RenderTarget>,
+    post: PostChain,
+    hdr: Option<HdrOutput>,
+    color_grading: Option<ColorGrading>,
+    virtual_screen: Option<VirtualScreen>,
+    lighting: Lighting,
 }
 impl<'a> Gfx<'a> {
     pub async fn new(window: &'a winit::window::Window) -> Self {
@@ -44,128 +178,315 @@ impl<'a> Gfx<'a> {
             .request_device(
                 &wgpu::DeviceDescriptor {
                     label: None,
-                    required_features: wgpu::Features::empty(),
-                    required_limits: wgpu::Limits::default(),
+                    required_features: adapter.features()
+                        & (wgpu::Features::FLOAT32_FILTERABLE | BINDLESS_FEATURES),
+                    // Bindless textures can use as many textures as the adapter allows.
+                    required_limits: wgpu::Limits {
+                        max_sampled_textures_per_shader_stage: adapter
+                            .limits()
+                            .max_sampled_textures_per_shader_stage,
+                        ..Default::default()
+                    },
                 },
                 None,
             )
             .await
             .unwrap();
-        surface.configure(
+        let format = wgpu::TextureFormat::Bgra8Unorm;
+        configure_surface(&surface, &device, format, size);
+        let camera = Camera::default();
+        let view = UniformBuffer::new(
             &device,
-            &wgpu::SurfaceConfiguration {
-                usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-                format: wgpu::TextureFormat::Bgra8Unorm,
-                width: size.width,
-                height: size.height,
-                present_mode: wgpu::PresentMode::Fifo,
-                desired_maximum_frame_latency: 2,
-                alpha_mode: wgpu::CompositeAlphaMode::Opaque,
-                view_formats: vec![],
-            },
+            wgpu::ShaderStages::VERTEX,
+            &ViewUniform::new(size, &camera, false),
+            Some("view"),
         );
-        let aspect_ratio_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: None,
-            contents: bytemuck::cast_slice(&[size.width, size.height]),
-            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-        });
-        let aspect_ratio_bind_group_layout =
-            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                label: None,
-                entries: &[wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStages::VERTEX,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                }],
-            });
-        let aspect_ratio_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: None,
-            layout: &aspect_ratio_bind_group_layout,
-            entries: &[wgpu::BindGroupEntry {
-                binding: 0,
-                resource: aspect_ratio_buffer.as_entire_binding(),
-            }],
-        });
-        let texture_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-            entries: &[
-                wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStages::FRAGMENT,
-                    ty: wgpu::BindingType::Texture {
-                        multisampled: false,
-                        view_dimension: wgpu::TextureViewDimension::D2,
-                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
-                    },
-                    count: None,
-                },
-                wgpu::BindGroupLayoutEntry {
-                    binding: 1,
-                    visibility: wgpu::ShaderStages::FRAGMENT,
-                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
-                    count: None,
-                },
-            ],
-            label: Some("texture_bind_group_layout"),
-        });
+        let texture_bind_group_layout =
+            texture_layout(true).build(&device, Some("texture_bind_group_layout"));
+        let unfilterable_texture_bind_group_layout =
+            texture_layout(false).build(&device, Some("unfilterable_texture_bind_group_layout"));
+        let preprocessor = Preprocessor::new();
+        let quad_geometry = QuadGeometry::new(&device);
+        let lights = LightBuffer::new(&device);
         let data = GfxRenderData {
             size,
+            window_size: size,
             device,
             queue,
             surface,
-            aspect_ratio_bind_group,
-            aspect_ratio_bind_group_layout,
+            surface_format: format,
+            format,
+            sample_count: 1,
+            depth: None,
+            camera,
+            view,
+            snap_to_pixels: false,
+            clip: Cell::new(None),
             texture_bind_group_layout,
+            unfilterable_texture_bind_group_layout,
+            textures: vec![],
+            shader_dir: None,
+            preprocessor,
+            pipelines: PipelineCache::default(),
+            quad_geometry,
+            lights,
         };
         Self {
             data: RefCell::new(data),
             renderers: vec![],
+            clips: vec![],
+            targets: vec![],
+            post: PostChain::default(),
+            hdr: None,
+            color_grading: None,
+            virtual_screen: None,
+            lighting: Lighting::default(),
+        }
+    }
+    // Call when the window is resized. Screen sized buffers follow on the next draw.
+    pub fn resize(&mut self, size: winit::dpi::PhysicalSize<u32>) {
+        // Minimized windows report a zero size, which surfaces can't be configured with.
+        if size.width == 0 || size.height == 0 {
+            return;
+        }
+        let mut data = self.data.borrow_mut();
+        data.window_size = size;
+        configure_surface(&data.surface, &data.device, data.surface_format, size);
+        if self.virtual_screen.is_none() {
+            set_size(&mut data, size);
+        }
+    }
+    // Draws everything at a fixed resolution, e.g. 320x180 for pixel art, then scales the
+    // frame up by the largest whole number that fits the window, with black bars around it.
+    // Quad edges are snapped to whole pixels, and post effects run at the low resolution.
+    // `None` goes back to drawing at the window size.
+    pub fn set_virtual_resolution(&mut self, size: Option<winit::dpi::PhysicalSize<u32>>) {
+        let mut data = self.data.borrow_mut();
+        self.virtual_screen = size.map(VirtualScreen::new);
+        data.snap_to_pixels = size.is_some();
+        let size = size.unwrap_or(data.window_size);
+        set_size(&mut data, size);
+    }
+    pub fn virtual_resolution(&self) -> Option<winit::dpi::PhysicalSize<u32>> {
+        self.virtual_screen.as_ref().map(|screen| screen.size)
+    }
+    // Where the virtual resolution frame is shown in the window, if one is set.
+    pub fn letterbox(&self) -> Option<Letterbox> {
+        let window_size = self.data.borrow().window_size;
+        self.virtual_screen
+            .as_ref()
+            .map(|screen| screen.letterbox(window_size))
+    }
+    // Maps a window position, e.g. of the cursor, to the world through the letterbox and the
+    // camera.
+    pub fn screen_to_world(&self, point: [f32; 2]) -> [f32; 2] {
+        let point = match self.letterbox() {
+            Some(letterbox) => letterbox.window_to_virtual(point),
+            None => point,
+        };
+        self.camera().screen_to_world(point)
+    }
+    pub fn world_to_screen(&self, point: [f32; 2]) -> [f32; 2] {
+        let point = self.camera().world_to_screen(point);
+        match self.letterbox() {
+            Some(letterbox) => letterbox.virtual_to_window(point),
+            None => point,
         }
     }
     pub fn add_renderer(&mut self, renderer: Box<dyn Renderer>) -> usize {
         self.renderers.push(renderer);
+        self.clips.push(None);
         self.renderers.len() - 1
     }
+    // Clips everything the renderer draws, on top of any clips it applies itself.
+    pub fn set_clip(&mut self, renderer: usize, clip: Option<Clip>) {
+        self.clips[renderer] = clip;
+    }
+    pub fn camera(&self) -> Camera {
+        self.data.borrow().camera
+    }
+    pub fn set_camera(&mut self, camera: Camera) {
+        self.data.borrow_mut().camera = camera;
+    }
+    // Gives back a renderer added with `add_renderer`, e.g. to submit new primitives each frame.
+    pub fn renderer_mut<T: Renderer>(&mut self, index: usize) -> Option<&mut T> {
+        let renderer: &mut dyn std::any::Any = self.renderers.get_mut(index)?.as_mut();
+        renderer.downcast_mut()
+    }
+    // Adds an offscreen target of `width` x `height` pixels, drawn every frame before the
+    // screen and before any target added later. Returns its index.
+    pub fn add_render_target(&mut self, width: u32, height: u32) -> usize {
+        let texture = {
+            let data = self.data.borrow();
+            Texture::render_target(&data, width, height, data.format)
+        };
+        let handle = self.add_texture(texture);
+        let size = winit::dpi::PhysicalSize::new(width, height);
+        let target = RenderTarget::new(&self.data.borrow(), handle, size);
+        self.targets.push(target);
+        self.targets.len() - 1
+    }
+    pub fn render_target_mut(&mut self, index: usize) -> Option<&mut RenderTarget> {
+        self.targets.get_mut(index)
+    }
+    // Appends a full-screen effect. With any effects added the scene is drawn into an
+    // offscreen texture and the effects are run in order on the way to the screen.
+    pub fn add_post_effect(&mut self, effect: Box<dyn PostEffect>) -> usize {
+        self.post.add(effect)
+    }
+    pub fn post_effect_mut<T: PostEffect>(&mut self, index: usize) -> Option<&mut T> {
+        self.post.effect_mut(index)
+    }
+    // Renderers build their pipelines against the depth buffer, so this has to be called
+    // before any are created. Render targets added before this won't have a depth buffer.
+    pub fn enable_depth_buffer(&mut self) {
+        let mut data = self.data.borrow_mut();
+        assert!(
+            data.pipelines.is_empty() && self.targets.is_empty(),
+            "enable_depth_buffer must be called before creating renderers and render targets"
+        );
+        data.depth = Some(DepthBuffer::new(&data.device, data.size));
+    }
+    // Renderers draw into a half float target so colors can go above 1, then the scene is
+    // tonemapped onto the surface after any post effects. Renderers, render targets and post
+    // effects are built for the format they draw into, so this has to be called before any
+    // are created.
+    pub fn enable_hdr(&mut self, settings: HdrSettings) {
+        let mut data = self.data.borrow_mut();
+        assert!(
+            data.pipelines.is_empty() && self.targets.is_empty() && self.post.is_empty(),
+            "enable_hdr must be called before creating renderers, render targets and effects"
+        );
+        data.format = HDR_FORMAT;
+        self.hdr = Some(HdrOutput::new(settings));
+    }
+    // Grades the final frame, after post effects and HDR tonemapping.
+    pub fn set_color_grading(&mut self, grading: Option<ColorGrading>) {
+        self.color_grading = grading;
+    }
+    pub fn color_grading_mut(&mut self) -> Option<&mut ColorGrading> {
+        self.color_grading.as_mut()
+    }
+    pub fn hdr_settings(&self) -> Option<HdrSettings> {
+        self.hdr.as_ref().map(|hdr| hdr.settings)
+    }
+    // Panics if HDR isn't enabled.
+    pub fn set_hdr_settings(&mut self, settings: HdrSettings) {
+        self.hdr.as_mut().expect("HDR is not enabled").settings = settings;
+    }
+    pub fn lighting(&self) -> &Lighting {
+        &self.lighting
+    }
+    // Lights used by renderers created `with_lighting`, uploaded at the start of each frame.
+    pub fn lighting_mut(&mut self) -> &mut Lighting {
+        &mut self.lighting
+    }
+    // Only affects renderers created after this call. Shader modules such as
+    // `quad_transform` are read from `<name>.wgsl` in `dir` too when present.
+    pub fn enable_shader_hot_reload(&mut self, dir: impl Into<PathBuf>) {
+        let dir = dir.into();
+        let mut data = self.data.borrow_mut();
+        data.preprocessor.set_module_dir(Some(dir.clone()));
+        data.shader_dir = Some(dir);
+    }
+    // Makes `source` available to shaders through `#include "name"`.
+    pub fn register_shader_module(&mut self, name: &str, source: &str) {
+        self.data.borrow_mut().preprocessor.register_module(name, source);
+    }
+    pub fn add_texture(&mut self, texture: Texture) -> TextureHandle {
+        let mut data = self.data.borrow_mut();
+        data.textures.push(texture);
+        TextureHandle(data.textures.len() - 1)
+    }
     pub fn draw(&mut self) {
-        let data = self.data.borrow_mut();
+        let mut data = self.data.borrow_mut();
+        let GfxRenderData {
+            device,
+            queue,
+            lights,
+            ..
+        } = &mut *data;
+        // Occluders from every renderer, including those of render targets, shadow every pass.
+        let mut occluders = vec![];
+        let target_renderers = self.targets.iter().flat_map(|target| target.renderers());
+        for renderer in self.renderers.iter().chain(target_renderers) {
+            renderer.occluders(&mut occluders);
+        }
+        lights.update(device, queue, &self.lighting, &occluders);
+        let mut encoder = data
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+        // Set before anything is prepared, since renderers sort quads by texture opacity.
+        for target in self.targets.iter() {
+            data.texture_mut(target.texture()).opaque = target.clear_color.a >= 1.0;
+        }
+        for target in self.targets.iter_mut() {
+            target.swap(&mut data);
+            data.view.set(&data.queue, &data.view_uniform());
+            for renderer in target.renderers_mut() {
+                renderer.prepare(&data);
+            }
+            let renderers = target
+                .renderers()
+                .iter()
+                .map(|renderer| (renderer.as_ref(), None))
+                .collect::<Vec<_>>();
+            let view = &data.texture(target.texture()).view;
+            render_pass(&data, &mut encoder, view, target.clear_color, &renderers);
+            target.swap(&mut data);
+        }
+
+        data.view.set(&data.queue, &data.view_uniform());
+        for renderer in self.renderers.iter_mut() {
+            renderer.prepare(&data);
+        }
         let output = data
             .surface
             .get_current_texture()
             .expect("Failed to get output texture");
-        let view = output
+        let surface_view = output
             .texture
             .create_view(&wgpu::TextureViewDescriptor::default());
-        let mut encoder = data
-            .device
-            .create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
-        {
-            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: None,
-                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &view,
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(wgpu::Color {
-                            r: 0.0,
-                            g: 0.0,
-                            b: 0.0,
-                            a: 1.0,
-                        }),
-                        store: wgpu::StoreOp::Store,
-                    },
-                })],
-                depth_stencil_attachment: None,
-                timestamp_writes: None,
-                occlusion_query_set: None,
-            });
-
-            for renderer in self.renderers.iter() {
-                renderer.render(&data, &mut render_pass);
-            }
+        // With a virtual resolution everything below draws into the low resolution frame.
+        let view = match self.virtual_screen.as_mut() {
+            Some(screen) => &screen.prepare(&data).view,
+            None => &surface_view,
+        };
+        if let Some(hdr) = self.hdr.as_mut() {
+            hdr.prepare(&data);
+        }
+        if let Some(grading) = self.color_grading.as_mut() {
+            grading.prepare(&data);
+        }
+        // Everything before grading draws into its input instead of the frame.
+        let graded = self.color_grading.as_ref();
+        let frame = graded.and_then(|grading| grading.input()).map_or(view, |input| &input.view);
+        let offscreen = self.hdr.is_some() || !self.post.is_empty();
+        let scene = if offscreen {
+            &self.post.prepare(&data).view
+        } else {
+            frame
+        };
+        let renderers = self
+            .renderers
+            .iter()
+            .zip(&self.clips)
+            .map(|(renderer, clip)| {
+                (renderer.as_ref(), clip.map(|clip| clip.to_screen(&data.camera)))
+            })
+            .collect::<Vec<_>>();
+        render_pass(&data, &mut encoder, scene, Color::BLACK, &renderers);
+        if let Some(hdr) = self.hdr.as_ref() {
+            let scene = self.post.render(&data, &mut encoder, None);
+            hdr.render(&mut encoder, scene, frame);
+        } else if offscreen {
+            self.post.render(&data, &mut encoder, Some(frame));
+        }
+        if let Some(grading) = graded {
+            grading.render(&mut encoder, view);
+        }
+        if let Some(screen) = self.virtual_screen.as_ref() {
+            screen.render(&mut encoder, &surface_view);
         }
 
         let command_buffer = encoder.finish();
@@ -173,3 +494,96 @@ impl<'a> Gfx<'a> {
         output.present();
     }
 }
+
+// Resizes what renderers draw at, along with the depth buffer.
+fn set_size(data: &mut GfxRenderData, size: winit::dpi::PhysicalSize<u32>) {
+    data.size = size;
+    if data.depth.is_some() {
+        data.depth = Some(DepthBuffer::new(&data.device, size));
+    }
+}
+
+fn configure_surface(
+    surface: &wgpu::Surface,
+    device: &wgpu::Device,
+    format: wgpu::TextureFormat,
+    size: winit::dpi::PhysicalSize<u32>,
+) {
+    surface.configure(
+        device,
+        &wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            format,
+            width: size.width,
+            height: size.height,
+            present_mode: wgpu::PresentMode::Fifo,
+            desired_maximum_frame_latency: 2,
+            alpha_mode: wgpu::CompositeAlphaMode::Opaque,
+            view_formats: vec![],
+        },
+    );
+}
+
+// Clears `target` and draws the renderers into it, each within its screen clip, opaque
+// geometry first when there is a depth buffer to keep it behind.
+fn render_pass(
+    data: &GfxRenderData,
+    encoder: &mut wgpu::CommandEncoder,
+    target: &wgpu::TextureView,
+    clear_color: Color,
+    renderers: &[(&dyn Renderer, Option<Rect>)],
+) {
+    let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: None,
+        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+            view: target,
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load: wgpu::LoadOp::Clear(clear_color.into()),
+                store: wgpu::StoreOp::Store,
+            },
+        })],
+        depth_stencil_attachment: data.depth.as_ref().map(|depth| {
+            wgpu::RenderPassDepthStencilAttachment {
+                view: &depth.view,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(1.0),
+                    store: wgpu::StoreOp::Discard,
+                }),
+                stencil_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(0),
+                    store: wgpu::StoreOp::Discard,
+                }),
+            }
+        }),
+        timestamp_writes: None,
+        occlusion_query_set: None,
+    });
+    // Without depth testing, painter's order decides, so each renderer draws everything before
+    // the next one.
+    if data.depth.is_none() {
+        for (renderer, clip) in renderers {
+            data.clip.set(*clip);
+            render_pass.set_stencil_reference(0);
+            if data.set_scissor(&mut render_pass, None) {
+                renderer.render(data, &mut render_pass);
+                renderer.render_transparent(data, &mut render_pass);
+            }
+        }
+        return;
+    }
+    for (renderer, clip) in renderers {
+        data.clip.set(*clip);
+        render_pass.set_stencil_reference(0);
+        if data.set_scissor(&mut render_pass, None) {
+            renderer.render(data, &mut render_pass);
+        }
+    }
+    for (renderer, clip) in renderers {
+        data.clip.set(*clip);
+        render_pass.set_stencil_reference(0);
+        if data.set_scissor(&mut render_pass, None) {
+            renderer.render_transparent(data, &mut render_pass);
+        }
+    }
+}
@@ -0,0 +1,15 @@
+#include "post_process"
+
+struct Grayscale {
+    // 0 leaves colors unchanged, 1 is fully gray.
+    amount: f32,
+};
+
+@group(1) @binding(0)
+var<uniform> effect_uniforms: Grayscale;
+
+fn effect(in: PostInput) -> vec4<f32> {
+    let color = sample_source(in.uv);
+    let luma = dot(color.rgb, vec3<f32>(0.2126, 0.7152, 0.0722));
+    return vec4<f32>(mix(color.rgb, vec3<f32>(luma), effect_uniforms.amount), color.a);
+}
@@ -0,0 +1,353 @@
+use std::rc::Rc;
+
+use crate::bind_group::BindGroupLayoutBuilder;
+use crate::gfx::{texture_layout, GfxRenderData};
+use crate::pipeline::PipelineDescriptor;
+use crate::post::begin_pass;
+use crate::reflect::LayoutError;
+use crate::shader::Shader;
+use crate::texture::Texture;
+use crate::uniform::UniformBuffer;
+
+const BLOOM_SHADER: &str = include_str!("bloom.wgsl");
+const TONEMAP_SHADER: &str = include_str!("tonemap.wgsl");
+
+// What renderers draw into when HDR is enabled.
+pub const HDR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tonemap {
+    Reinhard,
+    Aces,
+}
+
+// Glow around colors brighter than `threshold`, blurred by repeatedly halving the image and
+// adding the levels back up.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Bloom {
+    pub threshold: f32,
+    pub intensity: f32,
+    // Number of halvings. Each level spreads the glow twice as far.
+    pub levels: u32,
+}
+impl Default for Bloom {
+    fn default() -> Self {
+        Self {
+            threshold: 1.0,
+            intensity: 0.5,
+            levels: 5,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HdrSettings {
+    // Scene colors are multiplied by this before tonemapping.
+    pub exposure: f32,
+    pub tonemap: Tonemap,
+    pub bloom: Option<Bloom>,
+}
+impl Default for HdrSettings {
+    fn default() -> Self {
+        Self {
+            exposure: 1.0,
+            tonemap: Tonemap::Aces,
+            bloom: Some(Bloom::default()),
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct BloomUniforms {
+    threshold: f32,
+    knee: f32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct TonemapUniforms {
+    exposure: f32,
+    bloom_intensity: f32,
+    curve: u32,
+    _padding: u32,
+}
+
+// One full-screen pass drawing the texture bound to group 0 into a target of `format`.
+struct HdrPass {
+    shader: Shader,
+    pipeline: Rc<wgpu::RenderPipeline>,
+    format: wgpu::TextureFormat,
+    blend: Option<wgpu::BlendState>,
+}
+impl HdrPass {
+    fn new(
+        data: &GfxRenderData,
+        name: &str,
+        source: &str,
+        defines: &[(&str, &str)],
+        format: wgpu::TextureFormat,
+        blend: Option<wgpu::BlendState>,
+        layouts: &[&wgpu::BindGroupLayout],
+    ) -> Self {
+        let shader = Shader::new(data, name, source, defines);
+        let pipeline = Self::create_pipeline(data, &shader, format, blend, layouts)
+            .unwrap_or_else(|err| panic!("{}: {}", name, err));
+        Self {
+            shader,
+            pipeline,
+            format,
+            blend,
+        }
+    }
+    fn create_pipeline(
+        data: &GfxRenderData,
+        shader: &Shader,
+        format: wgpu::TextureFormat,
+        blend: Option<wgpu::BlendState>,
+        layouts: &[&wgpu::BindGroupLayout],
+    ) -> Result<Rc<wgpu::RenderPipeline>, LayoutError> {
+        let reflection = shader.reflection();
+        reflection.check_vertex_layouts("vs_main", &[])?;
+        reflection.check_bind_group_layouts(&[
+            texture_layout(true).entries(),
+            BindGroupLayoutBuilder::new()
+                .uniform(wgpu::ShaderStages::FRAGMENT)
+                .entries(),
+            texture_layout(true).entries(),
+        ])?;
+        let desc = PipelineDescriptor {
+            format,
+            blend,
+            depth_format: None,
+            depth_write: false,
+            ..PipelineDescriptor::new(data, shader, &[], layouts)
+        };
+        Ok(data.pipelines.get(&data.device, &desc))
+    }
+    fn reload(&mut self, data: &GfxRenderData, layouts: &[&wgpu::BindGroupLayout]) {
+        if self.shader.poll(data) {
+            match Self::create_pipeline(data, &self.shader, self.format, self.blend, layouts) {
+                Ok(pipeline) => self.pipeline = pipeline,
+                Err(err) => eprintln!("{}: {}", self.shader.name(), err),
+            }
+        }
+    }
+}
+
+struct HdrPasses {
+    prefilter: HdrPass,
+    downsample: HdrPass,
+    upsample: HdrPass,
+    tonemap: HdrPass,
+    bloom_uniforms: UniformBuffer<BloomUniforms>,
+    tonemap_uniforms: UniformBuffer<TonemapUniforms>,
+}
+impl HdrPasses {
+    fn new(data: &GfxRenderData) -> Self {
+        let bloom_uniforms = UniformBuffer::new(
+            &data.device,
+            wgpu::ShaderStages::FRAGMENT,
+            &BloomUniforms {
+                threshold: 0.0,
+                knee: 0.0,
+            },
+            Some("bloom"),
+        );
+        let tonemap_uniforms = UniformBuffer::new(
+            &data.device,
+            wgpu::ShaderStages::FRAGMENT,
+            &TonemapUniforms {
+                exposure: 1.0,
+                bloom_intensity: 0.0,
+                curve: 0,
+                _padding: 0,
+            },
+            Some("tonemap"),
+        );
+        let texture_layout = data.texture_bind_group_layout(true);
+        let bloom_layouts = [texture_layout, bloom_uniforms.layout()];
+        let tonemap_layouts = [texture_layout, tonemap_uniforms.layout(), texture_layout];
+        let bloom_pass = |defines: &[(&str, &str)], blend| {
+            let name = "bloom.wgsl";
+            HdrPass::new(data, name, BLOOM_SHADER, defines, HDR_FORMAT, blend, &bloom_layouts)
+        };
+        // Upsampled levels are added onto the downsampled level of the same size.
+        let additive = wgpu::BlendComponent {
+            src_factor: wgpu::BlendFactor::One,
+            dst_factor: wgpu::BlendFactor::One,
+            operation: wgpu::BlendOperation::Add,
+        };
+        let upsample = bloom_pass(
+            &[("UPSAMPLE", "")],
+            Some(wgpu::BlendState {
+                color: additive,
+                alpha: additive,
+            }),
+        );
+        Self {
+            prefilter: bloom_pass(&[("PREFILTER", "")], None),
+            downsample: bloom_pass(&[], None),
+            upsample,
+            tonemap: HdrPass::new(
+                data,
+                "tonemap.wgsl",
+                TONEMAP_SHADER,
+                &[],
+                data.surface_format,
+                None,
+                &tonemap_layouts,
+            ),
+            bloom_uniforms,
+            tonemap_uniforms,
+        }
+    }
+    fn reload(&mut self, data: &GfxRenderData) {
+        let texture_layout = data.texture_bind_group_layout(true);
+        let bloom_layouts = [texture_layout, self.bloom_uniforms.layout()];
+        let tonemap_layouts = [texture_layout, self.tonemap_uniforms.layout(), texture_layout];
+        self.prefilter.reload(data, &bloom_layouts);
+        self.downsample.reload(data, &bloom_layouts);
+        self.upsample.reload(data, &bloom_layouts);
+        self.tonemap.reload(data, &tonemap_layouts);
+    }
+}
+
+// Bloom and tonemapping from the HDR scene onto the surface, run after any post effects.
+pub(crate) struct HdrOutput {
+    pub(crate) settings: HdrSettings,
+    // Built on first use, so enabling HDR doesn't create pipelines before the depth buffer.
+    passes: Option<HdrPasses>,
+    // Bloom levels, the first half the screen size and each half the one before.
+    pyramid: Vec<Texture>,
+}
+impl HdrOutput {
+    pub(crate) fn new(settings: HdrSettings) -> Self {
+        Self {
+            settings,
+            passes: None,
+            pyramid: vec![],
+        }
+    }
+    pub(crate) fn prepare(&mut self, data: &GfxRenderData) {
+        let passes = self.passes.get_or_insert_with(|| HdrPasses::new(data));
+        passes.reload(data);
+        let bloom = self.settings.bloom;
+        if let Some(bloom) = bloom {
+            passes.bloom_uniforms.set(
+                &data.queue,
+                &BloomUniforms {
+                    threshold: bloom.threshold,
+                    knee: bloom.threshold * 0.5,
+                },
+            );
+        }
+        passes.tonemap_uniforms.set(
+            &data.queue,
+            &TonemapUniforms {
+                exposure: self.settings.exposure,
+                bloom_intensity: bloom.map_or(0.0, |bloom| bloom.intensity),
+                curve: match self.settings.tonemap {
+                    Tonemap::Reinhard => 0,
+                    Tonemap::Aces => 1,
+                },
+                _padding: 0,
+            },
+        );
+        let sizes = pyramid_sizes(data.size, bloom.map_or(0, |bloom| bloom.levels));
+        if self.pyramid.iter().map(|level| level.size()).ne(sizes.iter().copied()) {
+            self.pyramid = sizes
+                .iter()
+                .map(|&(width, height)| Texture::render_target(data, width, height, HDR_FORMAT))
+                .collect();
+        }
+    }
+    pub(crate) fn render(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        scene: &Texture,
+        output: &wgpu::TextureView,
+    ) {
+        let Some(passes) = self.passes.as_ref() else {
+            return;
+        };
+        let clear = wgpu::LoadOp::Clear(wgpu::Color::BLACK);
+        let draw = |encoder: &mut wgpu::CommandEncoder,
+                    target: &wgpu::TextureView,
+                    load,
+                    pass: &HdrPass,
+                    bind_groups: &[&wgpu::BindGroup]| {
+            let mut render_pass = begin_pass(encoder, target, load);
+            render_pass.set_pipeline(&pass.pipeline);
+            for (i, bind_group) in bind_groups.iter().enumerate() {
+                render_pass.set_bind_group(i as u32, bind_group, &[]);
+            }
+            render_pass.draw(0..3, 0..1);
+        };
+        let bloom_uniforms = passes.bloom_uniforms.bind_group();
+        let mut source = scene;
+        for (i, level) in self.pyramid.iter().enumerate() {
+            let pass = if i == 0 {
+                &passes.prefilter
+            } else {
+                &passes.downsample
+            };
+            draw(encoder, &level.view, clear, pass, &[&source.bind_group, bloom_uniforms]);
+            source = level;
+        }
+        for pair in self.pyramid.windows(2).rev() {
+            let bind_groups = [&pair[1].bind_group, bloom_uniforms];
+            draw(encoder, &pair[0].view, wgpu::LoadOp::Load, &passes.upsample, &bind_groups);
+        }
+        // Without bloom the scene stands in for the bloom texture, with zero intensity.
+        let bloom = self.pyramid.first().unwrap_or(scene);
+        draw(
+            encoder,
+            output,
+            clear,
+            &passes.tonemap,
+            &[&scene.bind_group, passes.tonemap_uniforms.bind_group(), &bloom.bind_group],
+        );
+    }
+}
+
+// Sizes of the bloom levels for a screen of `size`, stopping early once a level would be a
+// single pixel.
+fn pyramid_sizes(size: winit::dpi::PhysicalSize<u32>, levels: u32) -> Vec<(u32, u32)> {
+    (1..=levels)
+        .map(|level| ((size.width >> level).max(1), (size.height >> level).max(1)))
+        .take_while(|&(width, height)| width > 1 || height > 1)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reflect::check_shader;
+
+    #[test]
+    fn pyramid_halves_until_one_pixel() {
+        let size = winit::dpi::PhysicalSize::new(600, 400);
+        assert_eq!(pyramid_sizes(size, 3), [(300, 200), (150, 100), (75, 50)]);
+        assert_eq!(pyramid_sizes(size, 20).last(), Some(&(2, 1)));
+        assert!(pyramid_sizes(size, 0).is_empty());
+    }
+
+    #[test]
+    fn hdr_shaders_match_layouts() {
+        let shaders = [
+            ("bloom.wgsl", BLOOM_SHADER, &[("PREFILTER", "")][..], 8),
+            ("bloom.wgsl", BLOOM_SHADER, &[], 8),
+            ("bloom.wgsl", BLOOM_SHADER, &[("UPSAMPLE", "")], 8),
+            ("tonemap.wgsl", TONEMAP_SHADER, &[], 16),
+        ];
+        for (name, source, defines, size) in shaders {
+            let texture = texture_layout(true);
+            let uniforms = BindGroupLayoutBuilder::new().uniform(wgpu::ShaderStages::FRAGMENT);
+            let groups = [texture.entries(), uniforms.entries(), texture.entries()];
+            let reflection = check_shader(name, source, defines, &[], &groups);
+            let uniform_size = reflection.binding("effect_uniforms").unwrap().size.unwrap();
+            assert!(uniform_size <= size, "{}", name);
+        }
+    }
+}
@@ -1,5 +1,25 @@
+pub mod assets;
+pub mod batcher;
+pub mod camera;
+pub mod clip;
+pub mod bind_group;
 pub mod color;
 pub mod gfx;
+pub mod hdr;
+pub mod lighting;
+pub mod line;
+pub mod lut;
+pub mod material;
+pub mod pipeline;
+pub mod post;
+pub mod preprocessor;
 pub mod quad;
+pub mod reflect;
+pub mod render_target;
+pub mod shader;
+pub mod shape;
 pub mod texture;
+pub mod texture_array;
+pub mod uniform;
 pub mod vertex;
+pub mod virtual_resolution;
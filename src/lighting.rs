@@ -0,0 +1,364 @@
+use wgpu::util::DeviceExt;
+
+use crate::bind_group::{BindGroupBuilder, BindGroupLayoutBuilder};
+use crate::clip::Rect;
+use crate::color::Color;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LightKind {
+    Point,
+    // Lights a cone around `direction`, `angle` radians either side of it. The edge fades over
+    // `softness` radians inside the cone.
+    Spot {
+        direction: [f32; 2],
+        angle: f32,
+        softness: f32,
+    },
+}
+
+// Shadows cast by occluders, see `Renderer::occluders`. Occluders are culled to each shadowed
+// light's radius on the CPU, and every pixel a light reaches tests the ones left, five times
+// for soft shadows.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Shadow {
+    None,
+    Hard,
+    // Penumbras as cast by a light `size` world units across.
+    Soft { size: f32 },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Light {
+    pub pos: [f32; 2],
+    // Distance above the scene in world units. Low lights graze normal mapped surfaces, high
+    // ones light them more evenly.
+    pub height: f32,
+    pub color: Color,
+    pub intensity: f32,
+    // Distance in world units at which the light has faded out completely.
+    pub radius: f32,
+    // Exponent of the fade towards `radius`, 1 fades linearly.
+    pub falloff: f32,
+    pub kind: LightKind,
+    pub shadow: Shadow,
+}
+impl Light {
+    pub fn point(pos: [f32; 2], color: Color, radius: f32) -> Self {
+        Self {
+            pos,
+            height: radius * 0.25,
+            color,
+            intensity: 1.0,
+            radius,
+            falloff: 2.0,
+            kind: LightKind::Point,
+            shadow: Shadow::None,
+        }
+    }
+    pub fn spot(pos: [f32; 2], direction: [f32; 2], angle: f32, color: Color, radius: f32) -> Self {
+        Self {
+            kind: LightKind::Spot {
+                direction,
+                angle,
+                softness: angle * 0.25,
+            },
+            ..Self::point(pos, color, radius)
+        }
+    }
+}
+
+// The lights of a scene, drawn by renderers created `with_lighting`. Every light is applied
+// to every lit pixel within its radius, which stays fast up to a few hundred lights.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lighting {
+    // Light reaching every surface regardless of lights. White leaves unlit scenes unchanged.
+    pub ambient: Color,
+    pub lights: Vec<Light>,
+}
+impl Default for Lighting {
+    fn default() -> Self {
+        Self {
+            ambient: Color::WHITE,
+            lights: vec![],
+        }
+    }
+}
+
+// Matches `Light` in lighting.wgsl.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+struct LightRaw {
+    color: [f32; 4],
+    pos: [f32; 2],
+    direction: [f32; 2],
+    radius: f32,
+    falloff: f32,
+    cone_inner: f32,
+    cone_outer: f32,
+    height: f32,
+    // Negative without shadows, 0 for hard shadows.
+    shadow_size: f32,
+    // The light's range of the occluder buffer.
+    occluder_start: u32,
+    occluder_count: u32,
+}
+impl From<&Light> for LightRaw {
+    fn from(light: &Light) -> Self {
+        let (direction, cone_inner, cone_outer) = match light.kind {
+            LightKind::Point => ([1.0, 0.0], -1.0, -2.0),
+            LightKind::Spot {
+                direction: [x, y],
+                angle,
+                softness,
+            } => {
+                let length = (x * x + y * y).sqrt().max(f32::EPSILON);
+                let inner = (angle - softness).max(0.0);
+                ([x / length, y / length], inner.cos(), angle.cos())
+            }
+        };
+        let shadow_size = match light.shadow {
+            Shadow::None => -1.0,
+            Shadow::Hard => 0.0,
+            Shadow::Soft { size } => size.max(0.0),
+        };
+        let Color { r, g, b, .. } = light.color;
+        let intensity = light.intensity;
+        Self {
+            color: [r * intensity, g * intensity, b * intensity, 1.0],
+            pos: light.pos,
+            direction,
+            radius: light.radius.max(f32::EPSILON),
+            falloff: light.falloff,
+            cone_inner,
+            cone_outer,
+            height: light.height,
+            shadow_size,
+            occluder_start: 0,
+            occluder_count: 0,
+        }
+    }
+}
+
+// The lights along with the occluders each one's shadows can fall from, light after light.
+// Shadow rays run from points within the radius to points on the light, so occluders outside
+// the circle covering both never block them.
+fn light_occluders(lights: &[Light], occluders: &[Rect]) -> (Vec<LightRaw>, Vec<OccluderRaw>) {
+    let mut raw_lights = vec![];
+    let mut raw_occluders = vec![];
+    for light in lights {
+        let mut raw = LightRaw::from(light);
+        raw.occluder_start = raw_occluders.len() as u32;
+        if raw.shadow_size >= 0.0 {
+            let reach = raw.radius.max(raw.shadow_size * 0.5);
+            let near = occluders.iter().filter(|rect| {
+                let dx = light.pos[0] - light.pos[0].clamp(rect.x, rect.x + rect.width);
+                let dy = light.pos[1] - light.pos[1].clamp(rect.y, rect.y + rect.height);
+                dx * dx + dy * dy < reach * reach
+            });
+            raw_occluders.extend(near.map(OccluderRaw::from));
+        }
+        raw.occluder_count = raw_occluders.len() as u32 - raw.occluder_start;
+        raw_lights.push(raw);
+    }
+    (raw_lights, raw_occluders)
+}
+
+// Matches `Occluder` in lighting.wgsl.
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct OccluderRaw {
+    min: [f32; 2],
+    max: [f32; 2],
+}
+impl From<&Rect> for OccluderRaw {
+    fn from(rect: &Rect) -> Self {
+        Self {
+            min: [rect.x, rect.y],
+            max: [rect.x + rect.width, rect.y + rect.height],
+        }
+    }
+}
+
+// Matches `LightingInfo` in lighting.wgsl.
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct LightingInfo {
+    ambient: [f32; 4],
+    count: u32,
+    _padding: [u32; 3],
+}
+
+pub fn lighting_layout() -> BindGroupLayoutBuilder {
+    BindGroupLayoutBuilder::new()
+        .uniform(wgpu::ShaderStages::FRAGMENT)
+        .storage(wgpu::ShaderStages::FRAGMENT, true)
+        .storage(wgpu::ShaderStages::FRAGMENT, true)
+}
+
+// `Lighting` and the frame's occluders on the GPU, bound to group 3 by lit renderers.
+pub struct LightBuffer {
+    info: wgpu::Buffer,
+    lights: StorageBuffer,
+    occluders: StorageBuffer,
+    layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+}
+impl LightBuffer {
+    pub(crate) fn new(device: &wgpu::Device) -> Self {
+        let info = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("lighting"),
+            contents: bytemuck::bytes_of(&LightingInfo {
+                ambient: [1.0; 4],
+                count: 0,
+                _padding: [0; 3],
+            }),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let lights = StorageBuffer::new::<LightRaw>(device, "lights", 64);
+        let occluders = StorageBuffer::new::<OccluderRaw>(device, "occluders", 64);
+        let layout = lighting_layout().build(device, Some("lighting"));
+        let bind_group = light_bind_group(device, &layout, &info, &lights, &occluders);
+        Self {
+            info,
+            lights,
+            occluders,
+            layout,
+            bind_group,
+        }
+    }
+    pub fn layout(&self) -> &wgpu::BindGroupLayout {
+        &self.layout
+    }
+    pub fn bind_group(&self) -> &wgpu::BindGroup {
+        &self.bind_group
+    }
+    // Uploads `lighting` and the world rects of `occluders` near each shadowed light, growing
+    // the buffers if needed.
+    pub(crate) fn update(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        lighting: &Lighting,
+        occluders: &[Rect],
+    ) {
+        let (lights, occluders) = light_occluders(&lighting.lights, occluders);
+        let grew = self.lights.write(device, queue, &lights);
+        if self.occluders.write(device, queue, &occluders) || grew {
+            self.bind_group =
+                light_bind_group(device, &self.layout, &self.info, &self.lights, &self.occluders);
+        }
+        let Color { r, g, b, a } = lighting.ambient;
+        let info = LightingInfo {
+            ambient: [r, g, b, a],
+            count: lights.len() as u32,
+            _padding: [0; 3],
+        };
+        queue.write_buffer(&self.info, 0, bytemuck::bytes_of(&info));
+    }
+}
+
+// A storage buffer of up to `capacity` items, replaced by a larger one when it runs out.
+struct StorageBuffer {
+    buffer: wgpu::Buffer,
+    label: &'static str,
+    capacity: usize,
+}
+impl StorageBuffer {
+    fn new<T>(device: &wgpu::Device, label: &'static str, capacity: usize) -> Self {
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(label),
+            size: (std::mem::size_of::<T>() * capacity) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        Self {
+            buffer,
+            label,
+            capacity,
+        }
+    }
+    // Returns true if the buffer was replaced.
+    fn write<T: bytemuck::Pod>(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        items: &[T],
+    ) -> bool {
+        let grow = items.len() > self.capacity;
+        if grow {
+            *self = Self::new::<T>(device, self.label, items.len().next_power_of_two());
+        }
+        queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(items));
+        grow
+    }
+}
+
+fn light_bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    info: &wgpu::Buffer,
+    lights: &StorageBuffer,
+    occluders: &StorageBuffer,
+) -> wgpu::BindGroup {
+    BindGroupBuilder::new()
+        .buffer(info)
+        .buffer(&lights.buffer)
+        .buffer(&occluders.buffer)
+        .build(device, layout, Some("lighting"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spot_lights_get_normalized_cones() {
+        let light = Light::spot([0.0, 0.0], [0.0, 2.0], 0.5, Color::WHITE, 100.0);
+        let raw = LightRaw::from(&Light {
+            intensity: 2.0,
+            ..light
+        });
+        assert_eq!(raw.direction, [0.0, 1.0]);
+        assert_eq!(raw.cone_outer, 0.5f32.cos());
+        assert_eq!(raw.cone_inner, 0.375f32.cos());
+        assert_eq!(raw.color, [2.0, 2.0, 2.0, 1.0]);
+        assert_eq!(raw.shadow_size, -1.0);
+        assert_eq!(std::mem::size_of::<LightRaw>(), 64);
+    }
+
+    #[test]
+    fn occluders_are_culled_to_each_light() {
+        let occluders = [
+            Rect::new(5.0, -1.0, 2.0, 2.0),
+            Rect::new(20.0, 0.0, 2.0, 2.0),
+            Rect::new(-12.0, 0.0, 2.0, 2.0),
+        ];
+        let light = Light::point([0.0, 0.0], Color::WHITE, 10.0);
+        let lights = [
+            Light {
+                shadow: Shadow::Hard,
+                ..light
+            },
+            light,
+            Light {
+                pos: [15.0, 0.0],
+                shadow: Shadow::Soft { size: 4.0 },
+                ..light
+            },
+            // Soft shadows reach out to points on the light past its radius.
+            Light {
+                radius: 1.0,
+                shadow: Shadow::Soft { size: 12.0 },
+                ..light
+            },
+        ];
+        let (lights, occluders) = light_occluders(&lights, &occluders);
+        let ranges = lights
+            .iter()
+            .map(|light| (light.occluder_start, light.occluder_count))
+            .collect::<Vec<_>>();
+        assert_eq!(ranges, [(0, 1), (1, 0), (1, 2), (3, 1)]);
+        let mins = occluders.iter().map(|occluder| occluder.min).collect::<Vec<_>>();
+        assert_eq!(mins, [[5.0, -1.0], [5.0, -1.0], [20.0, 0.0], [5.0, -1.0]]);
+    }
+}
@@ -0,0 +1,108 @@
+// Per pixel 2D lighting, see `Lighting`. Lights are in world coordinates, y down, and normals
+// in tangent space with y up, as normal map tools usually export them.
+
+struct Light {
+    // Premultiplied by intensity.
+    color: vec4<f32>,
+    pos: vec2<f32>,
+    // Unit direction of a spot light's cone.
+    direction: vec2<f32>,
+    radius: f32,
+    falloff: f32,
+    // Cosines of the cone's half angles, full brightness inside `cone_inner`. Point lights use
+    // -2 and -1 to light every direction.
+    cone_inner: f32,
+    cone_outer: f32,
+    height: f32,
+    // Negative without shadows, 0 for hard shadows, or the width of the light casting soft
+    // shadows.
+    shadow_size: f32,
+    // The light's range of `occluders`, those near enough to shadow it.
+    occluder_start: u32,
+    occluder_count: u32,
+};
+
+// A world rect blocking light.
+struct Occluder {
+    min: vec2<f32>,
+    max: vec2<f32>,
+};
+
+struct LightingInfo {
+    ambient: vec4<f32>,
+    count: u32,
+};
+
+// Renderers with fewer bind groups define this as the group after their last.
+#ifndef LIGHTING_GROUP
+#define LIGHTING_GROUP 3
+#endif
+@group(LIGHTING_GROUP) @binding(0)
+var<uniform> lighting: LightingInfo;
+@group(LIGHTING_GROUP) @binding(1)
+var<storage, read> lights: array<Light>;
+@group(LIGHTING_GROUP) @binding(2)
+var<storage, read> occluders: array<Occluder>;
+
+// Whether the segment from `start` to `to` enters `occluder`. Surfaces inside an occluder
+// aren't shadowed by it, so occluders can be lit themselves.
+fn blocks(occluder: Occluder, start: vec2<f32>, to: vec2<f32>) -> bool {
+    let dir = to - start;
+    let safe_dir = select(dir, vec2<f32>(1e-6), abs(dir) < vec2<f32>(1e-6));
+    let t0 = (occluder.min - start) / safe_dir;
+    let t1 = (occluder.max - start) / safe_dir;
+    let near = max(min(t0.x, t1.x), min(t0.y, t1.y));
+    let far = min(max(t0.x, t1.x), max(t0.y, t1.y));
+    return near <= far && near > 0.0 && near < 1.0;
+}
+
+fn visible(light: Light, start: vec2<f32>, to: vec2<f32>) -> bool {
+    let end = light.occluder_start + light.occluder_count;
+    for (var i = light.occluder_start; i < end; i++) {
+        if blocks(occluders[i], start, to) {
+            return false;
+        }
+    }
+    return true;
+}
+
+// Fraction of `light` reaching `world` past the occluders.
+fn shadow(light: Light, world: vec2<f32>) -> f32 {
+    if light.shadow_size < 0.0 {
+        return 1.0;
+    }
+    if light.shadow_size == 0.0 {
+        return select(0.0, 1.0, visible(light, world, light.pos));
+    }
+    // Samples across the light, perpendicular to the direction it's seen from.
+    let across = normalize(vec2<f32>(world.y - light.pos.y, light.pos.x - world.x));
+    var lit = 0.0;
+    for (var i = 0; i < 5; i++) {
+        let point = light.pos + across * light.shadow_size * (f32(i) / 4.0 - 0.5);
+        lit += select(0.0, 1.0, visible(light, world, point));
+    }
+    return lit / 5.0;
+}
+
+// Light reaching a surface at `world` facing `normal`, ambient included.
+fn light_at(world: vec2<f32>, normal: vec3<f32>) -> vec3<f32> {
+    var total = lighting.ambient.rgb;
+    for (var i = 0u; i < lighting.count; i++) {
+        let light = lights[i];
+        let offset = light.pos - world;
+        let distance = length(offset);
+        if distance >= light.radius {
+            continue;
+        }
+        let attenuation = pow(1.0 - distance / light.radius, light.falloff);
+        let to_light = normalize(vec3<f32>(offset.x, -offset.y, light.height));
+        let diffuse = max(dot(normal, to_light), 0.0);
+        var cone = 1.0;
+        if distance > 0.0 {
+            let angle = dot(-offset / distance, light.direction);
+            cone = smoothstep(light.cone_outer, light.cone_inner, angle);
+        }
+        total += light.color.rgb * attenuation * diffuse * cone * shadow(light, world);
+    }
+    return total;
+}
@@ -0,0 +1,603 @@
+use std::rc::Rc;
+
+use crate::color::Color;
+use crate::gfx::{view_layout, Gfx, GfxRenderData, Renderer};
+use crate::pipeline::PipelineDescriptor;
+use crate::quad::sort_for_depth;
+use crate::reflect::LayoutError;
+use crate::shader::Shader;
+
+pub(crate) const LINE_SHADER: &str = include_str!("line.wgsl");
+
+// Joins sharper than this ratio of miter length to line width are beveled instead, as with
+// the SVG default.
+const MITER_LIMIT: f32 = 4.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineJoin {
+    Miter,
+    Round,
+    Bevel,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineCap {
+    Butt,
+    Round,
+    // Extends the line by half its width.
+    Square,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LinePoint {
+    pub pos: [f32; 2],
+    // Blended along the segments on either side.
+    pub color: Color,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Polyline {
+    pub points: Vec<LinePoint>,
+    // In world units.
+    pub width: f32,
+    // Layer in 0..1, higher layers in front.
+    pub z: f32,
+    pub join: LineJoin,
+    // Ignored for closed polylines.
+    pub cap: LineCap,
+    // Joins the last point back to the first.
+    pub closed: bool,
+}
+impl Polyline {
+    pub fn new(points: &[[f32; 2]], width: f32, color: Color) -> Self {
+        Self {
+            points: points.iter().map(|&pos| LinePoint { pos, color }).collect(),
+            width,
+            z: 0.0,
+            join: LineJoin::Miter,
+            cap: LineCap::Butt,
+            closed: false,
+        }
+    }
+    pub fn segment(start: [f32; 2], end: [f32; 2], width: f32, color: Color) -> Self {
+        Self::new(&[start, end], width, color)
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+struct LineVertex {
+    pos: [f32; 3],
+    color: [f32; 4],
+    // xy is the offset from the nearest point of the line's center, or an affine stand-in
+    // with the same length along the edges. z is the distance past a butt or square cap,
+    // negative inside.
+    edge: [f32; 3],
+    half_width: f32,
+}
+impl LineVertex {
+    fn layout() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<LineVertex>() as u64,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &[
+                wgpu::VertexAttribute {
+                    format: wgpu::VertexFormat::Float32x3,
+                    offset: 0,
+                    shader_location: 0,
+                },
+                wgpu::VertexAttribute {
+                    format: wgpu::VertexFormat::Float32x4,
+                    offset: std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
+                    shader_location: 1,
+                },
+                wgpu::VertexAttribute {
+                    format: wgpu::VertexFormat::Float32x3,
+                    offset: std::mem::size_of::<[f32; 7]>() as wgpu::BufferAddress,
+                    shader_location: 2,
+                },
+                wgpu::VertexAttribute {
+                    format: wgpu::VertexFormat::Float32,
+                    offset: std::mem::size_of::<[f32; 10]>() as wgpu::BufferAddress,
+                    shader_location: 3,
+                },
+            ],
+        }
+    }
+}
+
+fn add(a: [f32; 2], b: [f32; 2]) -> [f32; 2] {
+    [a[0] + b[0], a[1] + b[1]]
+}
+fn sub(a: [f32; 2], b: [f32; 2]) -> [f32; 2] {
+    [a[0] - b[0], a[1] - b[1]]
+}
+fn scale(a: [f32; 2], s: f32) -> [f32; 2] {
+    [a[0] * s, a[1] * s]
+}
+fn dot(a: [f32; 2], b: [f32; 2]) -> f32 {
+    a[0] * b[0] + a[1] * b[1]
+}
+fn cross(a: [f32; 2], b: [f32; 2]) -> f32 {
+    a[0] * b[1] - a[1] * b[0]
+}
+fn length(a: [f32; 2]) -> f32 {
+    dot(a, a).sqrt()
+}
+fn rotate(a: [f32; 2], angle: f32) -> [f32; 2] {
+    let (sin, cos) = angle.sin_cos();
+    [a[0] * cos - a[1] * sin, a[0] * sin + a[1] * cos]
+}
+fn raw(color: Color) -> [f32; 4] {
+    [color.r, color.g, color.b, color.a]
+}
+fn mix(a: Color, b: Color, t: f32) -> [f32; 4] {
+    [
+        a.r + (b.r - a.r) * t,
+        a.g + (b.g - a.g) * t,
+        a.b + (b.b - a.b) * t,
+        a.a + (b.a - a.a) * t,
+    ]
+}
+
+// Position, `LineVertex::edge` and color of a triangle corner.
+type Corner = ([f32; 2], [f32; 3], [f32; 4]);
+
+// Turns polylines into a triangle list. Every edge is pushed out by `fringe`, a pixel in
+// world units, for the shader to fade.
+struct Tessellator<'a> {
+    vertices: &'a mut Vec<LineVertex>,
+    z: f32,
+    half_width: f32,
+    // Half width including the fringe.
+    extent: f32,
+    fringe: f32,
+}
+impl<'a> Tessellator<'a> {
+    // Winds the triangle to survive back face culling.
+    fn triangle(&mut self, corners: [Corner; 3]) {
+        let [a, b, c] = corners;
+        let corners = if cross(sub(b.0, a.0), sub(c.0, a.0)) < 0.0 {
+            [a, c, b]
+        } else {
+            [a, b, c]
+        };
+        for (pos, edge, color) in corners {
+            self.vertices.push(LineVertex {
+                pos: [pos[0], pos[1], self.z],
+                color,
+                edge,
+                half_width: self.half_width,
+            });
+        }
+    }
+    // A pie slice of radius `extent` around `center`, from direction `start` through `sweep`
+    // radians.
+    fn fan(&mut self, center: [f32; 2], start: [f32; 2], sweep: f32, color: [f32; 4]) {
+        // Keeps the chords within a quarter pixel of the arc.
+        let radius = self.extent / self.fringe;
+        let steps = (sweep.abs() * (radius * 2.0).sqrt()).ceil().clamp(1.0, 64.0) as usize;
+        let step = sweep / steps as f32;
+        let mut from = scale(start, self.extent);
+        for i in 1..=steps {
+            let to = scale(rotate(start, step * i as f32), self.extent);
+            let (a, b) = (add(center, from), add(center, to));
+            let inside = -self.extent;
+            self.triangle([
+                (center, [0.0, 0.0, inside], color),
+                (a, [from[0], from[1], inside], color),
+                (b, [to[0], to[1], inside], color),
+            ]);
+            from = to;
+        }
+    }
+    // A rect along `dir` from `start` to `end` distances past `origin`, with `past(t)`
+    // giving the distance past a cap at t.
+    fn strip(
+        &mut self,
+        origin: [f32; 2],
+        dir: [f32; 2],
+        range: (f32, f32),
+        colors: ([f32; 4], [f32; 4]),
+        past: impl Fn(f32) -> f32,
+    ) {
+        let normal = [-dir[1], dir[0]];
+        let side = scale(normal, self.extent);
+        let (start, end) = range;
+        let a = add(origin, scale(dir, start));
+        let b = add(origin, scale(dir, end));
+        let e = self.extent;
+        let corners = [
+            (add(a, side), [e, 0.0, past(start)], colors.0),
+            (sub(a, side), [-e, 0.0, past(start)], colors.0),
+            (add(b, side), [e, 0.0, past(end)], colors.1),
+            (sub(b, side), [-e, 0.0, past(end)], colors.1),
+        ];
+        self.triangle([corners[0], corners[1], corners[2]]);
+        self.triangle([corners[2], corners[1], corners[3]]);
+    }
+    // The body of the segment from `a` to `b` between distances `range` along it. An end with
+    // an inner corner runs from the corner through the center of the line to the outer edge,
+    // so the segments on either side of a joint meet along its bisector instead of overlapping.
+    fn body(
+        &mut self,
+        a: &LinePoint,
+        b: &LinePoint,
+        range: (f32, f32),
+        corners: [Option<[f32; 2]>; 2],
+    ) {
+        let len = length(sub(b.pos, a.pos));
+        let dir = scale(sub(b.pos, a.pos), 1.0 / len);
+        let normal = [-dir[1], dir[0]];
+        let side = scale(normal, self.extent);
+        // Convex, going along the start from the normal's side and back along the end.
+        let mut outline = Vec::with_capacity(6);
+        let ends = [(range.0, corners[0]), (range.1, corners[1])];
+        for (i, (t, corner)) in ends.into_iter().enumerate() {
+            let center = add(a.pos, scale(dir, t));
+            let mut end = match corner {
+                Some(corner) if dot(sub(corner, center), normal) > 0.0 => {
+                    vec![corner, center, sub(center, side)]
+                }
+                Some(corner) => vec![add(center, side), center, corner],
+                None => vec![add(center, side), sub(center, side)],
+            };
+            if i == 1 {
+                end.reverse();
+            }
+            outline.extend(end);
+        }
+        let inside = -self.extent;
+        let corner = |pos: [f32; 2]| -> Corner {
+            let offset = sub(pos, a.pos);
+            let t = (dot(offset, dir) / len).clamp(0.0, 1.0);
+            (pos, [dot(offset, normal), 0.0, inside], mix(a.color, b.color, t))
+        };
+        for i in 1..outline.len() - 1 {
+            self.triangle([corner(outline[0]), corner(outline[i]), corner(outline[i + 1])]);
+        }
+    }
+    // Where the inner edges of the segments meeting at `p` cross, or `None` if the line runs
+    // straight or the corner is more than `max_retreat` back along either segment.
+    fn inner_corner(
+        &self,
+        p: [f32; 2],
+        before: [f32; 2],
+        after: [f32; 2],
+        max_retreat: f32,
+    ) -> Option<[f32; 2]> {
+        let turn = cross(before, after);
+        if turn.abs() < 1e-6 {
+            return None;
+        }
+        // The extent times the tangent of half the turn.
+        let retreat = self.extent * turn.abs() / (1.0 + dot(before, after));
+        if retreat > max_retreat {
+            return None;
+        }
+        let side = if turn > 0.0 { -1.0 } else { 1.0 };
+        let outer_before = scale([-before[1], before[0]], side);
+        Some(sub(sub(p, scale(outer_before, self.extent)), scale(before, retreat)))
+    }
+    fn cap(&mut self, point: &LinePoint, dir: [f32; 2], cap: LineCap, inner: f32) {
+        let color = raw(point.color);
+        let normal = [-dir[1], dir[0]];
+        match cap {
+            // Turning clockwise from the normal passes through `dir`.
+            LineCap::Round => self.fan(point.pos, normal, -std::f32::consts::PI, color),
+            LineCap::Butt | LineCap::Square => {
+                let reach = match cap {
+                    LineCap::Square => self.half_width,
+                    _ => 0.0,
+                };
+                // `dir` points away from the line, so t runs from inside to beyond the cap.
+                let range = (-inner, reach + self.fringe);
+                self.strip(point.pos, dir, range, (color, color), |t| t - reach);
+            }
+        }
+    }
+    fn join(&mut self, point: &LinePoint, before: [f32; 2], after: [f32; 2], join: LineJoin) {
+        let turn = cross(before, after);
+        if turn.abs() < 1e-6 && dot(before, after) > 0.0 {
+            return;
+        }
+        let color = raw(point.color);
+        // The gap opens on the side the line turns away from.
+        let side = if turn > 0.0 { -1.0 } else { 1.0 };
+        let outer_before = scale([-before[1], before[0]], side);
+        let outer_after = scale([-after[1], after[0]], side);
+        let angle = dot(outer_before, outer_after).clamp(-1.0, 1.0).acos();
+        let bisector = add(outer_before, outer_after);
+        let bisector_length = length(bisector);
+        let cos_half = bisector_length * 0.5;
+        let join = match join {
+            LineJoin::Miter if cos_half * MITER_LIMIT < 1.0 => LineJoin::Bevel,
+            join => join,
+        };
+        let p = point.pos;
+        let e = self.extent;
+        let a = add(p, scale(outer_before, e));
+        let b = add(p, scale(outer_after, e));
+        match join {
+            LineJoin::Round => {
+                let sweep = angle * cross(outer_before, before).signum();
+                self.fan(p, outer_before, sweep, color);
+            }
+            // A reversing line has no bevel to draw.
+            _ if bisector_length < 1e-6 => {}
+            LineJoin::Bevel => {
+                let bisector = scale(bisector, 1.0 / bisector_length);
+                let inside = -e;
+                // Distance along the bisector over `cos_half` reaches the full extent at both
+                // outer corners and the line's edge right on the bevel.
+                self.triangle([
+                    (p, [0.0, 0.0, inside], color),
+                    (a, [dot(sub(a, p), bisector) / cos_half, 0.0, inside], color),
+                    (b, [dot(sub(b, p), bisector) / cos_half, 0.0, inside], color),
+                ]);
+            }
+            LineJoin::Miter => {
+                let bisector = scale(bisector, 1.0 / bisector_length);
+                let tip = add(p, scale(bisector, e / cos_half));
+                let inside = -e;
+                for (outer, corner) in [(outer_before, a), (outer_after, b)] {
+                    self.triangle([
+                        (p, [0.0, 0.0, inside], color),
+                        (corner, [e, 0.0, inside], color),
+                        (tip, [dot(sub(tip, p), outer), 0.0, inside], color),
+                    ]);
+                }
+            }
+        }
+    }
+    fn polyline(&mut self, line: &Polyline) {
+        let mut points = Vec::<&LinePoint>::with_capacity(line.points.len());
+        for point in &line.points {
+            if points.last().is_none_or(|last| length(sub(point.pos, last.pos)) > 1e-6) {
+                points.push(point);
+            }
+        }
+        let wraps = points.len() > 2
+            && length(sub(points[0].pos, points[points.len() - 1].pos)) <= 1e-6;
+        if line.closed && wraps {
+            points.pop();
+        }
+        let n = points.len();
+        if n < 2 || line.width <= 0.0 {
+            return;
+        }
+        let closed = line.closed && n > 2;
+        let segments = if closed { n } else { n - 1 };
+        let dir = |i: usize| {
+            let d = sub(points[(i + 1) % n].pos, points[i].pos);
+            scale(d, 1.0 / length(d))
+        };
+        let len = |i: usize| length(sub(points[(i + 1) % n].pos, points[i].pos));
+        // Segments are cut to the inner corner of each joint unless it lies past half of
+        // either one, where the other end's cut could reach. Sharper turns overlap.
+        let corners = (0..n)
+            .map(|j| {
+                if !closed && (j == 0 || j == n - 1) {
+                    return None;
+                }
+                let prev = (j + n - 1) % n;
+                let max_retreat = len(prev).min(len(j)) * 0.5;
+                self.inner_corner(points[j].pos, dir(prev), dir(j), max_retreat)
+            })
+            .collect::<Vec<_>>();
+        for i in 0..segments {
+            let (a, b) = (points[i], points[(i + 1) % n]);
+            let d = dir(i);
+            let len = len(i);
+            // Butt and square caps draw their own fade, so the body stops short of them.
+            let trim = if line.cap == LineCap::Round || closed {
+                0.0
+            } else {
+                self.fringe.min(len * 0.5)
+            };
+            let start = if i == 0 { trim } else { 0.0 };
+            let end = if i == segments - 1 { len - trim } else { len };
+            self.body(a, b, (start, end), [corners[i], corners[(i + 1) % n]]);
+            if !closed && i == 0 {
+                self.cap(a, scale(d, -1.0), line.cap, start);
+            }
+            if !closed && i == segments - 1 {
+                self.cap(b, d, line.cap, len - end);
+            }
+        }
+        let joins = if closed { 0..n } else { 1..n - 1 };
+        for j in joins {
+            let before = dir((j + n - 1) % n);
+            self.join(points[j], before, dir(j), line.join);
+        }
+    }
+}
+
+fn tessellate(lines: &[&Polyline], fringe: f32, vertices: &mut Vec<LineVertex>) {
+    for line in lines {
+        let half_width = line.width * 0.5;
+        let mut tessellator = Tessellator {
+            vertices: &mut *vertices,
+            z: line.z,
+            half_width,
+            extent: half_width + fringe,
+            fringe,
+        };
+        tessellator.polyline(line);
+    }
+}
+
+// Draws anti-aliased thick lines, tessellated on the CPU each frame. Lines are translucent at
+// their edges, so all of them are blended back to front after opaque geometry.
+pub struct LineRenderer {
+    vertex_buffer: wgpu::Buffer,
+    vertex_count: u32,
+    vertices: Vec<LineVertex>,
+    pipeline: Rc<wgpu::RenderPipeline>,
+    shader: Shader,
+    lines: Vec<Polyline>,
+}
+impl LineRenderer {
+    pub fn new(gfx: &mut Gfx) -> Self {
+        let gfx = gfx.data.borrow_mut();
+        let vertex_buffer = vertex_buffer(&gfx.device, 0);
+        let shader = Shader::new(&gfx, "line.wgsl", LINE_SHADER, &[]);
+        let pipeline = Self::create_pipeline(&gfx, &shader)
+            .unwrap_or_else(|err| panic!("{}: {}", shader.name(), err));
+        Self {
+            vertex_buffer,
+            vertex_count: 0,
+            vertices: vec![],
+            pipeline,
+            shader,
+            lines: vec![],
+        }
+    }
+    pub fn add(&mut self, line: Polyline) {
+        self.lines.push(line);
+    }
+    pub fn add_segment(&mut self, start: [f32; 2], end: [f32; 2], width: f32, color: Color) {
+        self.add(Polyline::segment(start, end, width, color));
+    }
+    pub fn clear(&mut self) {
+        self.lines.clear();
+    }
+    fn create_pipeline(
+        gfx: &GfxRenderData,
+        shader: &Shader,
+    ) -> Result<Rc<wgpu::RenderPipeline>, LayoutError> {
+        let vertex_layouts = [LineVertex::layout()];
+        let reflection = shader.reflection();
+        reflection.check_vertex_layouts("vs_main", &vertex_layouts)?;
+        reflection.check_bind_group_layouts(&[view_layout().entries()])?;
+        let bind_group_layouts = [gfx.view.layout()];
+        let desc = PipelineDescriptor::new(gfx, shader, &vertex_layouts, &bind_group_layouts);
+        Ok(gfx.pipelines.get(&gfx.device, &desc.transparent()))
+    }
+}
+impl Renderer for LineRenderer {
+    fn prepare(&mut self, data: &GfxRenderData) {
+        if self.shader.poll(data) {
+            match Self::create_pipeline(data, &self.shader) {
+                Ok(pipeline) => self.pipeline = pipeline,
+                Err(err) => eprintln!("{}: {}", self.shader.name(), err),
+            }
+        }
+        let mut lines = self.lines.iter().collect::<Vec<_>>();
+        sort_for_depth(&mut lines, data.depth.is_some(), |_| false, |line| line.z);
+        self.vertices.clear();
+        tessellate(&lines, 1.0 / data.camera.zoom, &mut self.vertices);
+        self.vertex_count = self.vertices.len() as u32;
+        let bytes = bytemuck::cast_slice(&self.vertices);
+        if bytes.len() as wgpu::BufferAddress > self.vertex_buffer.size() {
+            let size = (bytes.len() as wgpu::BufferAddress).next_power_of_two();
+            self.vertex_buffer = vertex_buffer(&data.device, size);
+        }
+        data.queue.write_buffer(&self.vertex_buffer, 0, bytes);
+    }
+    fn render<'a, 'b>(
+        &'a self,
+        _data: &'a GfxRenderData,
+        _render_pass: &mut wgpu::RenderPass<'b>,
+    )
+    where
+        'a: 'b
+    {
+    }
+    fn render_transparent<'a, 'b>(
+        &'a self,
+        data: &'a GfxRenderData,
+        render_pass: &mut wgpu::RenderPass<'b>,
+    )
+    where
+        'a: 'b
+    {
+        if self.vertex_count == 0 {
+            return;
+        }
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.set_bind_group(0, data.view.bind_group(), &[]);
+        render_pass.draw(0..self.vertex_count, 0..1);
+    }
+}
+
+fn vertex_buffer(device: &wgpu::Device, size: wgpu::BufferAddress) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("line_vertices"),
+        size: size.max(1024),
+        usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reflect::check_shader;
+
+    fn vertices(line: &Polyline) -> Vec<LineVertex> {
+        let mut vertices = vec![];
+        tessellate(&[line], 1.0, &mut vertices);
+        assert_eq!(vertices.len() % 3, 0);
+        vertices
+    }
+    fn triangles(line: &Polyline) -> usize {
+        vertices(line).len() / 3
+    }
+
+    #[test]
+    fn tessellates_joins_and_caps() {
+        // A rect for the segment and one for each butt cap.
+        let segment = Polyline::segment([0.0, 0.0], [10.0, 0.0], 2.0, Color::WHITE);
+        assert_eq!(triangles(&segment), 6);
+        // Each side of a joint cut to its inner corner takes another triangle.
+        let corner = Polyline::new(&[[0.0, 0.0], [10.0, 0.0], [10.0, 10.0]], 2.0, Color::WHITE);
+        assert_eq!(triangles(&corner), 10 + 2);
+        let bevel = Polyline { join: LineJoin::Bevel, ..corner.clone() };
+        assert_eq!(triangles(&bevel), 10 + 1);
+        // Straight runs and repeated points need no joins.
+        let points = [[0.0, 0.0], [5.0, 0.0], [5.0, 0.0], [10.0, 0.0]];
+        assert_eq!(triangles(&Polyline::new(&points, 2.0, Color::WHITE)), 8);
+        let closed = Polyline { closed: true, cap: LineCap::Round, ..corner };
+        assert_eq!(triangles(&closed), 3 * 4 + 3 * 2);
+    }
+
+    #[test]
+    fn joins_do_not_overlap_segments() {
+        // Extent 2 with the fringe: the segments' rects, from the butt caps' fade to the miter
+        // tip at (12, -2), cover 13 x 4 and 4 x 9 without overlapping.
+        let corner = Polyline::new(&[[0.0, 0.0], [10.0, 0.0], [10.0, 10.0]], 2.0, Color::WHITE);
+        let vertices = vertices(&corner);
+        let area = vertices
+            .chunks(3)
+            .map(|t| {
+                let [a, b, c] = [t[0].pos, t[1].pos, t[2].pos].map(|pos| [pos[0], pos[1]]);
+                cross(sub(b, a), sub(c, a)).abs() * 0.5
+            })
+            .sum::<f32>();
+        assert!((area - (13.0 * 4.0 + 4.0 * 9.0)).abs() < 1e-3, "{}", area);
+        let tip = [12.0, -2.0];
+        let at_tip = |vertex: &LineVertex| length(sub(tip, [vertex.pos[0], vertex.pos[1]])) < 1e-4;
+        assert!(vertices.iter().any(at_tip));
+    }
+
+    #[test]
+    fn sharp_miters_fall_back_to_bevels() {
+        let sharp = Polyline::new(&[[0.0, 0.0], [10.0, 0.0], [0.0, 1.0]], 2.0, Color::WHITE);
+        let bevel = Polyline { join: LineJoin::Bevel, ..sharp.clone() };
+        assert_eq!(vertices(&sharp), vertices(&bevel));
+        // Up to MITER_LIMIT the miter is kept.
+        let points = [[0.0, 0.0], [10.0, 0.0], [0.0, 10.0]];
+        let miter = Polyline::new(&points, 2.0, Color::WHITE);
+        let bevel = Polyline { join: LineJoin::Bevel, ..miter.clone() };
+        assert_ne!(vertices(&miter), vertices(&bevel));
+    }
+
+    #[test]
+    fn line_shader_matches_layouts() {
+        let view = view_layout();
+        let groups = [view.entries()];
+        check_shader("line.wgsl", LINE_SHADER, &[], &[LineVertex::layout()], &groups);
+    }
+}
@@ -0,0 +1,36 @@
+#include "quad_transform"
+
+struct LineOutput {
+    @builtin(position) pos: vec4<f32>,
+    @location(0) color: vec4<f32>,
+    // `LineVertex::edge` and the half width, in pixels.
+    @location(1) edge: vec3<f32>,
+    @location(2) @interpolate(flat) half_width: f32,
+};
+
+@vertex
+fn vs_main(
+    @location(0) pos: vec3<f32>,
+    @location(1) color: vec4<f32>,
+    @location(2) edge: vec3<f32>,
+    @location(3) half_width: f32,
+) -> LineOutput {
+    var out: LineOutput;
+    out.pos = world_to_clip(pos.xy, pos.z);
+    out.color = color;
+    out.edge = edge * view.zoom;
+    out.half_width = half_width * view.zoom;
+    return out;
+}
+
+@fragment
+fn fs_main(in: LineOutput) -> @location(0) vec4<f32> {
+    // Fades over a pixel across the sides of the line and past butt and square caps.
+    let side = saturate(in.half_width - length(in.edge.xy) + 0.5);
+    let cap = saturate(0.5 - in.edge.z);
+    let coverage = side * cap;
+    if coverage <= 0.0 {
+        discard;
+    }
+    return vec4<f32>(in.color.rgb, in.color.a * coverage);
+}
@@ -0,0 +1,400 @@
+use std::rc::Rc;
+
+use crate::bind_group::{BindGroupBuilder, BindGroupLayoutBuilder};
+use crate::gfx::{texture_layout, Gfx, GfxRenderData};
+use crate::pipeline::PipelineDescriptor;
+use crate::post::begin_pass;
+use crate::reflect::LayoutError;
+use crate::shader::Shader;
+use crate::texture::{Texture, Texture3d};
+use crate::uniform::UniformBuffer;
+
+const COLOR_GRADING_SHADER: &str = include_str!("color_grading.wgsl");
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CubeError {
+    pub file: String,
+    // 1-based, or 0 for errors that aren't about a particular line.
+    pub line: u32,
+    pub message: String,
+}
+impl std::fmt::Display for CubeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}: {}", self.file, self.line, self.message)
+    }
+}
+impl std::error::Error for CubeError {}
+
+// A 3D color lookup table in the Adobe/Resolve .cube format. Entries are ordered with red
+// changing fastest, then green, then blue.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CubeLut {
+    pub title: Option<String>,
+    pub size: u32,
+    pub domain_min: [f32; 3],
+    pub domain_max: [f32; 3],
+    pub entries: Vec<[f32; 3]>,
+}
+impl CubeLut {
+    pub fn from_file(path: &str) -> Result<Self, CubeError> {
+        let source = std::fs::read_to_string(path).map_err(|err| CubeError {
+            file: path.to_string(),
+            line: 0,
+            message: err.to_string(),
+        })?;
+        Self::parse(path, &source)
+    }
+    // `name` is only used in errors.
+    pub fn parse(name: &str, source: &str) -> Result<Self, CubeError> {
+        let error = |line: usize, message: String| CubeError {
+            file: name.to_string(),
+            line: line as u32,
+            message,
+        };
+        let mut title = None;
+        let mut size = None;
+        let mut domain_min = [0.0; 3];
+        let mut domain_max = [1.0; 3];
+        let mut entries = vec![];
+        for (i, line) in source.lines().enumerate() {
+            let line_number = i + 1;
+            let line = line.split('#').next().unwrap_or("").trim();
+            let Some(keyword) = line.split_whitespace().next() else {
+                continue;
+            };
+            let rest = line[keyword.len()..].trim();
+            let floats = |count: usize| -> Result<Vec<f32>, CubeError> {
+                let values = rest
+                    .split_whitespace()
+                    .map(|value| value.parse::<f32>())
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|err| error(line_number, format!("{}: {}", keyword, err)))?;
+                if values.len() != count {
+                    let message = format!("{} expects {} values", keyword, count);
+                    return Err(error(line_number, message));
+                }
+                Ok(values)
+            };
+            match keyword {
+                "TITLE" => title = Some(rest.trim_matches('"').to_string()),
+                "LUT_3D_SIZE" => {
+                    let value = rest.parse::<u32>().ok().filter(|size| (2..=256).contains(size));
+                    let message = format!("LUT_3D_SIZE must be between 2 and 256, found {}", rest);
+                    size = Some(value.ok_or_else(|| error(line_number, message))?);
+                }
+                "LUT_1D_SIZE" => {
+                    let message = "1D LUTs are not supported".to_string();
+                    return Err(error(line_number, message));
+                }
+                "DOMAIN_MIN" => domain_min.copy_from_slice(&floats(3)?),
+                "DOMAIN_MAX" => domain_max.copy_from_slice(&floats(3)?),
+                // Resolve's single range for all channels.
+                "LUT_3D_INPUT_RANGE" => {
+                    let range = floats(2)?;
+                    domain_min = [range[0]; 3];
+                    domain_max = [range[1]; 3];
+                }
+                _ if keyword.starts_with(|c: char| c.is_ascii_alphabetic()) => {
+                    let message = format!("unknown keyword {}", keyword);
+                    return Err(error(line_number, message));
+                }
+                _ => {
+                    let values = line
+                        .split_whitespace()
+                        .map(|value| value.parse::<f32>())
+                        .collect::<Result<Vec<_>, _>>()
+                        .map_err(|err| error(line_number, format!("invalid entry: {}", err)))?;
+                    let [r, g, b] = values[..] else {
+                        let message = format!("entries need 3 values, found {}", values.len());
+                        return Err(error(line_number, message));
+                    };
+                    if size.is_none() {
+                        let message = "entries before LUT_3D_SIZE".to_string();
+                        return Err(error(line_number, message));
+                    }
+                    entries.push([r, g, b]);
+                }
+            }
+        }
+        let size = size.ok_or_else(|| error(0, "missing LUT_3D_SIZE".to_string()))?;
+        let expected = (size * size * size) as usize;
+        if entries.len() != expected {
+            let message = format!("expected {} entries, found {}", expected, entries.len());
+            return Err(error(0, message));
+        }
+        if (0..3).any(|i| domain_max[i] <= domain_min[i]) {
+            let message = "DOMAIN_MAX must be above DOMAIN_MIN".to_string();
+            return Err(error(0, message));
+        }
+        Ok(Self {
+            title,
+            size,
+            domain_min,
+            domain_max,
+            entries,
+        })
+    }
+    // Passes colors through unchanged. `size` must be between 2 and 256, like LUT_3D_SIZE.
+    pub fn identity(size: u32) -> Self {
+        assert!((2..=256).contains(&size), "LUT size must be between 2 and 256, found {}", size);
+        let step = |i: u32| i as f32 / (size - 1) as f32;
+        let entries = (0..size * size * size)
+            .map(|i| [step(i % size), step(i / size % size), step(i / (size * size))])
+            .collect();
+        Self {
+            title: None,
+            size,
+            domain_min: [0.0; 3],
+            domain_max: [1.0; 3],
+            entries,
+        }
+    }
+
+    fn texture(&self, data: &GfxRenderData) -> Texture3d {
+        let bytes = self
+            .entries
+            .iter()
+            .flat_map(|&[r, g, b]| [r, g, b, 1.0])
+            .flat_map(|c| half::f16::from_f32(c).to_le_bytes())
+            .collect::<Vec<_>>();
+        let size = [self.size; 3];
+        Texture3d::create(data, size, wgpu::TextureFormat::Rgba16Float, &bytes)
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct GradingUniforms {
+    domain_min: [f32; 4],
+    domain_max: [f32; 4],
+    blend_domain_min: [f32; 4],
+    blend_domain_max: [f32; 4],
+    strength: f32,
+    blend: f32,
+    _padding: [f32; 2],
+}
+
+// Grades every pixel through a 3D LUT with trilinear interpolation, optionally blending
+// towards a second LUT. Set with `Gfx::set_color_grading`, it runs last, after post effects
+// and tonemapping, so LUTs map display colors and HDR colors reach bloom unclamped.
+pub struct ColorGrading {
+    shader: Shader,
+    pipeline: Rc<wgpu::RenderPipeline>,
+    // What the stage before grading draws into, reallocated whenever the screen size changed.
+    input: Option<Texture>,
+    uniforms: UniformBuffer<GradingUniforms>,
+    lut_layout: wgpu::BindGroupLayout,
+    lut_bind_group: wgpu::BindGroup,
+    luts: Vec<CubeLut>,
+    // Set when `luts` changed since the textures were last uploaded.
+    luts_changed: bool,
+    strength: f32,
+    blend: f32,
+}
+impl ColorGrading {
+    pub fn new(gfx: &mut Gfx, lut: CubeLut) -> Self {
+        let data = gfx.data.borrow_mut();
+        let shader = Shader::new(&data, "color_grading.wgsl", COLOR_GRADING_SHADER, &[]);
+        let uniforms = UniformBuffer::new(
+            &data.device,
+            wgpu::ShaderStages::FRAGMENT,
+            &bytemuck::Zeroable::zeroed(),
+            Some("color_grading"),
+        );
+        let lut_layout = lut_layout().build(&data.device, Some("color_grading_luts"));
+        let pipeline = Self::create_pipeline(&data, &shader, &uniforms, &lut_layout)
+            .unwrap_or_else(|err| panic!("{}: {}", shader.name(), err));
+        let lut_bind_group = create_lut_bind_group(&data, &lut_layout, &[&lut]);
+        Self {
+            shader,
+            pipeline,
+            input: None,
+            uniforms,
+            lut_layout,
+            lut_bind_group,
+            luts: vec![lut],
+            luts_changed: false,
+            strength: 1.0,
+            blend: 0.0,
+        }
+    }
+    // Replaces the first LUT, uploaded at the start of the next frame.
+    pub fn set_lut(&mut self, lut: CubeLut) {
+        self.luts[0] = lut;
+        self.luts_changed = true;
+    }
+    // The LUT `blend` moves towards. Without one only the first LUT is applied.
+    pub fn set_blend_lut(&mut self, lut: Option<CubeLut>) {
+        self.luts.truncate(1);
+        self.luts.extend(lut);
+        self.luts_changed = true;
+    }
+    pub fn strength(&self) -> f32 {
+        self.strength
+    }
+    pub fn set_strength(&mut self, strength: f32) {
+        self.strength = strength;
+    }
+    pub fn blend(&self) -> f32 {
+        self.blend
+    }
+    pub fn set_blend(&mut self, blend: f32) {
+        self.blend = blend;
+    }
+
+    fn create_pipeline(
+        data: &GfxRenderData,
+        shader: &Shader,
+        uniforms: &UniformBuffer<GradingUniforms>,
+        lut_layout: &wgpu::BindGroupLayout,
+    ) -> Result<Rc<wgpu::RenderPipeline>, LayoutError> {
+        let reflection = shader.reflection();
+        reflection.check_vertex_layouts("vs_main", &[])?;
+        reflection.check_bind_group_layouts(&[
+            texture_layout(true).entries(),
+            UniformBuffer::<GradingUniforms>::layout_builder(wgpu::ShaderStages::FRAGMENT)
+                .entries(),
+            self::lut_layout().entries(),
+        ])?;
+        let layouts = [data.texture_bind_group_layout(true), uniforms.layout(), lut_layout];
+        // Grading draws the final frame, after tonemapping from any HDR format.
+        let desc = PipelineDescriptor {
+            format: data.surface_format,
+            depth_format: None,
+            depth_write: false,
+            ..PipelineDescriptor::new(data, shader, &[], &layouts)
+        };
+        Ok(data.pipelines.get(&data.device, &desc))
+    }
+
+    // Uploads the uniforms and any changed LUTs, and sizes the input to the screen.
+    pub(crate) fn prepare(&mut self, data: &GfxRenderData) {
+        if self.shader.poll(data) {
+            match Self::create_pipeline(data, &self.shader, &self.uniforms, &self.lut_layout) {
+                Ok(pipeline) => self.pipeline = pipeline,
+                Err(err) => eprintln!("{}: {}", self.shader.name(), err),
+            }
+        }
+        if self.luts_changed {
+            let luts = self.luts.iter().collect::<Vec<_>>();
+            self.lut_bind_group = create_lut_bind_group(data, &self.lut_layout, &luts);
+            self.luts_changed = false;
+        }
+        let lut = &self.luts[0];
+        let blend_lut = self.luts.last().unwrap_or(lut);
+        let vec4 = |[x, y, z]: [f32; 3]| [x, y, z, 0.0];
+        self.uniforms.set(
+            &data.queue,
+            &GradingUniforms {
+                domain_min: vec4(lut.domain_min),
+                domain_max: vec4(lut.domain_max),
+                blend_domain_min: vec4(blend_lut.domain_min),
+                blend_domain_max: vec4(blend_lut.domain_max),
+                strength: self.strength,
+                blend: if self.luts.len() > 1 { self.blend } else { 0.0 },
+                _padding: [0.0; 2],
+            },
+        );
+        let size = (data.size.width, data.size.height);
+        if self.input.as_ref().map(|input| input.size()) != Some(size) {
+            let input = Texture::render_target(data, size.0, size.1, data.surface_format);
+            self.input = Some(input);
+        }
+    }
+    // What the frame is drawn into before grading.
+    pub(crate) fn input(&self) -> Option<&Texture> {
+        self.input.as_ref()
+    }
+    pub(crate) fn render(&self, encoder: &mut wgpu::CommandEncoder, output: &wgpu::TextureView) {
+        let Some(input) = self.input.as_ref() else {
+            return;
+        };
+        let mut render_pass = begin_pass(encoder, output, wgpu::LoadOp::Clear(wgpu::Color::BLACK));
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &input.bind_group, &[]);
+        render_pass.set_bind_group(1, self.uniforms.bind_group(), &[]);
+        render_pass.set_bind_group(2, &self.lut_bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
+
+fn lut_layout() -> BindGroupLayoutBuilder {
+    let lut = wgpu::TextureSampleType::Float { filterable: true };
+    BindGroupLayoutBuilder::new()
+        .texture(wgpu::ShaderStages::FRAGMENT, lut, wgpu::TextureViewDimension::D3)
+        .texture(wgpu::ShaderStages::FRAGMENT, lut, wgpu::TextureViewDimension::D3)
+        .sampler(wgpu::ShaderStages::FRAGMENT, true)
+}
+
+// Without a second LUT the first is bound in its place.
+fn create_lut_bind_group(
+    data: &GfxRenderData,
+    layout: &wgpu::BindGroupLayout,
+    luts: &[&CubeLut],
+) -> wgpu::BindGroup {
+    let textures = luts.iter().map(|lut| lut.texture(data)).collect::<Vec<_>>();
+    let blend = textures.last().unwrap_or(&textures[0]);
+    BindGroupBuilder::new()
+        .texture_view(&textures[0].view)
+        .texture_view(&blend.view)
+        .sampler(&textures[0].sampler)
+        .build(&data.device, layout, Some("color_grading_luts"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reflect::check_shader;
+
+    #[test]
+    fn parses_cube_files() {
+        let lut = CubeLut::parse(
+            "warm.cube",
+            r#"# Created by hand
+TITLE "Warm"
+LUT_3D_SIZE 2
+DOMAIN_MIN 0 0 0
+DOMAIN_MAX 1 1 1
+
+0 0 0
+1 0 0
+0 1 0
+1 1 0
+0 0 1
+1 0 1
+0 1 1
+1 1 1 # white
+"#,
+        )
+        .unwrap();
+        assert_eq!(lut.title.as_deref(), Some("Warm"));
+        assert_eq!(lut, CubeLut { title: lut.title.clone(), ..CubeLut::identity(2) });
+    }
+
+    #[test]
+    fn reports_cube_errors_with_lines() {
+        let error = |source: &str| {
+            let err = CubeLut::parse("bad.cube", source).unwrap_err();
+            (err.line, err.message)
+        };
+        assert_eq!(error("LUT_3D_SIZE 2\n0 0\n").0, 2);
+        assert_eq!(error("LUT_3D_SIZE 1\n").0, 1);
+        assert_eq!(error("LUT_3D_SIZE 2\n0 0 x\n").0, 2);
+        assert_eq!(error("LUT_1D_SIZE 16\n").0, 1);
+        assert_eq!(error("0 0 0\n").0, 1);
+        assert_eq!(error("LUT_3D_SIZE 2\n0 0 0\n"), (0, "expected 8 entries, found 1".into()));
+    }
+
+    #[test]
+    fn color_grading_matches_layouts() {
+        let texture = texture_layout(true);
+        let uniforms =
+            UniformBuffer::<GradingUniforms>::layout_builder(wgpu::ShaderStages::FRAGMENT);
+        let lut = self::lut_layout();
+        let groups = [texture.entries(), uniforms.entries(), lut.entries()];
+        let source = COLOR_GRADING_SHADER;
+        let reflection = check_shader("color_grading.wgsl", source, &[], &[], &groups);
+        let size = reflection.binding("effect_uniforms").unwrap().size.unwrap();
+        assert_eq!(size as usize, std::mem::size_of::<GradingUniforms>());
+    }
+}
@@ -4,10 +4,10 @@ use winit::{
     window::WindowBuilder,
 };
 
+use gfxperiment::assets::Assets;
 use gfxperiment::color::Color;
 use gfxperiment::gfx::Gfx;
 use gfxperiment::quad::{Quad, QuadRenderer, TexturedQuad, TexturedQuadRenderer };
-use gfxperiment::texture::Texture;
 
 const WINDOW_SIZE: winit::dpi::PhysicalSize<u32> = winit::dpi::PhysicalSize {
     width: 600,
@@ -27,26 +27,40 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     event_loop.set_control_flow(ControlFlow::Poll);
 
     let mut gfx = Gfx::new(&window).await;
+    let mut assets = Assets::new()?;
+    gfx.enable_depth_buffer();
+    if cfg!(debug_assertions) {
+        gfx.enable_shader_hot_reload(concat!(env!("CARGO_MANIFEST_DIR"), "/src"));
+    }
 
     let mut quad_renderer = Box::new(QuadRenderer::new(&mut gfx));
     let quads = vec![
         Quad {
             pos: [100., 100.],
+            z: 0.,
             width: 30.,
             height: 30.,
             color: Color::WHITE,
+            casts_shadow: false,
+            box_shadow: None,
         },
         Quad {
             pos: [200., 200.],
+            z: 0.,
             width: 40.,
             height: 60.,
             color: Color::RED,
+            casts_shadow: false,
+            box_shadow: None,
         },
         Quad {
-            pos: [500., 300.],
+            pos: [450., 200.],
+            z: 0.5,
             width: 80.,
             height: 40.,
-            color: Color::BLUE,
+            color: Color::new(0., 0., 1., 0.5),
+            casts_shadow: false,
+            box_shadow: None,
         },
     ];
     for quad in quads {
@@ -58,9 +72,11 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let tex_quads = vec![
         TexturedQuad {
             pos: [400., 150.],
+            z: 0.25,
             width: 128.,
             height: 128.,
-            texture: Texture::from_file(&mut gfx, "./testtexture.png"),
+            texture: assets.load_texture_async(&mut gfx, "./testtexture.png"),
+            normal_map: None,
         },
     ];
     for tex_quad in tex_quads {
@@ -76,7 +92,12 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             println!("The close button was pressed; stopping");
             elwt.exit();
         }
+        Event::WindowEvent {
+            event: WindowEvent::Resized(size),
+            ..
+        } => gfx.resize(size),
         Event::AboutToWait => {
+            assets.update(&mut gfx);
             gfx.draw();
         }
         Event::WindowEvent {
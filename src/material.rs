@@ -0,0 +1,359 @@
+use std::rc::Rc;
+
+use crate::bind_group::{BindGroupBuilder, BindGroupLayoutBuilder};
+use crate::gfx::{view_layout, Gfx, GfxRenderData, Renderer};
+use crate::pipeline::PipelineDescriptor;
+use crate::quad::{instance_buffer, sort_for_depth, write_instances, Quad, QuadRaw};
+use crate::reflect::LayoutError;
+use crate::shader::Shader;
+use crate::texture::TextureHandle;
+use crate::uniform::{
+    check_uniform_layout, check_uniform_size, Uniform, UniformBuffer, UniformLayoutError,
+    UniformSizeError,
+};
+use crate::vertex::Vertex;
+
+// Wraps the user's fragment function in the standard quad vertex stage. The user source must
+// declare `struct MaterialUniforms` matching the Rust uniform type and
+// `fn material(in: MaterialInput) -> vec4<f32>`.
+const MATERIAL_TEMPLATE: &str = r#"#define INSTANCE_COLOR
+#include "quad_transform"
+
+struct MaterialInput {
+    uv: vec2<f32>,
+    color: vec4<f32>,
+    // Framebuffer position in pixels.
+    frag_coord: vec2<f32>,
+};
+
+@group(1) @binding(0)
+var<uniform> material_uniforms: MaterialUniforms;
+
+@vertex
+fn vs_main(
+    @location(0) vin: vec2<f32>,
+    @location(1) uv: vec2<f32>,
+    instance: Instance,
+) -> VertexOutput {
+    var out: VertexOutput;
+    out.pos = quad_to_clip(vin, instance.pos, instance.size);
+    out.color = instance.color;
+    out.uv = uv;
+    return out;
+}
+
+@fragment
+fn fs_main(vin: VertexOutput) -> @location(0) vec4<f32> {
+    return material(MaterialInput(vin.uv, vin.color, vin.pos.xy));
+}
+"#;
+
+// A WGSL fragment function together with its uniforms and textures. Texture `i` is available
+// to the fragment function as `material_texture_i` and `material_sampler_i`. Unfilterable
+// textures, such as 32-bit float ones, come with a non-filtering sampler.
+pub struct Material<U: Uniform> {
+    pub name: String,
+    pub fragment: String,
+    pub uniforms: U,
+    pub textures: Vec<TextureHandle>,
+}
+impl<U: Uniform> Material<U> {
+    pub fn new(name: &str, fragment: &str, uniforms: U) -> Self {
+        Self {
+            name: name.to_string(),
+            fragment: fragment.to_string(),
+            uniforms,
+            textures: vec![],
+        }
+    }
+    pub fn with_texture(mut self, texture: TextureHandle) -> Self {
+        self.textures.push(texture);
+        self
+    }
+
+    fn source(&self) -> String {
+        let mut source = MATERIAL_TEMPLATE.to_string();
+        for i in 0..self.textures.len() {
+            source.push_str(&format!(
+                "\n@group(2) @binding({})\nvar material_texture_{}: texture_2d<f32>;\n\
+                 @group(2) @binding({})\nvar material_sampler_{}: sampler;\n",
+                2 * i,
+                i,
+                2 * i + 1,
+                i
+            ));
+        }
+        source.push('\n');
+        source.push_str(&self.fragment);
+        source
+    }
+    // `filterable` holds whether each texture can be sampled with filtering.
+    fn texture_layout(&self, filterable: &[bool]) -> BindGroupLayoutBuilder {
+        filterable.iter().fold(BindGroupLayoutBuilder::new(), |builder, &filterable| {
+            builder
+                .texture_2d(wgpu::ShaderStages::FRAGMENT, filterable)
+                .sampler(wgpu::ShaderStages::FRAGMENT, filterable)
+        })
+    }
+    fn layouts(&self, filterable: &[bool]) -> Vec<BindGroupLayoutBuilder> {
+        let mut layouts = vec![
+            view_layout(),
+            UniformBuffer::<U>::layout_builder(wgpu::ShaderStages::FRAGMENT),
+        ];
+        if !self.textures.is_empty() {
+            layouts.push(self.texture_layout(filterable));
+        }
+        layouts
+    }
+}
+
+pub struct MaterialQuadRenderer<U: Uniform> {
+    instance_buffer: wgpu::Buffer,
+    uniforms: UniformBuffer<U>,
+    texture_layout: wgpu::BindGroupLayout,
+    texture_bind_group: wgpu::BindGroup,
+    // Ids of the textures the bind group was built from, so reloaded textures are picked up.
+    bound_textures: Vec<wgpu::Id<wgpu::Texture>>,
+    // Whether each texture was filterable when the layout was built.
+    filterable: Vec<bool>,
+    pipeline: Rc<wgpu::RenderPipeline>,
+    material: Material<U>,
+    quads: Vec<Quad>,
+}
+impl<U: Uniform> MaterialQuadRenderer<U> {
+    pub fn new(gfx: &mut Gfx, material: Material<U>) -> Result<Self, MaterialError> {
+        let gfx = gfx.data.borrow_mut();
+        let shader = Shader::try_new(&gfx, &material.name, &material.source(), &[])
+            .map_err(MaterialError::Shader)?;
+        let filterable = texture_filterable(&gfx, &material);
+        let layouts = material.layouts(&filterable);
+        let entries = layouts.iter().map(|layout| layout.entries()).collect::<Vec<_>>();
+        let reflection = shader.reflection();
+        reflection
+            .check_vertex_layouts("vs_main", &[Vertex::layout(), QuadRaw::layout()])
+            .and_then(|_| reflection.check_bind_group_layouts(&entries))
+            .map_err(MaterialError::Layout)?;
+        let binding = reflection.binding("material_uniforms");
+        let uniform_size = binding.and_then(|binding| binding.size).unwrap_or(0);
+        if let Some(binding) = binding {
+            check_uniform_layout::<U>(&binding.scalars).map_err(MaterialError::UniformLayout)?;
+        }
+        check_uniform_size(std::mem::size_of::<U>()).map_err(MaterialError::UniformPadding)?;
+        // Rust types may carry trailing padding that the WGSL struct doesn't need.
+        if (uniform_size as usize) > std::mem::size_of::<U>() {
+            return Err(MaterialError::UniformSize {
+                shader: uniform_size,
+                rust: std::mem::size_of::<U>(),
+            });
+        }
+
+        let instance_buffer = instance_buffer(&gfx.device, "material_instances", 0);
+        let uniforms = UniformBuffer::new(
+            &gfx.device,
+            wgpu::ShaderStages::FRAGMENT,
+            &material.uniforms,
+            Some(&material.name),
+        );
+        let texture_layout = material
+            .texture_layout(&filterable)
+            .build(&gfx.device, Some(&material.name));
+        let (texture_bind_group, bound_textures) =
+            create_texture_bind_group(&gfx, &texture_layout, &material);
+        // The texture group is only part of the layout when the material has textures.
+        let bind_group_layouts = [gfx.view.layout(), uniforms.layout(), &texture_layout];
+        let vertex_layouts = [Vertex::layout(), QuadRaw::layout()];
+        let desc = PipelineDescriptor::new(
+            &gfx,
+            &shader,
+            &vertex_layouts,
+            &bind_group_layouts[..layouts.len()],
+        )
+        .transparent();
+        let pipeline = gfx.pipelines.get(&gfx.device, &desc);
+        Ok(Self {
+            instance_buffer,
+            uniforms,
+            texture_layout,
+            texture_bind_group,
+            bound_textures,
+            filterable,
+            pipeline,
+            material,
+            quads: vec![],
+        })
+    }
+    pub fn add(&mut self, quad: Quad) {
+        self.quads.push(quad);
+    }
+    // Uploaded at the start of the next frame.
+    pub fn set_uniforms(&mut self, uniforms: U) {
+        self.material.uniforms = uniforms;
+    }
+    pub fn uniforms(&self) -> &U {
+        &self.material.uniforms
+    }
+}
+impl<U: Uniform> Renderer for MaterialQuadRenderer<U> {
+    fn prepare(&mut self, data: &GfxRenderData) {
+        self.uniforms.set(&data.queue, &self.material.uniforms);
+        let textures = self
+            .material
+            .textures
+            .iter()
+            .map(|handle| data.texture(*handle).texture.global_id())
+            .collect::<Vec<_>>();
+        if textures != self.bound_textures {
+            if texture_filterable(data, &self.material) == self.filterable {
+                (self.texture_bind_group, self.bound_textures) =
+                    create_texture_bind_group(data, &self.texture_layout, &self.material);
+            } else {
+                eprintln!(
+                    "{}: a texture changed filterability, keeping the old textures",
+                    self.material.name
+                );
+                self.bound_textures = textures;
+            }
+        }
+        let mut quads = self.quads.iter().collect::<Vec<_>>();
+        sort_for_depth(&mut quads, data.depth.is_some(), |_| false, |quad| quad.z);
+        let instances = quads
+            .into_iter()
+            .map(|quad| quad.into())
+            .collect::<Vec<QuadRaw>>();
+        let bytes = bytemuck::cast_slice(&instances);
+        write_instances(data, &mut self.instance_buffer, "material_instances", bytes);
+    }
+    fn render<'a, 'b>(
+        &'a self,
+        _data: &'a GfxRenderData,
+        _render_pass: &mut wgpu::RenderPass<'b>,
+    )
+    where
+        'a: 'b
+    {
+    }
+    // Materials may output any alpha, so every instance is drawn blended.
+    fn render_transparent<'a, 'b>(
+        &'a self,
+        data: &'a GfxRenderData,
+        render_pass: &mut wgpu::RenderPass<'b>,
+    )
+    where
+        'a: 'b
+    {
+        if self.quads.is_empty() {
+            return;
+        }
+        render_pass.set_pipeline(&self.pipeline);
+        data.quad_geometry.set_buffers(render_pass);
+        render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
+        render_pass.set_bind_group(0, data.view.bind_group(), &[]);
+        render_pass.set_bind_group(1, self.uniforms.bind_group(), &[]);
+        if !self.material.textures.is_empty() {
+            render_pass.set_bind_group(2, &self.texture_bind_group, &[]);
+        }
+        render_pass.draw_indexed(0..data.quad_geometry.index_count(), 0, 0..self.quads.len() as u32);
+    }
+}
+
+#[derive(Debug)]
+pub enum MaterialError {
+    Shader(crate::shader::ShaderError),
+    Layout(LayoutError),
+    UniformSize { shader: u32, rust: usize },
+    UniformPadding(UniformSizeError),
+    UniformLayout(UniformLayoutError),
+}
+impl std::fmt::Display for MaterialError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Shader(err) => write!(f, "{}\n{}", err, err.report),
+            Self::Layout(err) => write!(f, "{}", err),
+            Self::UniformSize { shader, rust } => write!(
+                f,
+                "MaterialUniforms is {} bytes in WGSL but the Rust type is {} bytes",
+                shader, rust
+            ),
+            Self::UniformPadding(err) => write!(f, "MaterialUniforms: {}", err),
+            Self::UniformLayout(err) => write!(f, "MaterialUniforms: {}", err),
+        }
+    }
+}
+impl std::error::Error for MaterialError {}
+
+fn texture_filterable<U: Uniform>(data: &GfxRenderData, material: &Material<U>) -> Vec<bool> {
+    material
+        .textures
+        .iter()
+        .map(|handle| data.texture(*handle).filterable)
+        .collect()
+}
+
+fn create_texture_bind_group<U: Uniform>(
+    data: &GfxRenderData,
+    layout: &wgpu::BindGroupLayout,
+    material: &Material<U>,
+) -> (wgpu::BindGroup, Vec<wgpu::Id<wgpu::Texture>>) {
+    let textures = material
+        .textures
+        .iter()
+        .map(|handle| data.texture(*handle))
+        .collect::<Vec<_>>();
+    let bind_group = textures
+        .iter()
+        .fold(BindGroupBuilder::new(), |builder, texture| {
+            builder.texture_view(&texture.view).sampler(&texture.sampler)
+        })
+        .build(&data.device, layout, Some(&material.name));
+    let ids = textures
+        .iter()
+        .map(|texture| texture.texture.global_id())
+        .collect();
+    (bind_group, ids)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reflect::reflect_shader;
+
+    #[test]
+    fn textured_material_matches_layouts() {
+        let material = Material::new(
+            "dissolve",
+            r#"
+struct MaterialUniforms {
+    threshold: f32,
+    edge: f32,
+};
+
+fn material(in: MaterialInput) -> vec4<f32> {
+    let noise = textureSample(material_texture_0, material_sampler_0, in.uv).r;
+    if noise < material_uniforms.threshold {
+        discard;
+    }
+    return in.color;
+}
+"#,
+            [0.5f32, 0.1],
+        )
+        .with_texture(TextureHandle(0));
+        let source = material.source();
+        let reflection = reflect_shader("dissolve", &source, &[]);
+        // Float32 textures are bound with unfilterable layouts.
+        for filterable in [true, false] {
+            reflection
+                .check_bind_group_layouts(
+                    &material
+                        .layouts(&[filterable])
+                        .iter()
+                        .map(|layout| layout.entries())
+                        .collect::<Vec<_>>(),
+                )
+                .unwrap();
+        }
+        let binding = reflection.binding("material_uniforms").unwrap();
+        assert_eq!(binding.size, Some(8));
+        check_uniform_layout::<[f32; 2]>(&binding.scalars).unwrap();
+    }
+}
@@ -0,0 +1,211 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::gfx::GfxRenderData;
+use crate::shader::Shader;
+
+// How a pipeline uses the stencil buffer, if the depth buffer has one. Masks are drawn by
+// incrementing or decrementing the stencil where it equals the reference, everything else only
+// draws where it equals the reference.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum StencilMode {
+    Test,
+    Increment,
+    Decrement,
+}
+
+// Everything that distinguishes one quad-style pipeline from another. Entry points are always
+// `vs_main` and `fs_main`.
+#[derive(Clone, Copy)]
+pub struct PipelineDescriptor<'a> {
+    pub shader: &'a Shader,
+    pub vertex_layouts: &'a [wgpu::VertexBufferLayout<'static>],
+    pub bind_group_layouts: &'a [&'a wgpu::BindGroupLayout],
+    pub blend: Option<wgpu::BlendState>,
+    pub format: wgpu::TextureFormat,
+    pub sample_count: u32,
+    pub depth_format: Option<wgpu::TextureFormat>,
+    pub depth_write: bool,
+    pub stencil: StencilMode,
+}
+impl<'a> PipelineDescriptor<'a> {
+    // Targets the surface and its depth buffer, if any, without blending.
+    pub fn new(
+        data: &GfxRenderData,
+        shader: &'a Shader,
+        vertex_layouts: &'a [wgpu::VertexBufferLayout<'static>],
+        bind_group_layouts: &'a [&'a wgpu::BindGroupLayout],
+    ) -> Self {
+        Self {
+            shader,
+            vertex_layouts,
+            bind_group_layouts,
+            blend: Some(wgpu::BlendState::REPLACE),
+            format: data.format,
+            sample_count: data.sample_count,
+            depth_format: data.depth.as_ref().map(|depth| depth.format),
+            depth_write: true,
+            stencil: StencilMode::Test,
+        }
+    }
+    // Alpha blended and depth tested without writing depth, for instances drawn back to
+    // front after everything opaque.
+    pub fn transparent(self) -> Self {
+        Self {
+            blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+            depth_write: false,
+            ..self
+        }
+    }
+    // Mask pipelines only write the stencil buffer and ignore depth.
+    pub fn with_stencil(self, stencil: StencilMode) -> Self {
+        Self {
+            stencil,
+            depth_write: self.depth_write && stencil == StencilMode::Test,
+            ..self
+        }
+    }
+    pub fn with_blend(mut self, blend: Option<wgpu::BlendState>) -> Self {
+        self.blend = blend;
+        self
+    }
+}
+
+#[derive(PartialEq, Eq, Hash)]
+struct PipelineKey {
+    code: String,
+    vertex_layouts: Vec<wgpu::VertexBufferLayout<'static>>,
+    bind_group_layouts: Vec<wgpu::Id<wgpu::BindGroupLayout>>,
+    blend: Option<wgpu::BlendState>,
+    format: wgpu::TextureFormat,
+    sample_count: u32,
+    depth_format: Option<wgpu::TextureFormat>,
+    depth_write: bool,
+    stencil: StencilMode,
+}
+
+// Pipelines shared between renderers. Shaders are keyed by their preprocessed code, so a
+// hot-reloaded shader gets a new pipeline while renderers that still use the old code keep
+// theirs.
+#[derive(Default)]
+pub struct PipelineCache {
+    pipelines: RefCell<HashMap<PipelineKey, Rc<wgpu::RenderPipeline>>>,
+}
+impl PipelineCache {
+    pub fn get(&self, device: &wgpu::Device, desc: &PipelineDescriptor) -> Rc<wgpu::RenderPipeline> {
+        let key = PipelineKey {
+            code: desc.shader.code().to_string(),
+            vertex_layouts: desc.vertex_layouts.to_vec(),
+            bind_group_layouts: desc
+                .bind_group_layouts
+                .iter()
+                .map(|layout| layout.global_id())
+                .collect(),
+            blend: desc.blend,
+            format: desc.format,
+            sample_count: desc.sample_count,
+            depth_format: desc.depth_format,
+            depth_write: desc.depth_write,
+            stencil: desc.stencil,
+        };
+        let mut pipelines = self.pipelines.borrow_mut();
+        if let Some(pipeline) = pipelines.get(&key) {
+            return pipeline.clone();
+        }
+        // Drop pipelines no renderer holds any more, e.g. ones built from an old shader.
+        pipelines.retain(|_, pipeline| Rc::strong_count(pipeline) > 1);
+        let pipeline = Rc::new(create_pipeline(device, desc));
+        pipelines.insert(key, pipeline.clone());
+        pipeline
+    }
+    pub fn len(&self) -> usize {
+        self.pipelines.borrow().len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.pipelines.borrow().is_empty()
+    }
+}
+
+fn create_pipeline(device: &wgpu::Device, desc: &PipelineDescriptor) -> wgpu::RenderPipeline {
+    let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some(desc.shader.name()),
+        source: wgpu::ShaderSource::Wgsl(desc.shader.code().into()),
+    });
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: None,
+        bind_group_layouts: desc.bind_group_layouts,
+        push_constant_ranges: &[],
+    });
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some(desc.shader.name()),
+        layout: Some(&pipeline_layout),
+        vertex: wgpu::VertexState {
+            module: &shader,
+            entry_point: "vs_main",
+            buffers: desc.vertex_layouts,
+        },
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            strip_index_format: None,
+            front_face: wgpu::FrontFace::Cw,
+            cull_mode: Some(wgpu::Face::Back),
+            unclipped_depth: false,
+            polygon_mode: wgpu::PolygonMode::Fill,
+            conservative: false,
+        },
+        depth_stencil: desc.depth_format.map(|format| wgpu::DepthStencilState {
+            format,
+            depth_write_enabled: desc.depth_write,
+            // Equal depths pass so quads on the same layer keep their submission order.
+            depth_compare: match desc.stencil {
+                StencilMode::Test => wgpu::CompareFunction::LessEqual,
+                _ => wgpu::CompareFunction::Always,
+            },
+            stencil: if format.has_stencil_aspect() {
+                stencil_state(desc.stencil)
+            } else {
+                wgpu::StencilState::default()
+            },
+            bias: wgpu::DepthBiasState::default(),
+        }),
+        multisample: wgpu::MultisampleState {
+            count: desc.sample_count,
+            mask: !0,
+            alpha_to_coverage_enabled: false,
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: &shader,
+            entry_point: "fs_main",
+            targets: &[Some(wgpu::ColorTargetState {
+                format: desc.format,
+                blend: desc.blend,
+                write_mask: match desc.stencil {
+                    StencilMode::Test => wgpu::ColorWrites::ALL,
+                    _ => wgpu::ColorWrites::empty(),
+                },
+            })],
+        }),
+        multiview: None,
+    })
+}
+
+fn stencil_state(mode: StencilMode) -> wgpu::StencilState {
+    let pass_op = match mode {
+        StencilMode::Test => wgpu::StencilOperation::Keep,
+        StencilMode::Increment => wgpu::StencilOperation::IncrementClamp,
+        StencilMode::Decrement => wgpu::StencilOperation::DecrementClamp,
+    };
+    let face = wgpu::StencilFaceState {
+        compare: wgpu::CompareFunction::Equal,
+        fail_op: wgpu::StencilOperation::Keep,
+        depth_fail_op: wgpu::StencilOperation::Keep,
+        pass_op,
+    };
+    wgpu::StencilState {
+        front: face,
+        back: face,
+        read_mask: 0xff,
+        write_mask: if mode == StencilMode::Test { 0 } else { 0xff },
+    }
+}
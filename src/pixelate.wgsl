@@ -0,0 +1,16 @@
+#include "post_process"
+
+struct Pixelate {
+    // Size of the blocks in screen pixels.
+    pixel_size: f32,
+};
+
+@group(1) @binding(0)
+var<uniform> effect_uniforms: Pixelate;
+
+fn effect(in: PostInput) -> vec4<f32> {
+    let size = max(effect_uniforms.pixel_size, 1.0);
+    // The texel center nearest each block's center, so filtering doesn't blur blocks together.
+    let block = (floor(in.frag_coord / size) + 0.5) * size;
+    return sample_source((floor(block) + 0.5) * source_texel());
+}
@@ -0,0 +1,453 @@
+use std::rc::Rc;
+
+use crate::gfx::{texture_layout, Gfx, GfxRenderData};
+use crate::pipeline::PipelineDescriptor;
+use crate::reflect::LayoutError;
+use crate::shader::{Shader, ShaderError};
+use crate::texture::Texture;
+use crate::uniform::{
+    check_uniform_layout, check_uniform_size, Uniform, UniformBuffer, UniformLayoutError,
+    UniformSizeError,
+};
+
+const VIGNETTE_SHADER: &str = include_str!("vignette.wgsl");
+const GRAYSCALE_SHADER: &str = include_str!("grayscale.wgsl");
+const GAUSSIAN_BLUR_SHADER: &str = include_str!("gaussian_blur.wgsl");
+const CRT_SHADER: &str = include_str!("crt.wgsl");
+const PIXELATE_SHADER: &str = include_str!("pixelate.wgsl");
+
+// A full-screen effect applied to the rendered scene, see `Gfx::add_post_effect`. Each pass
+// draws the whole target with the previous pass's output bound to group 0.
+pub trait PostEffect: std::any::Any {
+    // Called once per frame before any pass is drawn.
+    fn prepare(&mut self, _data: &GfxRenderData) {}
+    fn passes(&self) -> usize {
+        1
+    }
+    fn render<'a, 'b>(
+        &'a self,
+        data: &'a GfxRenderData,
+        pass: usize,
+        render_pass: &mut wgpu::RenderPass<'b>,
+    ) where
+        'a: 'b;
+}
+
+// A single pass effect from WGSL source that includes "post_process", defines
+// `fn effect(in: PostInput) -> vec4<f32>` and binds uniforms matching `U` as
+// `@group(1) @binding(0) var<uniform> effect_uniforms`.
+pub struct ShaderEffect<U: Uniform> {
+    shader: Shader,
+    pipeline: Rc<wgpu::RenderPipeline>,
+    uniforms: UniformBuffer<U>,
+    value: U,
+}
+impl<U: Uniform> ShaderEffect<U> {
+    pub fn new(
+        gfx: &mut Gfx,
+        name: &str,
+        source: &str,
+        uniforms: U,
+    ) -> Result<Self, PostEffectError> {
+        let gfx = gfx.data.borrow_mut();
+        let shader = Shader::try_new(&gfx, name, source, &[]).map_err(PostEffectError::Shader)?;
+        check_uniform_size(std::mem::size_of::<U>()).map_err(PostEffectError::UniformPadding)?;
+        let binding = shader.reflection().binding("effect_uniforms");
+        let uniform_size = binding.and_then(|binding| binding.size).unwrap_or(0);
+        if let Some(binding) = binding {
+            check_uniform_layout::<U>(&binding.scalars).map_err(PostEffectError::UniformLayout)?;
+        }
+        if (uniform_size as usize) > std::mem::size_of::<U>() {
+            return Err(PostEffectError::UniformSize {
+                shader: uniform_size,
+                rust: std::mem::size_of::<U>(),
+            });
+        }
+        let buffer = UniformBuffer::new(
+            &gfx.device,
+            wgpu::ShaderStages::FRAGMENT,
+            &uniforms,
+            Some(name),
+        );
+        let pipeline =
+            Self::create_pipeline(&gfx, &shader, &buffer).map_err(PostEffectError::Layout)?;
+        Ok(Self {
+            shader,
+            pipeline,
+            uniforms: buffer,
+            value: uniforms,
+        })
+    }
+    // Uploaded at the start of the next frame.
+    pub fn set_uniforms(&mut self, uniforms: U) {
+        self.value = uniforms;
+    }
+    pub fn uniforms(&self) -> &U {
+        &self.value
+    }
+
+    fn create_pipeline(
+        data: &GfxRenderData,
+        shader: &Shader,
+        uniforms: &UniformBuffer<U>,
+    ) -> Result<Rc<wgpu::RenderPipeline>, LayoutError> {
+        let reflection = shader.reflection();
+        reflection.check_vertex_layouts("vs_main", &[])?;
+        reflection.check_bind_group_layouts(&[
+            texture_layout(true).entries(),
+            UniformBuffer::<U>::layout_builder(wgpu::ShaderStages::FRAGMENT).entries(),
+        ])?;
+        let bind_group_layouts = [data.texture_bind_group_layout(true), uniforms.layout()];
+        // Post passes draw into color-only targets.
+        let desc = PipelineDescriptor {
+            depth_format: None,
+            depth_write: false,
+            ..PipelineDescriptor::new(data, shader, &[], &bind_group_layouts)
+        };
+        Ok(data.pipelines.get(&data.device, &desc))
+    }
+}
+impl<U: Uniform> PostEffect for ShaderEffect<U> {
+    fn prepare(&mut self, data: &GfxRenderData) {
+        if self.shader.poll(data) {
+            match Self::create_pipeline(data, &self.shader, &self.uniforms) {
+                Ok(pipeline) => self.pipeline = pipeline,
+                Err(err) => eprintln!("{}: {}", self.shader.name(), err),
+            }
+        }
+        self.uniforms.set(&data.queue, &self.value);
+    }
+    fn render<'a, 'b>(
+        &'a self,
+        _data: &'a GfxRenderData,
+        _pass: usize,
+        render_pass: &mut wgpu::RenderPass<'b>,
+    ) where
+        'a: 'b,
+    {
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(1, self.uniforms.bind_group(), &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct Vignette {
+    pub strength: f32,
+    // Distance from the center, with the corners at 1, where darkening starts.
+    pub radius: f32,
+}
+crate::impl_uniform!(Vignette { strength, radius });
+impl ShaderEffect<Vignette> {
+    pub fn vignette(gfx: &mut Gfx, vignette: Vignette) -> Self {
+        Self::new(gfx, "vignette.wgsl", VIGNETTE_SHADER, vignette)
+            .unwrap_or_else(|err| panic!("vignette.wgsl: {}", err))
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct Grayscale {
+    // 0 leaves colors unchanged, 1 is fully gray.
+    pub amount: f32,
+}
+crate::impl_uniform!(Grayscale { amount });
+impl ShaderEffect<Grayscale> {
+    pub fn grayscale(gfx: &mut Gfx, grayscale: Grayscale) -> Self {
+        Self::new(gfx, "grayscale.wgsl", GRAYSCALE_SHADER, grayscale)
+            .unwrap_or_else(|err| panic!("grayscale.wgsl: {}", err))
+    }
+}
+
+// Curvature, chromatic aberration, scanlines and a phosphor mask in a single pass. Each part
+// is disabled by setting its amount to 0.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct Crt {
+    // Barrel distortion, 0 keeps the image flat.
+    pub curvature: f32,
+    // How dark the gaps between scanlines get, from 0 to 1.
+    pub scanline_intensity: f32,
+    // Scanlines over the height of the screen. 0 darkens every other pixel row, ignoring
+    // curvature, the finest scanlines that don't vanish between pixel centers.
+    pub scanline_count: f32,
+    // Red and blue offset at the edges of the screen, in pixels.
+    pub chromatic_aberration: f32,
+    // How dark the other two colors of each phosphor column get, from 0 to 1.
+    pub mask_intensity: f32,
+    // Width of one phosphor column in pixels.
+    pub mask_size: f32,
+    // Multiplies the result to make up for the darkening.
+    pub brightness: f32,
+    // Rounding of the screen's corners in uv units.
+    pub corner_radius: f32,
+}
+impl Default for Crt {
+    fn default() -> Self {
+        Self {
+            curvature: 0.1,
+            scanline_intensity: 0.3,
+            scanline_count: 240.0,
+            chromatic_aberration: 1.0,
+            mask_intensity: 0.2,
+            mask_size: 1.0,
+            brightness: 1.2,
+            corner_radius: 0.03,
+        }
+    }
+}
+crate::impl_uniform!(Crt {
+    curvature,
+    scanline_intensity,
+    scanline_count,
+    chromatic_aberration,
+    mask_intensity,
+    mask_size,
+    brightness,
+    corner_radius,
+});
+impl ShaderEffect<Crt> {
+    pub fn crt(gfx: &mut Gfx, crt: Crt) -> Self {
+        Self::new(gfx, "crt.wgsl", CRT_SHADER, crt)
+            .unwrap_or_else(|err| panic!("crt.wgsl: {}", err))
+    }
+}
+
+// Draws the screen as blocks of a single color. Add it before `Crt` so the scanlines and mask
+// stay at full resolution.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct Pixelate {
+    // Size of the blocks in screen pixels.
+    pub pixel_size: f32,
+}
+crate::impl_uniform!(Pixelate { pixel_size });
+impl ShaderEffect<Pixelate> {
+    pub fn pixelate(gfx: &mut Gfx, pixelate: Pixelate) -> Self {
+        Self::new(gfx, "pixelate.wgsl", PIXELATE_SHADER, pixelate)
+            .unwrap_or_else(|err| panic!("pixelate.wgsl: {}", err))
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct BlurUniforms {
+    direction: [f32; 2],
+    sigma: f32,
+    _padding: f32,
+}
+crate::impl_uniform!(BlurUniforms { direction, sigma });
+
+// Separable blur in a horizontal and a vertical pass. `sigma` is in pixels and the kernel is
+// cut off at 32 pixels either side.
+pub struct GaussianBlur {
+    horizontal: ShaderEffect<BlurUniforms>,
+    vertical: ShaderEffect<BlurUniforms>,
+}
+impl GaussianBlur {
+    pub fn new(gfx: &mut Gfx, sigma: f32) -> Self {
+        let mut pass = |direction| {
+            let uniforms = BlurUniforms {
+                direction,
+                sigma,
+                _padding: 0.0,
+            };
+            ShaderEffect::new(gfx, "gaussian_blur.wgsl", GAUSSIAN_BLUR_SHADER, uniforms)
+                .unwrap_or_else(|err| panic!("gaussian_blur.wgsl: {}", err))
+        };
+        Self {
+            horizontal: pass([1.0, 0.0]),
+            vertical: pass([0.0, 1.0]),
+        }
+    }
+    pub fn sigma(&self) -> f32 {
+        self.horizontal.uniforms().sigma
+    }
+    pub fn set_sigma(&mut self, sigma: f32) {
+        for pass in [&mut self.horizontal, &mut self.vertical] {
+            pass.set_uniforms(BlurUniforms {
+                sigma,
+                ..*pass.uniforms()
+            });
+        }
+    }
+}
+impl PostEffect for GaussianBlur {
+    fn prepare(&mut self, data: &GfxRenderData) {
+        self.horizontal.prepare(data);
+        self.vertical.prepare(data);
+    }
+    fn passes(&self) -> usize {
+        2
+    }
+    fn render<'a, 'b>(
+        &'a self,
+        data: &'a GfxRenderData,
+        pass: usize,
+        render_pass: &mut wgpu::RenderPass<'b>,
+    ) where
+        'a: 'b,
+    {
+        let effect = if pass == 0 {
+            &self.horizontal
+        } else {
+            &self.vertical
+        };
+        effect.render(data, 0, render_pass);
+    }
+}
+
+#[derive(Debug)]
+pub enum PostEffectError {
+    Shader(ShaderError),
+    Layout(LayoutError),
+    UniformSize { shader: u32, rust: usize },
+    UniformPadding(UniformSizeError),
+    UniformLayout(UniformLayoutError),
+}
+impl std::fmt::Display for PostEffectError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Shader(err) => write!(f, "{}\n{}", err, err.report),
+            Self::Layout(err) => write!(f, "{}", err),
+            Self::UniformSize { shader, rust } => write!(
+                f,
+                "effect_uniforms is {} bytes in WGSL but the Rust type is {} bytes",
+                shader, rust
+            ),
+            Self::UniformPadding(err) => write!(f, "effect_uniforms: {}", err),
+            Self::UniformLayout(err) => write!(f, "effect_uniforms: {}", err),
+        }
+    }
+}
+impl std::error::Error for PostEffectError {}
+
+// The effects added to `Gfx`, run in order, and the two screen sized textures passes read
+// from and draw into in turn. The scene is drawn into the first and the last pass draws into
+// the surface.
+#[derive(Default)]
+pub(crate) struct PostChain {
+    effects: Vec<Box<dyn PostEffect>>,
+    targets: Vec<Texture>,
+}
+impl PostChain {
+    pub(crate) fn add(&mut self, effect: Box<dyn PostEffect>) -> usize {
+        self.effects.push(effect);
+        self.effects.len() - 1
+    }
+    pub(crate) fn effect_mut<T: PostEffect>(&mut self, index: usize) -> Option<&mut T> {
+        let effect: &mut dyn std::any::Any = self.effects.get_mut(index)?.as_mut();
+        effect.downcast_mut()
+    }
+    pub(crate) fn is_empty(&self) -> bool {
+        self.effects.iter().all(|effect| effect.passes() == 0)
+    }
+    // Prepares every effect and returns the texture to draw the scene into. The targets are
+    // reallocated whenever the screen size changed.
+    pub(crate) fn prepare(&mut self, data: &GfxRenderData) -> &Texture {
+        for effect in self.effects.iter_mut() {
+            effect.prepare(data);
+        }
+        let size = (data.size.width, data.size.height);
+        if self.targets.first().map(|target| target.size()) != Some(size) {
+            self.targets = (0..2)
+                .map(|_| Texture::render_target(data, size.0, size.1, data.format))
+                .collect();
+        }
+        &self.targets[0]
+    }
+    // Without an `output` the last pass draws into a target as well, which is returned.
+    pub(crate) fn render(
+        &self,
+        data: &GfxRenderData,
+        encoder: &mut wgpu::CommandEncoder,
+        output: Option<&wgpu::TextureView>,
+    ) -> &Texture {
+        let passes = self
+            .effects
+            .iter()
+            .flat_map(|effect| (0..effect.passes()).map(move |pass| (effect.as_ref(), pass)))
+            .collect::<Vec<_>>();
+        for (i, (effect, pass)) in passes.iter().enumerate() {
+            let target = match output {
+                Some(output) if i + 1 == passes.len() => output,
+                _ => &self.targets[(i + 1) % 2].view,
+            };
+            let mut render_pass =
+                begin_pass(encoder, target, wgpu::LoadOp::Clear(wgpu::Color::BLACK));
+            render_pass.set_bind_group(0, &self.targets[i % 2].bind_group, &[]);
+            effect.render(data, *pass, &mut render_pass);
+        }
+        &self.targets[passes.len() % 2]
+    }
+}
+
+// A pass drawing into `target` only, for full-screen passes.
+pub(crate) fn begin_pass<'a>(
+    encoder: &'a mut wgpu::CommandEncoder,
+    target: &'a wgpu::TextureView,
+    load: wgpu::LoadOp<wgpu::Color>,
+) -> wgpu::RenderPass<'a> {
+    encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some("full_screen"),
+        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+            view: target,
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load,
+                store: wgpu::StoreOp::Store,
+            },
+        })],
+        depth_stencil_attachment: None,
+        timestamp_writes: None,
+        occlusion_query_set: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reflect::check_shader;
+
+    #[test]
+    fn built_in_effects_match_layouts() {
+        type Check = fn(&[u32]) -> Result<(), UniformLayoutError>;
+        let effects: [(&str, &str, usize, Check); 5] = [
+            (
+                "vignette.wgsl",
+                VIGNETTE_SHADER,
+                std::mem::size_of::<Vignette>(),
+                check_uniform_layout::<Vignette>,
+            ),
+            (
+                "grayscale.wgsl",
+                GRAYSCALE_SHADER,
+                std::mem::size_of::<Grayscale>(),
+                check_uniform_layout::<Grayscale>,
+            ),
+            (
+                "gaussian_blur.wgsl",
+                GAUSSIAN_BLUR_SHADER,
+                std::mem::size_of::<BlurUniforms>(),
+                check_uniform_layout::<BlurUniforms>,
+            ),
+            ("crt.wgsl", CRT_SHADER, std::mem::size_of::<Crt>(), check_uniform_layout::<Crt>),
+            (
+                "pixelate.wgsl",
+                PIXELATE_SHADER,
+                std::mem::size_of::<Pixelate>(),
+                check_uniform_layout::<Pixelate>,
+            ),
+        ];
+        let uniforms = UniformBuffer::<[f32; 4]>::layout_builder(wgpu::ShaderStages::FRAGMENT);
+        let texture = texture_layout(true);
+        let groups = [texture.entries(), uniforms.entries()];
+        for (name, source, size, check_layout) in effects {
+            let reflection = check_shader(name, source, &[], &[], &groups);
+            let binding = reflection.binding("effect_uniforms").unwrap();
+            assert!(binding.size.unwrap() as usize <= size, "{}", name);
+            check_layout(&binding.scalars).unwrap_or_else(|err| panic!("{}: {}", name, err));
+        }
+    }
+}
@@ -0,0 +1,49 @@
+// Shared by full-screen post effects. Including shaders define
+// `fn effect(in: PostInput) -> vec4<f32>`, and usually their uniforms as
+// `@group(1) @binding(0) var<uniform> effect_uniforms: ...`.
+
+// Output of the previous pass, or the scene for the first pass.
+@group(0) @binding(0)
+var source: texture_2d<f32>;
+@group(0) @binding(1)
+var source_sampler: sampler;
+
+struct PostVertex {
+    @builtin(position) pos: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+};
+
+struct PostInput {
+    uv: vec2<f32>,
+    // Framebuffer position in pixels.
+    frag_coord: vec2<f32>,
+};
+
+fn sample_source(uv: vec2<f32>) -> vec4<f32> {
+    return textureSample(source, source_sampler, uv);
+}
+
+// Size of one source texel in uv units.
+fn source_texel() -> vec2<f32> {
+    return 1.0 / vec2<f32>(textureDimensions(source));
+}
+
+// One clockwise triangle covering the whole target.
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> PostVertex {
+    var positions = array<vec2<f32>, 3>(
+        vec2<f32>(-1.0, -1.0),
+        vec2<f32>(-1.0, 3.0),
+        vec2<f32>(3.0, -1.0),
+    );
+    let pos = positions[index];
+    var out: PostVertex;
+    out.pos = vec4<f32>(pos, 0.0, 1.0);
+    out.uv = vec2<f32>(pos.x * 0.5 + 0.5, 0.5 - pos.y * 0.5);
+    return out;
+}
+
+@fragment
+fn fs_main(vin: PostVertex) -> @location(0) vec4<f32> {
+    return effect(PostInput(vin.uv, vin.pos.xy));
+}
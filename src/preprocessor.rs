@@ -0,0 +1,253 @@
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+
+// Supports `#include "module"`, `#define NAME [value]`, `#undef NAME`, `#ifdef NAME`,
+// `#ifndef NAME`, `#else` and `#endif`. Defined values are substituted for whole identifiers
+// and every module is included at most once.
+#[derive(Default)]
+pub struct Preprocessor {
+    modules: HashMap<String, String>,
+    // Modules are read from `<name>.wgsl` in this directory when the file exists, ahead of the
+    // registered source, so they can be hot reloaded.
+    module_dir: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PreprocessError {
+    pub file: String,
+    pub line: u32,
+    pub message: String,
+}
+impl std::fmt::Display for PreprocessError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}: {}", self.file, self.line, self.message)
+    }
+}
+impl std::error::Error for PreprocessError {}
+
+pub struct Preprocessed {
+    pub code: String,
+    // The file and 1-based line each output line came from.
+    origins: Vec<(String, u32)>,
+    files: Vec<PathBuf>,
+}
+impl Preprocessed {
+    // Module files read from the module directory.
+    pub fn files(&self) -> &[PathBuf] {
+        &self.files
+    }
+    pub fn origin(&self, line: u32) -> Option<(&str, u32)> {
+        let (file, line) = self.origins.get(line.checked_sub(1)? as usize)?;
+        Some((file, *line))
+    }
+}
+
+struct State {
+    defines: HashMap<String, String>,
+    included: HashSet<String>,
+    stack: Vec<String>,
+    output: Preprocessed,
+}
+
+struct Conditional {
+    active: bool,
+    parent_active: bool,
+    seen_else: bool,
+}
+
+impl Preprocessor {
+    // Creates a preprocessor with the modules shared by the built-in shaders registered.
+    pub fn new() -> Self {
+        let mut preprocessor = Self::default();
+        preprocessor.register_module("quad_transform", include_str!("quad_transform.wgsl"));
+        preprocessor.register_module("post_process", include_str!("post_process.wgsl"));
+        preprocessor.register_module("lighting", include_str!("lighting.wgsl"));
+        preprocessor
+    }
+    pub fn register_module(&mut self, name: impl Into<String>, source: impl Into<String>) {
+        self.modules.insert(name.into(), source.into());
+    }
+    pub fn set_module_dir(&mut self, dir: Option<PathBuf>) {
+        self.module_dir = dir;
+    }
+    // The source of module `name` and the file it was read from, if any.
+    fn module(&self, name: &str) -> Result<(Cow<'_, str>, Option<PathBuf>), String> {
+        let path = self.module_dir.as_ref().map(|dir| dir.join(format!("{}.wgsl", name)));
+        if let Some(path) = path.filter(|path| path.exists()) {
+            return match std::fs::read_to_string(&path) {
+                Ok(source) => Ok((Cow::Owned(source), Some(path))),
+                Err(err) => Err(format!("failed to read {}: {}", path.display(), err)),
+            };
+        }
+        match self.modules.get(name) {
+            Some(source) => Ok((Cow::Borrowed(source.as_str()), None)),
+            None => Err(format!("unknown module \"{}\"", name)),
+        }
+    }
+    pub fn process(
+        &self,
+        file: &str,
+        source: &str,
+        defines: &[(&str, &str)],
+    ) -> Result<Preprocessed, PreprocessError> {
+        let mut state = State {
+            defines: defines
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+            included: HashSet::new(),
+            stack: vec![],
+            output: Preprocessed {
+                code: String::new(),
+                origins: vec![],
+                files: vec![],
+            },
+        };
+        self.process_file(file, source, &mut state)?;
+        Ok(state.output)
+    }
+
+    fn process_file(
+        &self,
+        file: &str,
+        source: &str,
+        state: &mut State,
+    ) -> Result<(), PreprocessError> {
+        state.stack.push(file.to_string());
+        let mut conditionals: Vec<Conditional> = vec![];
+        let mut last_line = 0;
+        for (i, line) in source.lines().enumerate() {
+            let line_number = i as u32 + 1;
+            last_line = line_number;
+            let error = |message: String| PreprocessError {
+                file: file.to_string(),
+                line: line_number,
+                message,
+            };
+            let active = conditionals.last().is_none_or(|c| c.active);
+            let trimmed = line.trim();
+            let Some(directive) = trimmed.strip_prefix('#') else {
+                if active {
+                    state.output.code.push_str(&substitute(line, &state.defines));
+                    state.output.code.push('\n');
+                    state.output.origins.push((file.to_string(), line_number));
+                }
+                continue;
+            };
+            let (keyword, rest) = directive
+                .split_once(char::is_whitespace)
+                .map_or((directive, ""), |(keyword, rest)| (keyword, rest.trim()));
+            match keyword {
+                "ifdef" | "ifndef" => {
+                    let name = identifier(rest)
+                        .ok_or_else(|| error(format!("#{} needs a name", keyword)))?;
+                    let defined = state.defines.contains_key(name);
+                    conditionals.push(Conditional {
+                        active: active && (defined == (keyword == "ifdef")),
+                        parent_active: active,
+                        seen_else: false,
+                    });
+                }
+                "else" => {
+                    let conditional = conditionals
+                        .last_mut()
+                        .filter(|c| !c.seen_else)
+                        .ok_or_else(|| error("#else without #ifdef".to_string()))?;
+                    conditional.seen_else = true;
+                    conditional.active = conditional.parent_active && !conditional.active;
+                }
+                "endif" => {
+                    conditionals
+                        .pop()
+                        .ok_or_else(|| error("#endif without #ifdef".to_string()))?;
+                }
+                _ if !active => (),
+                "define" => {
+                    let (name, value) = rest
+                        .split_once(char::is_whitespace)
+                        .map_or((rest, ""), |(name, value)| (name, value.trim()));
+                    let name = identifier(name)
+                        .ok_or_else(|| error("#define needs a name".to_string()))?;
+                    state.defines.insert(name.to_string(), value.to_string());
+                }
+                "undef" => {
+                    let name = identifier(rest)
+                        .ok_or_else(|| error("#undef needs a name".to_string()))?;
+                    state.defines.remove(name);
+                }
+                "include" => {
+                    let name = rest.trim_matches('"');
+                    if state.stack.iter().any(|file| file == name) {
+                        return Err(error(format!("\"{}\" includes itself", name)));
+                    }
+                    if state.included.insert(name.to_string()) {
+                        let (module, path) = self.module(name).map_err(&error)?;
+                        state.output.files.extend(path);
+                        self.process_file(name, &module, state)?;
+                    }
+                }
+                _ => return Err(error(format!("unknown directive #{}", keyword))),
+            }
+        }
+        if !conditionals.is_empty() {
+            return Err(PreprocessError {
+                file: file.to_string(),
+                line: last_line,
+                message: "missing #endif".to_string(),
+            });
+        }
+        state.stack.pop();
+        Ok(())
+    }
+}
+
+fn identifier(name: &str) -> Option<&str> {
+    let valid = !name.is_empty()
+        && !name.starts_with(|c: char| c.is_ascii_digit())
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    valid.then_some(name)
+}
+
+fn substitute(line: &str, defines: &HashMap<String, String>) -> String {
+    let mut output = String::with_capacity(line.len());
+    let mut word = String::new();
+    let flush = |word: &mut String, output: &mut String| {
+        match defines.get(word.as_str()) {
+            Some(value) if !value.is_empty() => output.push_str(value),
+            _ => output.push_str(word),
+        }
+        word.clear();
+    };
+    for c in line.chars() {
+        if c.is_ascii_alphanumeric() || c == '_' {
+            word.push(c);
+        } else {
+            flush(&mut word, &mut output);
+            output.push(c);
+        }
+    }
+    flush(&mut word, &mut output);
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_modules_from_module_dir_first() {
+        let dir = std::env::temp_dir().join(format!("preprocessor-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("on_disk.wgsl"), "const A: f32 = 2.0;").unwrap();
+        let mut preprocessor = Preprocessor::default();
+        preprocessor.register_module("on_disk", "const A: f32 = 1.0;");
+        preprocessor.register_module("embedded", "const B: f32 = 1.0;");
+        preprocessor.set_module_dir(Some(dir.clone()));
+        let source = "#include \"on_disk\"\n#include \"embedded\"\n";
+        let output = preprocessor.process("main.wgsl", source, &[]).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(output.code, "const A: f32 = 2.0;\nconst B: f32 = 1.0;\n");
+        assert_eq!(output.files(), [dir.join("on_disk.wgsl")]);
+    }
+}
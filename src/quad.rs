@@ -1,8 +1,19 @@
+use std::rc::Rc;
 use wgpu::util::DeviceExt;
+use crate::clip::Rect;
 use crate::color::Color;
-use crate::gfx::{ Gfx, GfxRenderData, Renderer };
+use crate::gfx::{ view_layout, texture_layout, Gfx, GfxRenderData, Renderer };
+use crate::bind_group::BindGroupLayoutBuilder;
+use crate::lighting::lighting_layout;
+use crate::pipeline::PipelineDescriptor;
+use crate::reflect::{LayoutError, ShaderReflection};
+use crate::shader::Shader;
 use crate::vertex::Vertex;
-use crate::texture::Texture;
+use crate::texture::{Texture, TextureHandle};
+use crate::texture_array::{BindlessTextures, TextureArray, TextureStorage};
+
+pub(crate) const FILL_QUAD_SHADER: &str = include_str!("fill_quad.wgsl");
+pub(crate) const TEXTURED_QUAD_SHADER: &str = include_str!("textured_quad.wgsl");
 
 const VERTICES: [Vertex; 4] = [
     // Top-Left
@@ -28,146 +39,330 @@ const VERTICES: [Vertex; 4] = [
 ];
 const INDICES: [u16; 6] = [0, 1, 2, 0, 2, 3];
 
+// The unit quad every quad renderer draws instances of, created once on `Gfx`.
+pub struct QuadGeometry {
+    pub vertex_buffer: wgpu::Buffer,
+    pub index_buffer: wgpu::Buffer,
+}
+impl QuadGeometry {
+    pub(crate) fn new(device: &wgpu::Device) -> Self {
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("quad_vertices"),
+            usage: wgpu::BufferUsages::VERTEX,
+            contents: bytemuck::cast_slice(&VERTICES),
+        });
+        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("quad_indices"),
+            usage: wgpu::BufferUsages::INDEX,
+            contents: bytemuck::cast_slice(&INDICES),
+        });
+        Self {
+            vertex_buffer,
+            index_buffer,
+        }
+    }
+    // Binds the quad to vertex buffer slot 0 and the index buffer.
+    pub fn set_buffers<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+    }
+    pub fn index_count(&self) -> u32 {
+        INDICES.len() as u32
+    }
+}
+
+// A CSS style `box-shadow` drawn under a quad, in world units.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoxShadow {
+    pub offset: [f32; 2],
+    // Blur radius, twice the standard deviation of the Gaussian, as in CSS.
+    pub blur: f32,
+    // Grows the shadow on every side before blurring. Negative values shrink it.
+    pub spread: f32,
+    pub color: Color,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Quad {
     pub pos: [f32; 2],
+    // Layer in 0..1, higher layers in front. Only used with a depth buffer, otherwise quads
+    // are drawn in the order they were added.
+    pub z: f32,
     pub width: f32,
     pub height: f32,
+    // Quads with alpha below 1 are blended.
     pub color: Color,
+    // Blocks light from lights with shadows, in the world rect the quad covers.
+    pub casts_shadow: bool,
+    // Drawn in the same instance as the quad, which makes the quad blended.
+    pub box_shadow: Option<BoxShadow>,
 }
 
 #[repr(C)]
 #[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
-struct QuadRaw {
-    pos: [f32; 2],
+pub(crate) struct QuadRaw {
+    pos: [f32; 3],
     size: [f32; 2],
     color: [f32; 4],
+    // Offset, blur and spread, with a transparent color when there is no shadow.
+    shadow: [f32; 4],
+    shadow_color: [f32; 4],
+}
+impl QuadRaw {
+    pub(crate) fn layout() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<QuadRaw>() as u64,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &[
+                wgpu::VertexAttribute {
+                    format: wgpu::VertexFormat::Float32x3,
+                    offset: 0,
+                    shader_location: 4,
+                },
+                wgpu::VertexAttribute {
+                    format: wgpu::VertexFormat::Float32x2,
+                    offset: std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
+                    shader_location: 5,
+                },
+                wgpu::VertexAttribute {
+                    format: wgpu::VertexFormat::Float32x4,
+                    offset: std::mem::size_of::<[f32; 5]>() as wgpu::BufferAddress,
+                    shader_location: 6,
+                },
+                wgpu::VertexAttribute {
+                    format: wgpu::VertexFormat::Float32x4,
+                    offset: std::mem::size_of::<[f32; 9]>() as wgpu::BufferAddress,
+                    shader_location: 8,
+                },
+                wgpu::VertexAttribute {
+                    format: wgpu::VertexFormat::Float32x4,
+                    offset: std::mem::size_of::<[f32; 13]>() as wgpu::BufferAddress,
+                    shader_location: 9,
+                },
+            ],
+        }
+    }
+}
+impl Quad {
+    fn is_opaque(&self) -> bool {
+        self.color.a >= 1.0 && self.box_shadow.is_none()
+    }
+    // The world rect covered, `pos` being the center.
+    pub fn rect(&self) -> Rect {
+        Rect::new(
+            self.pos[0] - self.width * 0.5,
+            self.pos[1] - self.height * 0.5,
+            self.width,
+            self.height,
+        )
+    }
 }
 impl From<&Quad> for QuadRaw {
     fn from(quad: &Quad) -> Self {
+        let (shadow, shadow_color) = match quad.box_shadow {
+            Some(BoxShadow {
+                offset: [x, y],
+                blur,
+                spread,
+                color,
+            }) => ([x, y, blur.max(0.0), spread], color),
+            None => ([0.0; 4], Color::TRANSPARENT),
+        };
         Self {
-            pos: quad.pos,
+            pos: [quad.pos[0], quad.pos[1], quad.z],
             size: [quad.width, quad.height],
             color: [quad.color.r, quad.color.g, quad.color.b, quad.color.a],
+            shadow,
+            shadow_color: [shadow_color.r, shadow_color.g, shadow_color.b, shadow_color.a],
         }
     }
 }
 
+// Opaque and transparent variants of the same pipeline.
+pub(crate) struct PipelinePair {
+    opaque: Rc<wgpu::RenderPipeline>,
+    transparent: Rc<wgpu::RenderPipeline>,
+}
+impl PipelinePair {
+    pub(crate) fn new(gfx: &GfxRenderData, desc: PipelineDescriptor) -> Self {
+        let opaque = gfx.pipelines.get(&gfx.device, &desc);
+        let transparent = gfx.pipelines.get(&gfx.device, &desc.transparent());
+        Self {
+            opaque,
+            transparent,
+        }
+    }
+    pub(crate) fn get(&self, transparent: bool) -> &wgpu::RenderPipeline {
+        if transparent {
+            &self.transparent
+        } else {
+            &self.opaque
+        }
+    }
+}
+
+pub(crate) fn instance_buffer(
+    device: &wgpu::Device,
+    label: &str,
+    size: wgpu::BufferAddress,
+) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some(label),
+        size: size.max(256),
+        usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+// Grows the buffer to the next power of two when `bytes` don't fit.
+pub(crate) fn write_instances(
+    data: &GfxRenderData,
+    buffer: &mut wgpu::Buffer,
+    label: &str,
+    bytes: &[u8],
+) {
+    let size = bytes.len() as wgpu::BufferAddress;
+    if size > buffer.size() {
+        *buffer = instance_buffer(&data.device, label, size.next_power_of_two());
+    }
+    data.queue.write_buffer(buffer, 0, bytes);
+}
+
+// With a depth buffer, puts opaque items first, followed by translucent ones from back to
+// front. Items on the same layer keep their order. Returns the number of opaque items. Without
+// one everything is blended in submission order, so items are left as they are and none count
+// as opaque.
+pub(crate) fn sort_for_depth<T>(
+    items: &mut [T],
+    depth: bool,
+    is_opaque: impl Fn(&T) -> bool,
+    z: impl Fn(&T) -> f32,
+) -> usize {
+    if !depth {
+        return 0;
+    }
+    items.sort_by(|a, b| match (is_opaque(a), is_opaque(b)) {
+        (true, true) => std::cmp::Ordering::Equal,
+        (true, false) => std::cmp::Ordering::Less,
+        (false, true) => std::cmp::Ordering::Greater,
+        (false, false) => z(a).total_cmp(&z(b)),
+    });
+    items.iter().take_while(|item| is_opaque(item)).count()
+}
+
 pub struct QuadRenderer {
-    vertex_buffer: wgpu::Buffer,
-    index_buffer: wgpu::Buffer,
     instance_buffer: wgpu::Buffer,
-    pipeline: wgpu::RenderPipeline,
+    pipelines: PipelinePair,
+    shader: Shader,
+    lit: bool,
     quads: Vec<Quad>,
+    // Opaque instances come first in the instance buffer.
+    opaque_count: u32,
 }
 impl QuadRenderer {
     pub fn new(gfx: &mut Gfx) -> Self {
         let gfx = gfx.data.borrow_mut();
-        let vertex_buffer = gfx
-            .device
-            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                label: None,
-                usage: wgpu::BufferUsages::VERTEX,
-                contents: bytemuck::cast_slice(&VERTICES),
-            });
-        let index_buffer = gfx
-            .device
-            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                label: None,
-                usage: wgpu::BufferUsages::INDEX,
-                contents: bytemuck::cast_slice(&INDICES),
-            });
-        let instance_buffer = gfx.device.create_buffer(&wgpu::BufferDescriptor {
-            label: None,
-            size: (std::mem::size_of::<QuadRaw>() * 128) as wgpu::BufferAddress,
-            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
-            mapped_at_creation: false,
-        });
-        let shader = gfx
-            .device
-            .create_shader_module(wgpu::include_wgsl!("fill_quad.wgsl"));
-        let pipeline_layout = gfx
-            .device
-            .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                label: None,
-                bind_group_layouts: &[
-                    &gfx.aspect_ratio_bind_group_layout,
-                ],
-                push_constant_ranges: &[],
-            });
-        let pipeline = gfx
-            .device
-            .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-                label: None,
-                layout: Some(&pipeline_layout),
-                vertex: wgpu::VertexState {
-                    module: &shader,
-                    entry_point: "vs_main",
-                    buffers: &[
-                        // Vertex buffer stuff
-                        Vertex::layout(),
-                        // Instance buffer stuff
-                        wgpu::VertexBufferLayout {
-                            array_stride: std::mem::size_of::<Quad>() as u64,
-                            step_mode: wgpu::VertexStepMode::Instance,
-                            attributes: &[
-                                wgpu::VertexAttribute {
-                                    format: wgpu::VertexFormat::Float32x2,
-                                    offset: 0,
-                                    shader_location: 4,
-                                },
-                                wgpu::VertexAttribute {
-                                    format: wgpu::VertexFormat::Float32x2,
-                                    offset: std::mem::size_of::<[f32; 2]>() as wgpu::BufferAddress,
-                                    shader_location: 5,
-                                },
-                                wgpu::VertexAttribute {
-                                    format: wgpu::VertexFormat::Float32x4,
-                                    offset: std::mem::size_of::<[f32; 4]>() as wgpu::BufferAddress,
-                                    shader_location: 6,
-                                },
-                            ],
-                        },
-                    ],
-                },
-                primitive: wgpu::PrimitiveState {
-                    topology: wgpu::PrimitiveTopology::TriangleList,
-                    strip_index_format: None,
-                    front_face: wgpu::FrontFace::Cw,
-                    cull_mode: Some(wgpu::Face::Back),
-                    unclipped_depth: false,
-                    polygon_mode: wgpu::PolygonMode::Fill,
-                    conservative: false,
-                },
-                depth_stencil: None,
-                multisample: wgpu::MultisampleState {
-                    count: 1,
-                    mask: !0,
-                    alpha_to_coverage_enabled: false,
-                },
-                fragment: Some(wgpu::FragmentState {
-                    module: &shader,
-                    entry_point: "fs_main",
-                    targets: &[Some(wgpu::ColorTargetState {
-                        format: wgpu::TextureFormat::Bgra8Unorm,
-                        blend: Some(wgpu::BlendState::REPLACE),
-                        write_mask: wgpu::ColorWrites::ALL,
-                    })],
-                }),
-                multiview: None,
-            });
+        let instance_buffer = instance_buffer(&gfx.device, "quad_instances", 0);
+        let shader = Shader::new(&gfx, "fill_quad.wgsl", FILL_QUAD_SHADER, &[]);
+        let pipelines = Self::create_pipelines(&gfx, &shader)
+            .unwrap_or_else(|err| panic!("{}: {}", shader.name(), err));
         Self {
-            vertex_buffer,
-            index_buffer,
             instance_buffer,
-            pipeline,
+            pipelines,
+            shader,
+            lit: false,
             quads: vec![],
+            opaque_count: 0,
         }
     }
+    // Shades quads with the lights from `Gfx::lighting_mut` as flat surfaces facing the
+    // viewer.
+    pub fn with_lighting(mut self, gfx: &mut Gfx) -> Self {
+        let data = gfx.data.borrow();
+        let defines = [("LIGHTING", "")];
+        self.shader = Shader::new(&data, "fill_quad.wgsl", FILL_QUAD_SHADER, &defines);
+        self.pipelines = Self::create_pipelines_with(&data, &self.shader, true, PipelinePair::new)
+            .unwrap_or_else(|err| panic!("{}: {}", self.shader.name(), err));
+        self.lit = true;
+        self
+    }
     pub fn add(&mut self, quad: Quad) {
         self.quads.push(quad);
     }
+
+    pub(crate) fn create_pipelines(
+        gfx: &GfxRenderData,
+        shader: &Shader,
+    ) -> Result<PipelinePair, LayoutError> {
+        Self::create_pipelines_with(gfx, shader, false, PipelinePair::new)
+    }
+    // Checks the shader against the quad layouts, with the light layout in group 1 if `lit`,
+    // and hands the descriptor to `build`.
+    pub(crate) fn create_pipelines_with<P>(
+        gfx: &GfxRenderData,
+        shader: &Shader,
+        lit: bool,
+        build: impl Fn(&GfxRenderData, PipelineDescriptor) -> P,
+    ) -> Result<P, LayoutError> {
+        let vertex_layouts = [Vertex::layout(), QuadRaw::layout()];
+        let reflection = shader.reflection();
+        reflection.check_vertex_layouts("vs_main", &vertex_layouts)?;
+        let mut groups = vec![view_layout()];
+        let mut bind_group_layouts = vec![gfx.view.layout()];
+        if lit {
+            groups.push(lighting_layout());
+            bind_group_layouts.push(gfx.lights.layout());
+        }
+        let entries = groups.iter().map(|group| group.entries()).collect::<Vec<_>>();
+        reflection.check_bind_group_layouts(&entries)?;
+        let desc = PipelineDescriptor::new(gfx, shader, &vertex_layouts, &bind_group_layouts);
+        Ok(build(gfx, desc))
+    }
+    fn draw<'a, 'b>(
+        &'a self,
+        data: &'a GfxRenderData,
+        render_pass: &mut wgpu::RenderPass<'b>,
+        instances: std::ops::Range<u32>,
+        transparent: bool,
+    ) where
+        'a: 'b,
+    {
+        if instances.is_empty() {
+            return;
+        }
+        render_pass.set_pipeline(self.pipelines.get(transparent));
+        data.quad_geometry.set_buffers(render_pass);
+        render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
+        render_pass.set_bind_group(0, data.view.bind_group(), &[]);
+        if self.lit {
+            render_pass.set_bind_group(1, data.lights.bind_group(), &[]);
+        }
+        render_pass.draw_indexed(0..data.quad_geometry.index_count(), 0, instances);
+    }
 }
 impl Renderer for QuadRenderer {
+    fn occluders(&self, occluders: &mut Vec<Rect>) {
+        let quads = self.quads.iter().filter(|quad| quad.casts_shadow);
+        occluders.extend(quads.map(Quad::rect));
+    }
+    fn prepare(&mut self, data: &GfxRenderData) {
+        if self.shader.poll(data) {
+            match Self::create_pipelines_with(data, &self.shader, self.lit, PipelinePair::new) {
+                Ok(pipelines) => self.pipelines = pipelines,
+                Err(err) => eprintln!("{}: {}", self.shader.name(), err),
+            }
+        }
+        let mut quads = self.quads.iter().collect::<Vec<_>>();
+        let depth = data.depth.is_some();
+        self.opaque_count =
+            sort_for_depth(&mut quads, depth, |quad| quad.is_opaque(), |quad| quad.z) as u32;
+        let instances = quads
+            .into_iter()
+            .map(|quad| quad.into())
+            .collect::<Vec<QuadRaw>>();
+        let bytes = bytemuck::cast_slice(&instances);
+        write_instances(data, &mut self.instance_buffer, "quad_instances", bytes);
+    }
     fn render<'a, 'b>(
         &'a self,
         data: &'a GfxRenderData,
@@ -176,155 +371,437 @@ impl Renderer for QuadRenderer {
     where
         'a: 'b
     {
-        let instances = self.quads
-            .iter()
-            .map(|quad| quad.into())
-            .collect::<Vec<QuadRaw>>();
-        data.queue
-            .write_buffer(&self.instance_buffer, 0, bytemuck::cast_slice(&instances));
-        render_pass.set_pipeline(&self.pipeline);
-        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
-        render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
-        render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
-        render_pass.set_bind_group(0, &data.aspect_ratio_bind_group, &[]);
-        render_pass.draw_indexed(0..INDICES.len() as u32, 0, 0..instances.len() as u32);
+        self.draw(data, render_pass, 0..self.opaque_count, false);
+    }
+    fn render_transparent<'a, 'b>(
+        &'a self,
+        data: &'a GfxRenderData,
+        render_pass: &mut wgpu::RenderPass<'b>,
+    )
+    where
+        'a: 'b
+    {
+        self.draw(data, render_pass, self.opaque_count..self.quads.len() as u32, true);
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct TexturedQuad {
     pub pos: [f32; 2],
+    // Layer in 0..1, higher layers in front.
+    pub z: f32,
     pub width: f32,
     pub height: f32,
-    pub texture: Texture,
+    pub texture: TextureHandle,
+    // Tangent space normal map in a linear, filterable format such as Rgba8Unorm. Only used by
+    // renderers created `with_lighting`.
+    pub normal_map: Option<TextureHandle>,
 }
 
 #[repr(C)]
 #[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
-struct TexturedQuadRaw {
-    pos: [f32; 2],
+pub(crate) struct TexturedQuadRaw {
+    pos: [f32; 3],
     size: [f32; 2],
+    // Texture array layer or bindless slot, unused when drawing one texture at a time.
+    layer: u32,
+}
+impl TexturedQuadRaw {
+    pub(crate) fn layout() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<TexturedQuadRaw>() as u64,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &[
+                wgpu::VertexAttribute {
+                    format: wgpu::VertexFormat::Float32x3,
+                    offset: 0,
+                    shader_location: 4,
+                },
+                wgpu::VertexAttribute {
+                    format: wgpu::VertexFormat::Float32x2,
+                    offset: std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
+                    shader_location: 5,
+                },
+                wgpu::VertexAttribute {
+                    format: wgpu::VertexFormat::Uint32,
+                    offset: std::mem::size_of::<[f32; 5]>() as wgpu::BufferAddress,
+                    shader_location: 7,
+                },
+            ],
+        }
+    }
 }
 impl From<&TexturedQuad> for TexturedQuadRaw {
     fn from(quad: &TexturedQuad) -> Self {
         Self {
-            pos: quad.pos,
+            pos: [quad.pos[0], quad.pos[1], quad.z],
             size: [quad.width, quad.height],
+            layer: 0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum TextureSource {
+    Texture(TextureHandle),
+    // The renderer's texture array or bindless textures.
+    Storage,
+}
+
+// Consecutive instances drawn with the same texture binding.
+struct TextureBatch {
+    source: TextureSource,
+    normal_map: Option<TextureHandle>,
+    instances: std::ops::Range<u32>,
+    transparent: bool,
+}
+
+// Texture array or bindless mode of a `TexturedQuadRenderer`.
+struct StorageMode {
+    storage: TextureStorage,
+    shader: Shader,
+    pipelines: PipelinePair,
+    lit: bool,
+}
+impl StorageMode {
+    fn new(
+        gfx: &GfxRenderData,
+        storage: TextureStorage,
+        defines: &[(&str, &str)],
+        lit: bool,
+    ) -> Self {
+        let storage_defines = storage.defines();
+        let defines = defines
+            .iter()
+            .copied()
+            .chain(storage_defines.iter().map(|(name, value)| (name.as_str(), value.as_str())))
+            .collect::<Vec<_>>();
+        let shader = Shader::new(gfx, "textured_quad.wgsl", TEXTURED_QUAD_SHADER, &defines);
+        let pipelines = Self::create_pipelines(gfx, &shader, &storage, lit)
+            .unwrap_or_else(|err| panic!("{}: {}", shader.name(), err));
+        Self {
+            storage,
+            shader,
+            pipelines,
+            lit,
         }
     }
+    fn create_pipelines(
+        gfx: &GfxRenderData,
+        shader: &Shader,
+        storage: &TextureStorage,
+        lit: bool,
+    ) -> Result<PipelinePair, LayoutError> {
+        let vertex_layouts = [Vertex::layout(), TexturedQuadRaw::layout()];
+        let reflection = shader.reflection();
+        reflection.check_vertex_layouts("vs_main", &vertex_layouts)?;
+        check_lit_layouts(reflection, storage.layout_builder(), lit)?;
+        let bind_group_layouts = lit_layouts(gfx, storage.layout(), lit);
+        let desc = PipelineDescriptor::new(gfx, shader, &vertex_layouts, &bind_group_layouts);
+        Ok(PipelinePair::new(gfx, desc))
+    }
+}
+
+// The view and texture layouts, followed by the normal map and light layouts when lit.
+fn check_lit_layouts(
+    reflection: &ShaderReflection,
+    texture: BindGroupLayoutBuilder,
+    lit: bool,
+) -> Result<(), LayoutError> {
+    let mut groups = vec![view_layout(), texture];
+    if lit {
+        groups.push(texture_layout(true));
+        groups.push(lighting_layout());
+    }
+    let entries = groups.iter().map(|group| group.entries()).collect::<Vec<_>>();
+    reflection.check_bind_group_layouts(&entries)
+}
+fn lit_layouts<'a>(
+    gfx: &'a GfxRenderData,
+    texture: &'a wgpu::BindGroupLayout,
+    lit: bool,
+) -> Vec<&'a wgpu::BindGroupLayout> {
+    let mut layouts = vec![gfx.view.layout(), texture];
+    if lit {
+        layouts.push(gfx.texture_bind_group_layout(true));
+        layouts.push(gfx.lights.layout());
+    }
+    layouts
 }
 
 pub struct TexturedQuadRenderer {
-    vertex_buffer: wgpu::Buffer,
-    index_buffer: wgpu::Buffer,
     instance_buffer: wgpu::Buffer,
-    pipeline: wgpu::RenderPipeline,
+    pipelines: PipelinePair,
+    unfilterable_pipelines: PipelinePair,
+    shader: Shader,
+    defines: Vec<(String, String)>,
+    storage: Option<StorageMode>,
+    // Stands in for missing normal maps, set when the renderer is lit.
+    flat_normal: Option<Texture>,
     quads: Vec<TexturedQuad>,
+    batches: Vec<TextureBatch>,
 }
 impl TexturedQuadRenderer {
     pub fn new(gfx: &mut Gfx) -> Self {
+        Self::with_defines(gfx, &[])
+    }
+    // Multiplies every sampled texel by `tint`.
+    pub fn with_tint(gfx: &mut Gfx, tint: Color) -> Self {
+        let tint = format!(
+            "vec4<f32>({:?}, {:?}, {:?}, {:?})",
+            tint.r, tint.g, tint.b, tint.a
+        );
+        Self::with_defines(gfx, &[("TINT", &tint)])
+    }
+    // Copies textures that are `width` x `height` and `format` into the layers of one array
+    // texture, up to `layers` of them, so their quads can share draw calls. Other quads are
+    // drawn one texture at a time.
+    pub fn with_texture_array(
+        self,
+        gfx: &mut Gfx,
+        width: u32,
+        height: u32,
+        format: wgpu::TextureFormat,
+        layers: u32,
+    ) -> Self {
+        let storage = {
+            let data = gfx.data.borrow();
+            TextureStorage::Layers(TextureArray::new(&data, width, height, format, layers))
+        };
+        self.with_storage(gfx, storage)
+    }
+    // Binds filterable textures of any size as one binding array indexed per instance. Returns
+    // the renderer unchanged if the adapter lacks `BINDLESS_FEATURES`.
+    pub fn with_bindless_textures(self, gfx: &mut Gfx) -> Self {
+        let bindless = BindlessTextures::new(&gfx.data.borrow());
+        match bindless {
+            Some(bindless) => self.with_storage(gfx, TextureStorage::Bindless(bindless)),
+            None => self,
+        }
+    }
+    // Shades quads with the lights from `Gfx::lighting_mut`, bending the light with each quad's
+    // normal map if it has one.
+    pub fn with_lighting(mut self, gfx: &mut Gfx) -> Self {
+        let data = gfx.data.borrow();
+        self.defines.push(("LIGHTING".to_string(), String::new()));
+        let defines = self
+            .defines
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+            .collect::<Vec<_>>();
+        self.shader = Shader::new(&data, "textured_quad.wgsl", TEXTURED_QUAD_SHADER, &defines);
+        (self.pipelines, self.unfilterable_pipelines) =
+            Self::create_pipelines_with(&data, &self.shader, true, PipelinePair::new)
+                .unwrap_or_else(|err| panic!("{}: {}", self.shader.name(), err));
+        if let Some(mode) = self.storage.take() {
+            self.storage = Some(StorageMode::new(&data, mode.storage, &defines, true));
+        }
+        let format = wgpu::TextureFormat::Rgba8Unorm;
+        self.flat_normal = Some(Texture::create(&data, 1, 1, format, &[128, 128, 255, 255]));
+        self
+    }
+    pub fn add(&mut self, quad: TexturedQuad) {
+        self.quads.push(quad);
+    }
+    // Draw calls issued for the last frame.
+    pub fn draw_calls(&self) -> usize {
+        self.batches.len()
+    }
+
+    fn with_defines(gfx: &mut Gfx, defines: &[(&str, &str)]) -> Self {
         let gfx = gfx.data.borrow_mut();
-        let vertex_buffer = gfx
-            .device
-            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                label: None,
-                usage: wgpu::BufferUsages::VERTEX,
-                contents: bytemuck::cast_slice(&VERTICES),
-            });
-        let index_buffer = gfx
-            .device
-            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                label: None,
-                usage: wgpu::BufferUsages::INDEX,
-                contents: bytemuck::cast_slice(&INDICES),
-            });
-        let instance_buffer = gfx.device.create_buffer(&wgpu::BufferDescriptor {
-            label: None,
-            size: (std::mem::size_of::<QuadRaw>() * 128) as wgpu::BufferAddress,
-            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
-            mapped_at_creation: false,
-        });
-        let shader = gfx
-            .device
-            .create_shader_module(wgpu::include_wgsl!("textured_quad.wgsl"));
-        let pipeline_layout = gfx
-            .device
-            .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                label: None,
-                bind_group_layouts: &[
-                    &gfx.aspect_ratio_bind_group_layout,
-                    &gfx.texture_bind_group_layout,
-                ],
-                push_constant_ranges: &[],
-            });
-        let pipeline = gfx
-            .device
-            .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-                label: None,
-                layout: Some(&pipeline_layout),
-                vertex: wgpu::VertexState {
-                    module: &shader,
-                    entry_point: "vs_main",
-                    buffers: &[
-                        // Vertex buffer stuff
-                        Vertex::layout(),
-                        // Instance buffer stuff
-                        wgpu::VertexBufferLayout {
-                            array_stride: std::mem::size_of::<Quad>() as u64,
-                            step_mode: wgpu::VertexStepMode::Instance,
-                            attributes: &[
-                                wgpu::VertexAttribute {
-                                    format: wgpu::VertexFormat::Float32x2,
-                                    offset: 0,
-                                    shader_location: 4,
-                                },
-                                wgpu::VertexAttribute {
-                                    format: wgpu::VertexFormat::Float32x2,
-                                    offset: std::mem::size_of::<[f32; 2]>() as wgpu::BufferAddress,
-                                    shader_location: 5,
-                                },
-                            ],
-                        },
-                    ],
-                },
-                primitive: wgpu::PrimitiveState {
-                    topology: wgpu::PrimitiveTopology::TriangleList,
-                    strip_index_format: None,
-                    front_face: wgpu::FrontFace::Cw,
-                    cull_mode: Some(wgpu::Face::Back),
-                    unclipped_depth: false,
-                    polygon_mode: wgpu::PolygonMode::Fill,
-                    conservative: false,
-                },
-                depth_stencil: None,
-                multisample: wgpu::MultisampleState {
-                    count: 1,
-                    mask: !0,
-                    alpha_to_coverage_enabled: false,
-                },
-                fragment: Some(wgpu::FragmentState {
-                    module: &shader,
-                    entry_point: "fs_main",
-                    targets: &[Some(wgpu::ColorTargetState {
-                        format: wgpu::TextureFormat::Bgra8Unorm,
-                        blend: Some(wgpu::BlendState::REPLACE),
-                        write_mask: wgpu::ColorWrites::ALL,
-                    })],
-                }),
-                multiview: None,
-            });
+        let instance_buffer = instance_buffer(&gfx.device, "textured_quad_instances", 0);
+        let shader = Shader::new(&gfx, "textured_quad.wgsl", TEXTURED_QUAD_SHADER, defines);
+        let (pipelines, unfilterable_pipelines) = Self::create_pipelines(&gfx, &shader)
+            .unwrap_or_else(|err| panic!("{}: {}", shader.name(), err));
         Self {
-            vertex_buffer,
-            index_buffer,
             instance_buffer,
-            pipeline,
+            pipelines,
+            unfilterable_pipelines,
+            shader,
+            defines: defines
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+            storage: None,
+            flat_normal: None,
             quads: vec![],
+            batches: vec![],
         }
     }
-    pub fn add(&mut self, quad: TexturedQuad) {
-        self.quads.push(quad);
+    fn with_storage(mut self, gfx: &mut Gfx, storage: TextureStorage) -> Self {
+        let data = gfx.data.borrow();
+        let defines = self
+            .defines
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+            .collect::<Vec<_>>();
+        let lit = self.flat_normal.is_some();
+        self.storage = Some(StorageMode::new(&data, storage, &defines, lit));
+        self
+    }
+
+    pub(crate) fn create_pipelines(
+        gfx: &GfxRenderData,
+        shader: &Shader,
+    ) -> Result<(PipelinePair, PipelinePair), LayoutError> {
+        Self::create_pipelines_with(gfx, shader, false, PipelinePair::new)
+    }
+    // Checks the shader against the textured quad layouts, with the lighting layouts if `lit`,
+    // and hands `build` the descriptors for filterable and unfilterable textures.
+    pub(crate) fn create_pipelines_with<P>(
+        gfx: &GfxRenderData,
+        shader: &Shader,
+        lit: bool,
+        build: impl Fn(&GfxRenderData, PipelineDescriptor) -> P,
+    ) -> Result<(P, P), LayoutError> {
+        let vertex_layouts = [Vertex::layout(), TexturedQuadRaw::layout()];
+        let reflection = shader.reflection();
+        reflection.check_vertex_layouts("vs_main", &vertex_layouts)?;
+        check_lit_layouts(reflection, texture_layout(true), lit)?;
+        // Float32 textures can't be filtered on every adapter, so they get their own pipeline.
+        let create_pipeline = |filterable: bool| {
+            let texture_layout = gfx.texture_bind_group_layout(filterable);
+            let bind_group_layouts = lit_layouts(gfx, texture_layout, lit);
+            let desc = PipelineDescriptor::new(gfx, shader, &vertex_layouts, &bind_group_layouts);
+            build(gfx, desc)
+        };
+        Ok((create_pipeline(true), create_pipeline(false)))
+    }
+    fn reload_shaders(&mut self, data: &GfxRenderData) {
+        let lit = self.flat_normal.is_some();
+        if self.shader.poll(data) {
+            match Self::create_pipelines_with(data, &self.shader, lit, PipelinePair::new) {
+                Ok(pipelines) => (self.pipelines, self.unfilterable_pipelines) = pipelines,
+                Err(err) => eprintln!("{}: {}", self.shader.name(), err),
+            }
+        }
+        if let Some(mode) = self.storage.as_mut() {
+            if mode.shader.poll(data) {
+                let pipelines =
+                    StorageMode::create_pipelines(data, &mode.shader, &mode.storage, mode.lit);
+                match pipelines {
+                    Ok(pipelines) => mode.pipelines = pipelines,
+                    Err(err) => eprintln!("{}: {}", mode.shader.name(), err),
+                }
+            }
+        }
+    }
+    fn draw<'a, 'b>(
+        &'a self,
+        data: &'a GfxRenderData,
+        render_pass: &mut wgpu::RenderPass<'b>,
+        transparent: bool,
+    ) where
+        'a: 'b,
+    {
+        let mut batches = self
+            .batches
+            .iter()
+            .filter(|batch| batch.transparent == transparent)
+            .peekable();
+        if batches.peek().is_none() {
+            return;
+        }
+        data.quad_geometry.set_buffers(render_pass);
+        render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
+        render_pass.set_bind_group(0, data.view.bind_group(), &[]);
+        if self.flat_normal.is_some() {
+            render_pass.set_bind_group(3, data.lights.bind_group(), &[]);
+        }
+        for batch in batches {
+            match batch.source {
+                TextureSource::Texture(handle) => {
+                    let texture = data.texture(handle);
+                    let pipelines = if texture.filterable {
+                        &self.pipelines
+                    } else {
+                        &self.unfilterable_pipelines
+                    };
+                    render_pass.set_pipeline(pipelines.get(transparent));
+                    render_pass.set_bind_group(1, &texture.bind_group, &[]);
+                }
+                TextureSource::Storage => {
+                    let Some(mode) = self.storage.as_ref() else {
+                        continue;
+                    };
+                    let Some(bind_group) = mode.storage.bind_group() else {
+                        continue;
+                    };
+                    render_pass.set_pipeline(mode.pipelines.get(transparent));
+                    render_pass.set_bind_group(1, bind_group, &[]);
+                }
+            }
+            if let Some(flat_normal) = self.flat_normal.as_ref() {
+                let normal_map = batch
+                    .normal_map
+                    .map_or(flat_normal, |handle| data.texture(handle));
+                render_pass.set_bind_group(2, &normal_map.bind_group, &[]);
+            }
+            render_pass.draw_indexed(
+                0..data.quad_geometry.index_count(),
+                0,
+                batch.instances.clone(),
+            );
+        }
     }
 }
 impl Renderer for TexturedQuadRenderer {
+    fn prepare(&mut self, data: &GfxRenderData) {
+        self.reload_shaders(data);
+        let mut quads = self.quads.iter().collect::<Vec<_>>();
+        let opaque_count = sort_for_depth(
+            &mut quads,
+            data.depth.is_some(),
+            |quad| data.texture(quad.texture).opaque,
+            |quad| quad.z,
+        );
+        let mut encoder = data
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+        let mut instances = vec![];
+        self.batches.clear();
+        for (i, quad) in quads.into_iter().enumerate() {
+            let slot = self
+                .storage
+                .as_mut()
+                .and_then(|mode| mode.storage.slot(data, &mut encoder, quad.texture));
+            let source = match slot {
+                Some(_) => TextureSource::Storage,
+                None => TextureSource::Texture(quad.texture),
+            };
+            let transparent = i >= opaque_count;
+            let normal_map = quad.normal_map.filter(|&handle| {
+                self.flat_normal.is_some() && data.texture(handle).filterable
+            });
+            let index = i as u32;
+            match self.batches.last_mut() {
+                Some(batch)
+                    if batch.source == source
+                        && batch.normal_map == normal_map
+                        && batch.transparent == transparent =>
+                {
+                    batch.instances.end = index + 1
+                }
+                _ => self.batches.push(TextureBatch {
+                    source,
+                    normal_map,
+                    instances: index..index + 1,
+                    transparent,
+                }),
+            }
+            instances.push(TexturedQuadRaw {
+                layer: slot.unwrap_or(0),
+                ..quad.into()
+            });
+        }
+        if let Some(mode) = self.storage.as_mut() {
+            mode.storage.update(data);
+            data.queue.submit(std::iter::once(encoder.finish()));
+        }
+        let bytes = bytemuck::cast_slice(&instances);
+        write_instances(data, &mut self.instance_buffer, "textured_quad_instances", bytes);
+    }
     fn render<'a, 'b>(
         &'a self,
         data: &'a GfxRenderData,
@@ -333,21 +810,123 @@ impl Renderer for TexturedQuadRenderer {
     where
         'a: 'b
     {
-        let instances = self.quads
-            .iter()
-            .map(|quad| quad.into())
-            .collect::<Vec<TexturedQuadRaw>>();
-        data.queue
-            .write_buffer(&self.instance_buffer, 0, bytemuck::cast_slice(&instances));
-        render_pass.set_pipeline(&self.pipeline);
-        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
-        render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
-        render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
-        render_pass.set_bind_group(0, &data.aspect_ratio_bind_group, &[]);
-        for (i, quad) in self.quads.iter().enumerate() {
-            let i = i as u32;
-            render_pass.set_bind_group(1, &quad.texture.bind_group, &[]);
-            render_pass.draw_indexed(0..INDICES.len() as u32, 0, i..i+1);
+        self.draw(data, render_pass, false);
+    }
+    fn render_transparent<'a, 'b>(
+        &'a self,
+        data: &'a GfxRenderData,
+        render_pass: &mut wgpu::RenderPass<'b>,
+    )
+    where
+        'a: 'b
+    {
+        self.draw(data, render_pass, true);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reflect::{check_shader, reflect_shader};
+
+    // Masks are drawn with the ALPHA_MASK variants, which have to match the same layouts.
+    const MASK_VARIANTS: [&[(&str, &str)]; 2] = [&[], &[("ALPHA_MASK", "")]];
+
+    #[test]
+    fn fill_quad_matches_layouts() {
+        let vertex_layouts = [Vertex::layout(), QuadRaw::layout()];
+        for defines in MASK_VARIANTS {
+            let view = view_layout();
+            let groups = [view.entries()];
+            check_shader("fill_quad.wgsl", FILL_QUAD_SHADER, defines, &vertex_layouts, &groups);
+        }
+        let (view, lighting) = (view_layout(), lighting_layout());
+        let groups = [view.entries(), lighting.entries()];
+        let defines = [("LIGHTING", "")];
+        check_shader("fill_quad.wgsl", FILL_QUAD_SHADER, &defines, &vertex_layouts, &groups);
+    }
+
+    #[test]
+    fn textured_quad_matches_layouts() {
+        let vertex_layouts = [Vertex::layout(), TexturedQuadRaw::layout()];
+        for defines in MASK_VARIANTS {
+            for filterable in [true, false] {
+                let (view, texture) = (view_layout(), texture_layout(filterable));
+                let groups = [view.entries(), texture.entries()];
+                let source = TEXTURED_QUAD_SHADER;
+                check_shader("textured_quad.wgsl", source, defines, &vertex_layouts, &groups);
+            }
         }
     }
+
+    #[test]
+    fn lit_textured_quad_matches_layouts() {
+        let variants = [
+            (vec![("LIGHTING", "")], texture_layout(true)),
+            (vec![("LIGHTING", ""), ("TEXTURE_ARRAY", "")], TextureArray::layout_builder(true)),
+            (vec![("LIGHTING", ""), ("BINDLESS", "64")], BindlessTextures::layout_builder(64)),
+        ];
+        for (defines, layout) in variants {
+            let reflection = reflect_shader("textured_quad.wgsl", TEXTURED_QUAD_SHADER, &defines);
+            reflection
+                .check_vertex_layouts("vs_main", &[Vertex::layout(), TexturedQuadRaw::layout()])
+                .unwrap();
+            check_lit_layouts(&reflection, layout, true).unwrap();
+        }
+    }
+
+    #[test]
+    fn texture_storage_variants_match_layouts() {
+        let variants = [
+            (("TEXTURE_ARRAY", ""), TextureArray::layout_builder(true)),
+            (("BINDLESS", "64"), BindlessTextures::layout_builder(64)),
+        ];
+        let vertex_layouts = [Vertex::layout(), TexturedQuadRaw::layout()];
+        for (define, layout) in variants {
+            let view = view_layout();
+            let groups = [view.entries(), layout.entries()];
+            let source = TEXTURED_QUAD_SHADER;
+            check_shader("textured_quad.wgsl", source, &[define], &vertex_layouts, &groups);
+        }
+    }
+
+    #[test]
+    fn quad_rect_is_centered_on_pos() {
+        let quad = Quad {
+            pos: [100.0, 50.0],
+            z: 0.0,
+            width: 40.0,
+            height: 20.0,
+            color: Color::WHITE,
+            casts_shadow: true,
+            box_shadow: None,
+        };
+        assert_eq!(quad.rect(), Rect::new(80.0, 40.0, 40.0, 20.0));
+    }
+
+    #[test]
+    fn sorts_opaque_first_then_back_to_front() {
+        // (opaque, z, submission order)
+        let mut items = vec![
+            (false, 0.5, 0),
+            (true, 0.9, 1),
+            (false, 0.1, 2),
+            (true, 0.2, 3),
+            (false, 0.5, 4),
+        ];
+        let opaque = sort_for_depth(&mut items, true, |item| item.0, |item| item.1);
+        assert_eq!(opaque, 2);
+        let order = items.iter().map(|item| item.2).collect::<Vec<_>>();
+        assert_eq!(order, [1, 3, 2, 0, 4]);
+    }
+
+    #[test]
+    fn keeps_submission_order_without_depth() {
+        // A translucent quad added before an opaque one stays under it.
+        let mut items = vec![(false, 0.9, 0), (true, 0.1, 1), (false, 0.5, 2)];
+        let opaque = sort_for_depth(&mut items, false, |item| item.0, |item| item.1);
+        assert_eq!(opaque, 0);
+        let order = items.iter().map(|item| item.2).collect::<Vec<_>>();
+        assert_eq!(order, [0, 1, 2]);
+    }
 }
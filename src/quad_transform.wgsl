@@ -0,0 +1,76 @@
+struct View {
+    // Size of the render target in pixels.
+    size: vec2<f32>,
+    // World position shown at the top-left corner.
+    camera: vec2<f32>,
+    // Pixels per world unit.
+    zoom: f32,
+    // Non-zero to snap quad edges to whole pixels.
+    snap: u32,
+};
+
+@group(0) @binding(0)
+var<uniform> view: View;
+
+struct VertexOutput {
+    @builtin(position) pos: vec4<f32>,
+    @location(0) color: vec4<f32>,
+    @location(1) uv: vec2<f32>,
+#ifdef INSTANCE_LAYER
+    @location(2) @interpolate(flat) layer: u32,
+#endif
+#ifdef LIGHTING
+    // World position, for lighting.
+    @location(3) world: vec2<f32>,
+#endif
+#ifdef INSTANCE_SHADOW
+    // World offset from the quad's center.
+    @location(4) local: vec2<f32>,
+    @location(5) @interpolate(flat) half_size: vec2<f32>,
+    @location(6) @interpolate(flat) shadow: vec4<f32>,
+    @location(7) @interpolate(flat) shadow_color: vec4<f32>,
+#endif
+};
+
+struct Instance {
+    // z is the layer in 0..1, higher layers in front.
+    @location(4) pos: vec3<f32>,
+    @location(5) size: vec2<f32>,
+#ifdef INSTANCE_COLOR
+    @location(6) color: vec4<f32>,
+#endif
+#ifdef INSTANCE_LAYER
+    // Texture array layer or bindless texture index.
+    @location(7) layer: u32,
+#endif
+#ifdef INSTANCE_SHADOW
+    // Box shadow offset, blur and spread, and its color.
+    @location(8) shadow: vec4<f32>,
+    @location(9) shadow_color: vec4<f32>,
+#endif
+};
+
+// Maps a corner of the unit quad to clip space for a quad of `size` world units centered on
+// `pos.xy`.
+fn quad_to_clip(vin: vec2<f32>, pos: vec3<f32>, size: vec2<f32>) -> vec4<f32> {
+    var scale: vec2<f32>;
+    var offset: vec2<f32>;
+    var center = (pos.xy - view.camera) * view.zoom;
+    var extent = size * view.zoom;
+    if view.snap != 0u {
+        let low = round(center - extent * 0.5);
+        let high = round(center + extent * 0.5);
+        center = (low + high) * 0.5;
+        extent = high - low;
+    }
+    scale = extent / view.size;
+    offset = (center / view.size * vec2<f32>(2.0, -2.0)) + vec2<f32>(-1.0, 1.0);
+    return vec4<f32>(vin * scale + offset, 1.0 - pos.z, 1.0);
+}
+
+// Maps a world position on layer `z` to clip space.
+fn world_to_clip(pos: vec2<f32>, z: f32) -> vec4<f32> {
+    let screen = (pos - view.camera) * view.zoom;
+    let clip = screen / view.size * vec2<f32>(2.0, -2.0) + vec2<f32>(-1.0, 1.0);
+    return vec4<f32>(clip, 1.0 - z, 1.0);
+}
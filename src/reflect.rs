@@ -0,0 +1,881 @@
+use std::collections::{HashMap, HashSet};
+
+use naga::{ImageClass, ImageDimension, ScalarKind, TypeInner};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct VertexInput {
+    pub location: u32,
+    pub name: Option<String>,
+    pub kind: ScalarKind,
+    pub components: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ResourceType {
+    Uniform,
+    Storage { read_only: bool },
+    Texture {
+        dimension: wgpu::TextureViewDimension,
+        kind: ScalarKind,
+        multisampled: bool,
+    },
+    DepthTexture {
+        dimension: wgpu::TextureViewDimension,
+        multisampled: bool,
+    },
+    StorageTexture {
+        dimension: wgpu::TextureViewDimension,
+    },
+    Sampler { comparison: bool },
+    Other,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResourceBinding {
+    pub group: u32,
+    pub binding: u32,
+    pub name: Option<String>,
+    pub ty: ResourceType,
+    // Set for `binding_array`s, `Some(0)` when the array has no fixed size.
+    pub count: Option<u32>,
+    // Size in bytes of uniform and storage buffer contents.
+    pub size: Option<u32>,
+    // Byte offset of every scalar in uniform buffer contents, vectors and matrices counting
+    // one scalar per component.
+    pub scalars: Vec<u32>,
+    // Stages of the entry points that use the binding, directly or through called functions.
+    pub visibility: wgpu::ShaderStages,
+    // Group and binding of every sampler a texture is sampled with.
+    pub samplers: Vec<(u32, u32)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct EntryPoint {
+    pub name: String,
+    pub stage: naga::ShaderStage,
+    pub vertex_inputs: Vec<VertexInput>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LayoutError {
+    MissingEntryPoint(String),
+    MissingAttribute { location: u32 },
+    DuplicateLocation { location: u32 },
+    AttributeFormat {
+        location: u32,
+        format: wgpu::VertexFormat,
+        kind: ScalarKind,
+        components: u32,
+    },
+    AttributeOutOfBounds {
+        location: u32,
+        offset: u64,
+        size: u64,
+        stride: u64,
+    },
+    MissingBinding { group: u32, binding: u32 },
+    BindingType {
+        group: u32,
+        binding: u32,
+        shader: ResourceType,
+        layout: wgpu::BindingType,
+    },
+    BindingCount {
+        group: u32,
+        binding: u32,
+        shader: Option<u32>,
+        layout: Option<u32>,
+    },
+    Visibility {
+        group: u32,
+        binding: u32,
+        shader: wgpu::ShaderStages,
+        layout: wgpu::ShaderStages,
+    },
+    // A float texture whose filterability doesn't match the sampler it's sampled with.
+    SamplerFiltering {
+        group: u32,
+        binding: u32,
+        sampler: (u32, u32),
+        filterable: bool,
+    },
+}
+impl std::fmt::Display for LayoutError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::MissingEntryPoint(name) => write!(f, "no entry point named {}", name),
+            Self::MissingAttribute { location } => {
+                write!(f, "no vertex attribute for @location({})", location)
+            }
+            Self::DuplicateLocation { location } => {
+                write!(f, "@location({}) is used by more than one attribute", location)
+            }
+            Self::AttributeFormat {
+                location,
+                format,
+                kind,
+                components,
+            } => write!(
+                f,
+                "@location({}) is a {:?} with {} components but the attribute is {:?}",
+                location, kind, components, format
+            ),
+            Self::AttributeOutOfBounds {
+                location,
+                offset,
+                size,
+                stride,
+            } => write!(
+                f,
+                "@location({}) reads {} bytes at offset {} past the stride of {}",
+                location, size, offset, stride
+            ),
+            Self::MissingBinding { group, binding } => {
+                write!(f, "no layout entry for @group({}) @binding({})", group, binding)
+            }
+            Self::BindingType {
+                group,
+                binding,
+                shader,
+                layout,
+            } => write!(
+                f,
+                "@group({}) @binding({}) is {:?} in the shader but {:?} in the layout",
+                group, binding, shader, layout
+            ),
+            Self::BindingCount {
+                group,
+                binding,
+                shader,
+                layout,
+            } => write!(
+                f,
+                "@group({}) @binding({}) has array count {:?} in the shader but {:?} in the layout",
+                group, binding, shader, layout
+            ),
+            Self::Visibility {
+                group,
+                binding,
+                shader,
+                layout,
+            } => write!(
+                f,
+                "@group({}) @binding({}) is used by {:?} but only visible to {:?}",
+                group, binding, shader, layout
+            ),
+            Self::SamplerFiltering {
+                group,
+                binding,
+                sampler,
+                filterable,
+            } => write!(
+                f,
+                "@group({}) @binding({}) is {} but its sampler at @group({}) @binding({}) is {}",
+                group,
+                binding,
+                if *filterable { "filterable" } else { "not filterable" },
+                sampler.0,
+                sampler.1,
+                if *filterable { "non-filtering" } else { "filtering" }
+            ),
+        }
+    }
+}
+impl std::error::Error for LayoutError {}
+
+// Vertex inputs of every entry point and all resource bindings of a shader module.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShaderReflection {
+    pub entry_points: Vec<EntryPoint>,
+    pub bindings: Vec<ResourceBinding>,
+}
+impl ShaderReflection {
+    pub fn new(module: &naga::Module) -> Self {
+        let mut visibility = HashMap::new();
+        for entry_point in module.entry_points.iter() {
+            let mut used = HashSet::new();
+            used_globals(module, &entry_point.function, &mut used);
+            for global in used {
+                *visibility.entry(global).or_insert(wgpu::ShaderStages::NONE) |=
+                    shader_stage(entry_point.stage);
+            }
+        }
+        let mut sampled = vec![];
+        for (_, function) in module.functions.iter() {
+            sampled_globals(function, &mut sampled);
+        }
+        for entry_point in module.entry_points.iter() {
+            sampled_globals(&entry_point.function, &mut sampled);
+        }
+        let entry_points = module
+            .entry_points
+            .iter()
+            .map(|entry_point| {
+                let mut vertex_inputs = vec![];
+                if entry_point.stage == naga::ShaderStage::Vertex {
+                    for argument in entry_point.function.arguments.iter() {
+                        collect_inputs(
+                            module,
+                            argument.ty,
+                            argument.name.as_ref(),
+                            argument.binding.as_ref(),
+                            &mut vertex_inputs,
+                        );
+                    }
+                }
+                vertex_inputs.sort_by_key(|input| input.location);
+                EntryPoint {
+                    name: entry_point.name.clone(),
+                    stage: entry_point.stage,
+                    vertex_inputs,
+                }
+            })
+            .collect();
+        let mut bindings = module
+            .global_variables
+            .iter()
+            .filter_map(|(handle, global)| {
+                let binding = global.binding.as_ref()?;
+                let (ty, count) = match module.types[global.ty].inner {
+                    TypeInner::BindingArray { base, size } => {
+                        let count = match size {
+                            naga::ArraySize::Constant(count) => count.get(),
+                            naga::ArraySize::Dynamic => 0,
+                        };
+                        (base, Some(count))
+                    }
+                    _ => (global.ty, None),
+                };
+                let inner = &module.types[ty].inner;
+                let ty = resource_type(&global.space, inner);
+                let size = matches!(ty, ResourceType::Uniform | ResourceType::Storage { .. })
+                    .then(|| inner.size(module.to_ctx()));
+                let samplers = sampled
+                    .iter()
+                    .filter(|(image, _)| *image == handle)
+                    .filter_map(|(_, sampler)| module.global_variables[*sampler].binding.as_ref())
+                    .map(|sampler| (sampler.group, sampler.binding))
+                    .collect();
+                let mut scalars = vec![];
+                if ty == ResourceType::Uniform {
+                    collect_scalars(module, inner, 0, &mut scalars);
+                }
+                Some(ResourceBinding {
+                    group: binding.group,
+                    binding: binding.binding,
+                    name: global.name.clone(),
+                    ty,
+                    count,
+                    size,
+                    scalars,
+                    visibility: visibility.remove(&handle).unwrap_or(wgpu::ShaderStages::NONE),
+                    samplers,
+                })
+            })
+            .collect::<Vec<_>>();
+        bindings.sort_by_key(|binding| (binding.group, binding.binding));
+        Self {
+            entry_points,
+            bindings,
+        }
+    }
+    pub fn vertex_inputs(&self, entry_point: &str) -> Option<&[VertexInput]> {
+        self.entry_points
+            .iter()
+            .find(|e| e.name == entry_point)
+            .map(|e| e.vertex_inputs.as_slice())
+    }
+    pub fn binding(&self, name: &str) -> Option<&ResourceBinding> {
+        self.bindings
+            .iter()
+            .find(|b| b.name.as_deref() == Some(name))
+    }
+    pub fn bind_group(&self, group: u32) -> impl Iterator<Item = &ResourceBinding> {
+        self.bindings.iter().filter(move |b| b.group == group)
+    }
+    // Every input of `entry_point` must be fed by an attribute of a matching type that fits
+    // inside its buffer's stride.
+    pub fn check_vertex_layouts(
+        &self,
+        entry_point: &str,
+        layouts: &[wgpu::VertexBufferLayout],
+    ) -> Result<(), LayoutError> {
+        let inputs = self
+            .vertex_inputs(entry_point)
+            .ok_or_else(|| LayoutError::MissingEntryPoint(entry_point.to_string()))?;
+        let mut seen = std::collections::HashSet::new();
+        for layout in layouts {
+            for attribute in layout.attributes {
+                let location = attribute.shader_location;
+                if !seen.insert(location) {
+                    return Err(LayoutError::DuplicateLocation { location });
+                }
+                let size = attribute.format.size();
+                if attribute.offset + size > layout.array_stride {
+                    return Err(LayoutError::AttributeOutOfBounds {
+                        location,
+                        offset: attribute.offset,
+                        size,
+                        stride: layout.array_stride,
+                    });
+                }
+            }
+        }
+        for input in inputs {
+            let attribute = layouts
+                .iter()
+                .flat_map(|layout| layout.attributes.iter())
+                .find(|attribute| attribute.shader_location == input.location)
+                .ok_or(LayoutError::MissingAttribute {
+                    location: input.location,
+                })?;
+            if format_info(attribute.format) != (input.kind, input.components) {
+                return Err(LayoutError::AttributeFormat {
+                    location: input.location,
+                    format: attribute.format,
+                    kind: input.kind,
+                    components: input.components,
+                });
+            }
+        }
+        Ok(())
+    }
+    // `groups[i]` holds the entries of the layout bound at `@group(i)`.
+    pub fn check_bind_group_layouts(
+        &self,
+        groups: &[&[wgpu::BindGroupLayoutEntry]],
+    ) -> Result<(), LayoutError> {
+        for resource in self.bindings.iter() {
+            let (group, binding) = (resource.group, resource.binding);
+            let entry = layout_entry(groups, group, binding)
+                .ok_or(LayoutError::MissingBinding { group, binding })?;
+            if !binding_compatible(&resource.ty, &entry.ty) {
+                return Err(LayoutError::BindingType {
+                    group,
+                    binding,
+                    shader: resource.ty.clone(),
+                    layout: entry.ty,
+                });
+            }
+            let layout_count = entry.count.map(|count| count.get());
+            let counts_match = match (resource.count, layout_count) {
+                (None, None) => true,
+                (Some(0), Some(_)) => true,
+                (Some(shader), Some(layout)) => shader == layout,
+                _ => false,
+            };
+            if !counts_match {
+                return Err(LayoutError::BindingCount {
+                    group,
+                    binding,
+                    shader: resource.count,
+                    layout: layout_count,
+                });
+            }
+            if !entry.visibility.contains(resource.visibility) {
+                return Err(LayoutError::Visibility {
+                    group,
+                    binding,
+                    shader: resource.visibility,
+                    layout: entry.visibility,
+                });
+            }
+            let wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable },
+                ..
+            } = entry.ty
+            else {
+                continue;
+            };
+            for &sampler in resource.samplers.iter() {
+                let filtering = match layout_entry(groups, sampler.0, sampler.1).map(|e| e.ty) {
+                    Some(wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering)) => true,
+                    Some(wgpu::BindingType::Sampler(wgpu::SamplerBindingType::NonFiltering)) => {
+                        false
+                    }
+                    _ => continue,
+                };
+                if filtering != filterable {
+                    return Err(LayoutError::SamplerFiltering {
+                        group,
+                        binding,
+                        sampler,
+                        filterable,
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+pub fn reflect_wgsl(source: &str) -> Result<ShaderReflection, crate::shader::ShaderError> {
+    crate::shader::validate(source).map(|module| ShaderReflection::new(&module))
+}
+
+fn layout_entry<'a>(
+    groups: &[&'a [wgpu::BindGroupLayoutEntry]],
+    group: u32,
+    binding: u32,
+) -> Option<&'a wgpu::BindGroupLayoutEntry> {
+    groups
+        .get(group as usize)
+        .and_then(|entries| entries.iter().find(|entry| entry.binding == binding))
+}
+
+fn shader_stage(stage: naga::ShaderStage) -> wgpu::ShaderStages {
+    match stage {
+        naga::ShaderStage::Vertex => wgpu::ShaderStages::VERTEX,
+        naga::ShaderStage::Fragment => wgpu::ShaderStages::FRAGMENT,
+        naga::ShaderStage::Compute => wgpu::ShaderStages::COMPUTE,
+    }
+}
+
+// Globals referenced by `function` and every function it calls. WGSL forbids recursion, so
+// the walk always ends.
+fn used_globals(
+    module: &naga::Module,
+    function: &naga::Function,
+    used: &mut HashSet<naga::Handle<naga::GlobalVariable>>,
+) {
+    for (_, expression) in function.expressions.iter() {
+        if let naga::Expression::GlobalVariable(global) = *expression {
+            used.insert(global);
+        }
+    }
+    let mut calls = vec![];
+    collect_calls(&function.body, &mut calls);
+    for call in calls {
+        used_globals(module, &module.functions[call], used);
+    }
+}
+
+fn collect_calls(block: &naga::Block, calls: &mut Vec<naga::Handle<naga::Function>>) {
+    for statement in block.iter() {
+        match statement {
+            naga::Statement::Block(block) => collect_calls(block, calls),
+            naga::Statement::If { accept, reject, .. } => {
+                collect_calls(accept, calls);
+                collect_calls(reject, calls);
+            }
+            naga::Statement::Switch { cases, .. } => {
+                for case in cases {
+                    collect_calls(&case.body, calls);
+                }
+            }
+            naga::Statement::Loop {
+                body, continuing, ..
+            } => {
+                collect_calls(body, calls);
+                collect_calls(continuing, calls);
+            }
+            naga::Statement::Call { function, .. } => calls.push(*function),
+            _ => (),
+        }
+    }
+}
+
+// Texture and sampler globals sampled together. Textures and samplers passed in as function
+// arguments can't be traced back to a binding and are skipped.
+fn sampled_globals(
+    function: &naga::Function,
+    sampled: &mut Vec<(naga::Handle<naga::GlobalVariable>, naga::Handle<naga::GlobalVariable>)>,
+) {
+    for (_, expression) in function.expressions.iter() {
+        if let naga::Expression::ImageSample { image, sampler, .. } = *expression {
+            if let (Some(image), Some(sampler)) =
+                (global_of(function, image), global_of(function, sampler))
+            {
+                sampled.push((image, sampler));
+            }
+        }
+    }
+}
+
+// The global an expression reads from, looking through indexing into binding arrays.
+fn global_of(
+    function: &naga::Function,
+    expression: naga::Handle<naga::Expression>,
+) -> Option<naga::Handle<naga::GlobalVariable>> {
+    match function.expressions[expression] {
+        naga::Expression::GlobalVariable(global) => Some(global),
+        naga::Expression::Access { base, .. } | naga::Expression::AccessIndex { base, .. } => {
+            global_of(function, base)
+        }
+        _ => None,
+    }
+}
+
+fn collect_inputs(
+    module: &naga::Module,
+    ty: naga::Handle<naga::Type>,
+    name: Option<&String>,
+    binding: Option<&naga::Binding>,
+    inputs: &mut Vec<VertexInput>,
+) {
+    match (binding, &module.types[ty].inner) {
+        (Some(naga::Binding::Location { location, .. }), inner) => {
+            let (kind, components) = match *inner {
+                TypeInner::Scalar(scalar) => (scalar.kind, 1),
+                TypeInner::Vector { size, scalar } => (scalar.kind, size as u32),
+                _ => return,
+            };
+            inputs.push(VertexInput {
+                location: *location,
+                name: name.cloned(),
+                kind,
+                components,
+            });
+        }
+        (None, TypeInner::Struct { members, .. }) => {
+            for member in members {
+                collect_inputs(
+                    module,
+                    member.ty,
+                    member.name.as_ref(),
+                    member.binding.as_ref(),
+                    inputs,
+                );
+            }
+        }
+        _ => (),
+    }
+}
+
+fn collect_scalars(module: &naga::Module, inner: &TypeInner, offset: u32, scalars: &mut Vec<u32>) {
+    match *inner {
+        TypeInner::Scalar(_) | TypeInner::Atomic(_) => scalars.push(offset),
+        TypeInner::Vector { size, scalar } => {
+            let width = scalar.width as u32;
+            scalars.extend((0..size as u32).map(|i| offset + i * width));
+        }
+        TypeInner::Matrix {
+            columns,
+            rows,
+            scalar,
+        } => {
+            // Columns are aligned like vectors, so a vec3 column takes 4 scalars of space.
+            let width = scalar.width as u32;
+            let stride = naga::proc::Alignment::from(rows) * width;
+            for column in 0..columns as u32 {
+                let start = offset + column * stride;
+                scalars.extend((0..rows as u32).map(|i| start + i * width));
+            }
+        }
+        TypeInner::Array {
+            base,
+            size: naga::ArraySize::Constant(count),
+            stride,
+        } => {
+            for i in 0..count.get() {
+                collect_scalars(module, &module.types[base].inner, offset + i * stride, scalars);
+            }
+        }
+        TypeInner::Struct { ref members, .. } => {
+            for member in members {
+                let inner = &module.types[member.ty].inner;
+                collect_scalars(module, inner, offset + member.offset, scalars);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn view_dimension(dim: ImageDimension, arrayed: bool) -> wgpu::TextureViewDimension {
+    match (dim, arrayed) {
+        (ImageDimension::D1, _) => wgpu::TextureViewDimension::D1,
+        (ImageDimension::D2, false) => wgpu::TextureViewDimension::D2,
+        (ImageDimension::D2, true) => wgpu::TextureViewDimension::D2Array,
+        (ImageDimension::D3, _) => wgpu::TextureViewDimension::D3,
+        (ImageDimension::Cube, false) => wgpu::TextureViewDimension::Cube,
+        (ImageDimension::Cube, true) => wgpu::TextureViewDimension::CubeArray,
+    }
+}
+
+fn resource_type(space: &naga::AddressSpace, inner: &TypeInner) -> ResourceType {
+    match (space, inner) {
+        (naga::AddressSpace::Uniform, _) => ResourceType::Uniform,
+        (naga::AddressSpace::Storage { access }, _) => ResourceType::Storage {
+            read_only: !access.contains(naga::StorageAccess::STORE),
+        },
+        (
+            _,
+            TypeInner::Image {
+                dim,
+                arrayed,
+                class,
+            },
+        ) => {
+            let dimension = view_dimension(*dim, *arrayed);
+            match *class {
+                ImageClass::Sampled { kind, multi } => ResourceType::Texture {
+                    dimension,
+                    kind,
+                    multisampled: multi,
+                },
+                ImageClass::Depth { multi } => ResourceType::DepthTexture {
+                    dimension,
+                    multisampled: multi,
+                },
+                ImageClass::Storage { .. } => ResourceType::StorageTexture { dimension },
+            }
+        }
+        (_, TypeInner::Sampler { comparison }) => ResourceType::Sampler {
+            comparison: *comparison,
+        },
+        _ => ResourceType::Other,
+    }
+}
+
+fn binding_compatible(shader: &ResourceType, layout: &wgpu::BindingType) -> bool {
+    match (shader, layout) {
+        (
+            ResourceType::Uniform,
+            wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                ..
+            },
+        ) => true,
+        (
+            ResourceType::Storage { read_only },
+            wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage {
+                    read_only: layout_read_only,
+                },
+                ..
+            },
+        ) => *read_only || !layout_read_only,
+        (
+            ResourceType::Texture {
+                dimension,
+                kind,
+                multisampled,
+            },
+            wgpu::BindingType::Texture {
+                sample_type,
+                view_dimension,
+                multisampled: layout_multisampled,
+            },
+        ) => {
+            let kind_matches = matches!(
+                (kind, sample_type),
+                (ScalarKind::Float, wgpu::TextureSampleType::Float { .. })
+                    | (ScalarKind::Uint, wgpu::TextureSampleType::Uint)
+                    | (ScalarKind::Sint, wgpu::TextureSampleType::Sint)
+            );
+            kind_matches && dimension == view_dimension && multisampled == layout_multisampled
+        }
+        (
+            ResourceType::DepthTexture {
+                dimension,
+                multisampled,
+            },
+            wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Depth,
+                view_dimension,
+                multisampled: layout_multisampled,
+            },
+        ) => dimension == view_dimension && multisampled == layout_multisampled,
+        (
+            ResourceType::StorageTexture { dimension },
+            wgpu::BindingType::StorageTexture { view_dimension, .. },
+        ) => dimension == view_dimension,
+        (ResourceType::Sampler { comparison }, wgpu::BindingType::Sampler(ty)) => {
+            *comparison == (*ty == wgpu::SamplerBindingType::Comparison)
+        }
+        _ => false,
+    }
+}
+
+fn format_info(format: wgpu::VertexFormat) -> (ScalarKind, u32) {
+    use wgpu::VertexFormat as F;
+    match format {
+        F::Uint8x2 | F::Uint16x2 | F::Uint32x2 => (ScalarKind::Uint, 2),
+        F::Uint32x3 => (ScalarKind::Uint, 3),
+        F::Uint8x4 | F::Uint16x4 | F::Uint32x4 => (ScalarKind::Uint, 4),
+        F::Uint32 => (ScalarKind::Uint, 1),
+        F::Sint8x2 | F::Sint16x2 | F::Sint32x2 => (ScalarKind::Sint, 2),
+        F::Sint32x3 => (ScalarKind::Sint, 3),
+        F::Sint8x4 | F::Sint16x4 | F::Sint32x4 => (ScalarKind::Sint, 4),
+        F::Sint32 => (ScalarKind::Sint, 1),
+        F::Float32 | F::Float64 => (ScalarKind::Float, 1),
+        F::Unorm8x2 | F::Snorm8x2 | F::Unorm16x2 | F::Snorm16x2 | F::Float16x2 | F::Float32x2
+        | F::Float64x2 => (ScalarKind::Float, 2),
+        F::Float32x3 | F::Float64x3 => (ScalarKind::Float, 3),
+        F::Unorm8x4 | F::Snorm8x4 | F::Unorm16x4 | F::Snorm16x4 | F::Float16x4 | F::Float32x4
+        | F::Float64x4 => (ScalarKind::Float, 4),
+    }
+}
+
+// Compiles a built-in shader with the built-in modules, panicking with naga's report.
+#[cfg(test)]
+pub(crate) fn reflect_shader(
+    name: &str,
+    source: &str,
+    defines: &[(&str, &str)],
+) -> ShaderReflection {
+    let preprocessor = crate::preprocessor::Preprocessor::new();
+    let (_, module) = crate::shader::compile(&preprocessor, name, source, defines)
+        .unwrap_or_else(|err| panic!("{}\n{}", err, err.report));
+    ShaderReflection::new(&module)
+}
+
+// Compiles a built-in shader and checks it against the layouts its pipeline is created with,
+// returning the reflection for any further checks.
+#[cfg(test)]
+pub(crate) fn check_shader(
+    name: &str,
+    source: &str,
+    defines: &[(&str, &str)],
+    vertex_layouts: &[wgpu::VertexBufferLayout],
+    bind_groups: &[&[wgpu::BindGroupLayoutEntry]],
+) -> ShaderReflection {
+    let reflection = reflect_shader(name, source, defines);
+    reflection
+        .check_vertex_layouts("vs_main", vertex_layouts)
+        .and_then(|_| reflection.check_bind_group_layouts(bind_groups))
+        .unwrap_or_else(|err| panic!("{} {:?}: {}", name, defines, err));
+    reflection
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bind_group::BindGroupLayoutBuilder;
+
+    fn reflect(name: &str, source: &str) -> ShaderReflection {
+        reflect_shader(name, source, &[])
+    }
+
+    #[test]
+    fn lists_fill_quad_inputs() {
+        let reflection = reflect("fill_quad.wgsl", include_str!("fill_quad.wgsl"));
+        let locations = reflection
+            .vertex_inputs("vs_main")
+            .unwrap()
+            .iter()
+            .map(|input| (input.location, input.components))
+            .collect::<Vec<_>>();
+        assert_eq!(locations, vec![(0, 2), (1, 2), (4, 3), (5, 2), (6, 4), (8, 4), (9, 4)]);
+        assert_eq!(reflection.vertex_inputs("fs_main"), Some(&[][..]));
+        assert_eq!(reflection.bind_group(0).count(), 1);
+        assert_eq!(reflection.bindings[0].ty, ResourceType::Uniform);
+    }
+
+    #[test]
+    fn lists_textured_quad_bindings() {
+        let reflection = reflect("textured_quad.wgsl", include_str!("textured_quad.wgsl"));
+        let tys = reflection
+            .bind_group(1)
+            .map(|binding| binding.ty.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            tys,
+            vec![
+                ResourceType::Texture {
+                    dimension: wgpu::TextureViewDimension::D2,
+                    kind: ScalarKind::Float,
+                    multisampled: false,
+                },
+                ResourceType::Sampler { comparison: false },
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_mismatched_attributes() {
+        let reflection = reflect("fill_quad.wgsl", include_str!("fill_quad.wgsl"));
+        let attributes = [
+            wgpu::VertexAttribute {
+                format: wgpu::VertexFormat::Float32x2,
+                offset: 0,
+                shader_location: 0,
+            },
+            wgpu::VertexAttribute {
+                format: wgpu::VertexFormat::Uint32x2,
+                offset: 8,
+                shader_location: 1,
+            },
+        ];
+        let layout = wgpu::VertexBufferLayout {
+            array_stride: 16,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &attributes,
+        };
+        assert_eq!(
+            reflection.check_vertex_layouts("vs_main", std::slice::from_ref(&layout)),
+            Err(LayoutError::AttributeFormat {
+                location: 1,
+                format: wgpu::VertexFormat::Uint32x2,
+                kind: ScalarKind::Float,
+                components: 2,
+            })
+        );
+        let short = wgpu::VertexBufferLayout {
+            array_stride: 12,
+            ..layout
+        };
+        assert!(matches!(
+            reflection.check_vertex_layouts("vs_main", &[short]),
+            Err(LayoutError::AttributeOutOfBounds { location: 1, .. })
+        ));
+    }
+
+    fn check_textured_quad(textures: BindGroupLayoutBuilder) -> Result<(), LayoutError> {
+        let reflection = reflect("textured_quad.wgsl", include_str!("textured_quad.wgsl"));
+        let view = crate::gfx::view_layout();
+        reflection.check_bind_group_layouts(&[view.entries(), textures.entries()])
+    }
+
+    #[test]
+    fn reports_hidden_bindings() {
+        let vertex = wgpu::ShaderStages::VERTEX;
+        let textures = BindGroupLayoutBuilder::new()
+            .texture_2d(vertex, true)
+            .sampler(vertex, true);
+        assert_eq!(
+            check_textured_quad(textures),
+            Err(LayoutError::Visibility {
+                group: 1,
+                binding: 0,
+                shader: wgpu::ShaderStages::FRAGMENT,
+                layout: vertex,
+            })
+        );
+    }
+
+    #[test]
+    fn reports_unfilterable_texture_with_filtering_sampler() {
+        let fragment = wgpu::ShaderStages::FRAGMENT;
+        let textures = BindGroupLayoutBuilder::new()
+            .texture_2d(fragment, false)
+            .sampler(fragment, true);
+        assert_eq!(
+            check_textured_quad(textures),
+            Err(LayoutError::SamplerFiltering {
+                group: 1,
+                binding: 0,
+                sampler: (1, 1),
+                filterable: false,
+            })
+        );
+    }
+
+    #[test]
+    fn reports_filterable_texture_with_non_filtering_sampler() {
+        let fragment = wgpu::ShaderStages::FRAGMENT;
+        let textures = BindGroupLayoutBuilder::new()
+            .texture_2d(fragment, true)
+            .sampler(fragment, false);
+        assert_eq!(
+            check_textured_quad(textures),
+            Err(LayoutError::SamplerFiltering {
+                group: 1,
+                binding: 0,
+                sampler: (1, 1),
+                filterable: true,
+            })
+        );
+        assert_eq!(check_textured_quad(crate::gfx::texture_layout(true)), Ok(()));
+    }
+}
@@ -0,0 +1,76 @@
+use crate::camera::{Camera, ViewUniform};
+use crate::color::Color;
+use crate::gfx::{DepthBuffer, GfxRenderData, Renderer};
+use crate::texture::TextureHandle;
+use crate::uniform::UniformBuffer;
+
+// An offscreen texture drawn into by its own renderers every frame, before the screen. The
+// texture is registered with `Gfx` and can be drawn with a `TexturedQuad` like any other, by
+// the screen or by a later target. It has the surface format so any renderer can draw into it,
+// but a target's renderers must not sample the target itself.
+pub struct RenderTarget {
+    texture: TextureHandle,
+    pub clear_color: Color,
+    pub camera: Camera,
+    size: winit::dpi::PhysicalSize<u32>,
+    view: UniformBuffer<ViewUniform>,
+    depth: Option<DepthBuffer>,
+    renderers: Vec<Box<dyn Renderer>>,
+}
+impl RenderTarget {
+    pub(crate) fn new(
+        data: &GfxRenderData,
+        texture: TextureHandle,
+        size: winit::dpi::PhysicalSize<u32>,
+    ) -> Self {
+        let camera = Camera::default();
+        let view = UniformBuffer::new(
+            &data.device,
+            wgpu::ShaderStages::VERTEX,
+            &ViewUniform::new(size, &camera, data.snap_to_pixels),
+            Some("render_target_view"),
+        );
+        // Pipelines are built against the screen's depth buffer, so targets need a matching one.
+        let depth = data
+            .depth
+            .as_ref()
+            .map(|_| DepthBuffer::new(&data.device, size));
+        Self {
+            texture,
+            clear_color: Color::TRANSPARENT,
+            camera,
+            size,
+            view,
+            depth,
+            renderers: vec![],
+        }
+    }
+    pub fn texture(&self) -> TextureHandle {
+        self.texture
+    }
+    pub fn size(&self) -> (u32, u32) {
+        (self.size.width, self.size.height)
+    }
+    pub fn add_renderer(&mut self, renderer: Box<dyn Renderer>) -> usize {
+        self.renderers.push(renderer);
+        self.renderers.len() - 1
+    }
+    pub fn renderer_mut<T: Renderer>(&mut self, index: usize) -> Option<&mut T> {
+        let renderer: &mut dyn std::any::Any = self.renderers.get_mut(index)?.as_mut();
+        renderer.downcast_mut()
+    }
+    pub(crate) fn renderers(&self) -> &[Box<dyn Renderer>] {
+        &self.renderers
+    }
+    pub(crate) fn renderers_mut(&mut self) -> &mut [Box<dyn Renderer>] {
+        &mut self.renderers
+    }
+    // Swaps the target's size, camera, view uniform and depth buffer with the screen's, so
+    // renderers draw into the target without knowing about it. Called again to swap back.
+    pub(crate) fn swap(&mut self, data: &mut GfxRenderData) {
+        std::mem::swap(&mut self.size, &mut data.size);
+        std::mem::swap(&mut self.camera, &mut data.camera);
+        std::mem::swap(&mut self.view, &mut data.view);
+        std::mem::swap(&mut self.depth, &mut data.depth);
+    }
+}
@@ -0,0 +1,234 @@
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+
+use notify::Watcher;
+
+use crate::gfx::GfxRenderData;
+use crate::preprocessor::{Preprocessed, Preprocessor};
+use crate::reflect::ShaderReflection;
+
+#[derive(Debug)]
+pub struct ShaderError {
+    pub file: String,
+    pub message: String,
+    pub line: Option<u32>,
+    pub column: Option<u32>,
+    // Full diagnostic with the offending source line, as printed by naga.
+    pub report: String,
+}
+impl std::fmt::Display for ShaderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (self.line, self.column) {
+            (Some(line), Some(column)) => {
+                write!(f, "{}:{}:{}: {}", self.file, line, column, self.message)
+            }
+            _ => write!(f, "{}: {}", self.file, self.message),
+        }
+    }
+}
+impl std::error::Error for ShaderError {}
+
+pub fn validate(source: &str) -> Result<naga::Module, ShaderError> {
+    let module = naga::front::wgsl::parse_str(source).map_err(|err| {
+        let location = err.location(source);
+        ShaderError {
+            file: "wgsl".to_string(),
+            message: err.message().to_string(),
+            line: location.map(|l| l.line_number),
+            column: location.map(|l| l.line_position),
+            report: err.emit_to_string(source),
+        }
+    })?;
+    naga::valid::Validator::new(
+        naga::valid::ValidationFlags::all(),
+        naga::valid::Capabilities::all(),
+    )
+    .validate(&module)
+    .map_err(|err| {
+        let location = err.location(source);
+        ShaderError {
+            file: "wgsl".to_string(),
+            message: err.as_inner().to_string(),
+            line: location.map(|l| l.line_number),
+            column: location.map(|l| l.line_position),
+            report: err.emit_to_string(source),
+        }
+    })?;
+    Ok(module)
+}
+
+// Preprocesses and validates a shader. Error locations point into the file or module the
+// offending line came from rather than into the preprocessed output.
+pub fn compile(
+    preprocessor: &Preprocessor,
+    file: &str,
+    source: &str,
+    defines: &[(&str, &str)],
+) -> Result<(Preprocessed, naga::Module), ShaderError> {
+    let preprocessed = preprocessor
+        .process(file, source, defines)
+        .map_err(|err| ShaderError {
+            file: err.file.clone(),
+            message: err.message.clone(),
+            line: Some(err.line),
+            column: None,
+            report: err.to_string(),
+        })?;
+    match validate(&preprocessed.code) {
+        Ok(module) => Ok((preprocessed, module)),
+        Err(mut err) => {
+            let origin = err.line.and_then(|line| preprocessed.origin(line));
+            match origin {
+                Some((origin_file, origin_line)) => {
+                    err.file = origin_file.to_string();
+                    err.line = Some(origin_line);
+                }
+                None => err.file = file.to_string(),
+            }
+            Err(err)
+        }
+    }
+}
+
+struct Watch {
+    // The shader's own file, which may not exist.
+    path: PathBuf,
+    // Module files the current code included.
+    modules: Vec<PathBuf>,
+    _watcher: notify::RecommendedWatcher,
+    events: mpsc::Receiver<notify::Result<notify::Event>>,
+}
+
+// A preprocessed shader built from source embedded in the binary. When shader hot reloading
+// is enabled on `Gfx` the file of the same name and the modules it includes are read from the
+// shader directory instead, and reloaded whenever one of them changes. Only sources that pass
+// validation replace the current code, so a renderer can keep its last good pipeline.
+pub struct Shader {
+    name: String,
+    defines: Vec<(String, String)>,
+    embedded: String,
+    code: String,
+    reflection: ShaderReflection,
+    watch: Option<Watch>,
+}
+impl Shader {
+    pub fn new(data: &GfxRenderData, name: &str, embedded: &str, defines: &[(&str, &str)]) -> Self {
+        Self::try_new(data, name, embedded, defines)
+            .unwrap_or_else(|err| panic!("Invalid shader {}\n{}", err, err.report))
+    }
+    pub fn try_new(
+        data: &GfxRenderData,
+        name: &str,
+        embedded: &str,
+        defines: &[(&str, &str)],
+    ) -> Result<Self, ShaderError> {
+        let (preprocessed, module) = compile(&data.preprocessor, name, embedded, defines)?;
+        let (code, reflection) = (preprocessed.code, ShaderReflection::new(&module));
+        let mut shader = Self {
+            name: name.to_string(),
+            defines: defines
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+            embedded: embedded.to_string(),
+            code,
+            reflection,
+            watch: None,
+        };
+        if let Some(dir) = data.shader_dir.as_ref().filter(|dir| dir.exists()) {
+            match watch(dir, name) {
+                Ok(watch) => {
+                    shader.watch = Some(watch);
+                    shader.reload(&data.preprocessor);
+                }
+                Err(err) => eprintln!("Failed to watch {}: {}", dir.display(), err),
+            }
+        }
+        Ok(shader)
+    }
+    pub fn code(&self) -> &str {
+        &self.code
+    }
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    pub fn reflection(&self) -> &ShaderReflection {
+        &self.reflection
+    }
+    // Returns true if the file or an included module changed and the new code validated.
+    pub fn poll(&mut self, data: &GfxRenderData) -> bool {
+        let Some(watch) = self.watch.as_ref() else {
+            return false;
+        };
+        let changed = watch.events.try_iter().any(|event| match event {
+            Ok(event) => {
+                (event.kind.is_modify() || event.kind.is_create())
+                    && event.paths.iter().any(|path| {
+                        *path == watch.path || watch.modules.contains(path)
+                    })
+            }
+            Err(_) => false,
+        });
+        changed && self.reload(&data.preprocessor)
+    }
+
+    fn reload(&mut self, preprocessor: &Preprocessor) -> bool {
+        let Some(watch) = self.watch.as_ref() else {
+            return false;
+        };
+        let source = if watch.path.exists() {
+            match std::fs::read_to_string(&watch.path) {
+                Ok(source) => source,
+                Err(err) => {
+                    eprintln!("Failed to read {}: {}", watch.path.display(), err);
+                    return false;
+                }
+            }
+        } else {
+            self.embedded.clone()
+        };
+        let defines = self
+            .defines
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+            .collect::<Vec<_>>();
+        match compile(preprocessor, &self.name, &source, &defines) {
+            Ok((preprocessed, module)) => {
+                let modules = preprocessed.files().iter().map(|path| canonical(path));
+                if let Some(watch) = self.watch.as_mut() {
+                    watch.modules = modules.collect();
+                }
+                if preprocessed.code == self.code {
+                    return false;
+                }
+                self.code = preprocessed.code;
+                self.reflection = ShaderReflection::new(&module);
+                true
+            }
+            Err(err) => {
+                eprintln!("{}\n{}", err, err.report);
+                false
+            }
+        }
+    }
+}
+
+// Watches the shader directory, where the shader and any modules it includes may live.
+// Editors usually save by replacing files, so the directory is watched rather than the files.
+fn watch(dir: &Path, name: &str) -> notify::Result<Watch> {
+    let dir = dir.canonicalize()?;
+    let (sender, events) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(sender)?;
+    watcher.watch(&dir, notify::RecursiveMode::NonRecursive)?;
+    Ok(Watch {
+        path: dir.join(name),
+        modules: vec![],
+        _watcher: watcher,
+        events,
+    })
+}
+
+// Event paths are canonical, so watched paths are compared in the same form.
+fn canonical(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
+}
@@ -0,0 +1,274 @@
+use std::f32::consts::TAU;
+use std::rc::Rc;
+
+use crate::color::Color;
+use crate::gfx::{view_layout, Gfx, GfxRenderData, Renderer};
+use crate::pipeline::PipelineDescriptor;
+use crate::quad::sort_for_depth;
+use crate::reflect::LayoutError;
+use crate::shader::Shader;
+use crate::vertex::Vertex;
+
+pub(crate) const SHAPE_SHADER: &str = include_str!("shape.wgsl");
+
+// A circle or ellipse, optionally stroked and cut down to a sector. Angles are in radians from
+// +x towards +y, which is clockwise on screen.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Shape {
+    // Center.
+    pub pos: [f32; 2],
+    // Layer in 0..1, higher layers in front.
+    pub z: f32,
+    // Equal for circles.
+    pub radii: [f32; 2],
+    pub color: Color,
+    // Width of the outline drawn inside the edge, filled when `None`. Outlines of ellipses are
+    // inset by the width along both axes, so they are only roughly even in between. Outlines
+    // thinner than a pixel fade out with their width.
+    pub stroke: Option<f32>,
+    pub start_angle: f32,
+    // `TAU` or more draws the whole shape.
+    pub sweep: f32,
+}
+impl Shape {
+    pub fn ellipse(pos: [f32; 2], radii: [f32; 2], color: Color) -> Self {
+        Self {
+            pos,
+            z: 0.0,
+            radii,
+            color,
+            stroke: None,
+            start_angle: 0.0,
+            sweep: TAU,
+        }
+    }
+    pub fn circle(pos: [f32; 2], radius: f32, color: Color) -> Self {
+        Self::ellipse(pos, [radius, radius], color)
+    }
+    pub fn ring(pos: [f32; 2], radius: f32, inner_radius: f32, color: Color) -> Self {
+        Self {
+            stroke: Some(radius - inner_radius),
+            ..Self::circle(pos, radius, color)
+        }
+    }
+    pub fn pie(pos: [f32; 2], radius: f32, start_angle: f32, sweep: f32, color: Color) -> Self {
+        Self {
+            start_angle,
+            sweep,
+            ..Self::circle(pos, radius, color)
+        }
+    }
+    // A stroke `thickness` wide along the circle of `radius`.
+    pub fn arc(
+        pos: [f32; 2],
+        radius: f32,
+        thickness: f32,
+        start_angle: f32,
+        sweep: f32,
+        color: Color,
+    ) -> Self {
+        Self {
+            stroke: Some(thickness),
+            ..Self::pie(pos, radius + thickness * 0.5, start_angle, sweep, color)
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub(crate) struct ShapeRaw {
+    pos: [f32; 3],
+    size: [f32; 2],
+    color: [f32; 4],
+    // Stroke, or -1 when filled, start angle and sweep.
+    params: [f32; 3],
+}
+impl From<&Shape> for ShapeRaw {
+    fn from(shape: &Shape) -> Self {
+        let Color { r, g, b, a } = shape.color;
+        // A sector is given by its start and a positive sweep.
+        let (start, sweep) = if shape.sweep < 0.0 {
+            (shape.start_angle + shape.sweep, -shape.sweep)
+        } else {
+            (shape.start_angle, shape.sweep)
+        };
+        Self {
+            pos: [shape.pos[0], shape.pos[1], shape.z],
+            size: [shape.radii[0] * 2.0, shape.radii[1] * 2.0],
+            color: [r, g, b, a],
+            params: [shape.stroke.map_or(-1.0, |stroke| stroke.max(0.0)), start, sweep],
+        }
+    }
+}
+impl ShapeRaw {
+    pub(crate) fn layout() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<ShapeRaw>() as u64,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &[
+                wgpu::VertexAttribute {
+                    format: wgpu::VertexFormat::Float32x3,
+                    offset: 0,
+                    shader_location: 4,
+                },
+                wgpu::VertexAttribute {
+                    format: wgpu::VertexFormat::Float32x2,
+                    offset: std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
+                    shader_location: 5,
+                },
+                wgpu::VertexAttribute {
+                    format: wgpu::VertexFormat::Float32x4,
+                    offset: std::mem::size_of::<[f32; 5]>() as wgpu::BufferAddress,
+                    shader_location: 6,
+                },
+                wgpu::VertexAttribute {
+                    format: wgpu::VertexFormat::Float32x3,
+                    offset: std::mem::size_of::<[f32; 9]>() as wgpu::BufferAddress,
+                    shader_location: 8,
+                },
+            ],
+        }
+    }
+}
+
+// Draws shapes as instances of the unit quad, anti-aliased in the fragment shader so they stay
+// crisp at any zoom. Their edges are always translucent, so every shape is blended back to
+// front after opaque geometry.
+pub struct ShapeRenderer {
+    instance_buffer: wgpu::Buffer,
+    pipeline: Rc<wgpu::RenderPipeline>,
+    shader: Shader,
+    shapes: Vec<Shape>,
+}
+impl ShapeRenderer {
+    pub fn new(gfx: &mut Gfx) -> Self {
+        let gfx = gfx.data.borrow_mut();
+        let instance_buffer = instance_buffer(&gfx.device, 0);
+        let shader = Shader::new(&gfx, "shape.wgsl", SHAPE_SHADER, &[]);
+        let pipeline = Self::create_pipeline(&gfx, &shader)
+            .unwrap_or_else(|err| panic!("{}: {}", shader.name(), err));
+        Self {
+            instance_buffer,
+            pipeline,
+            shader,
+            shapes: vec![],
+        }
+    }
+    pub fn add(&mut self, shape: Shape) {
+        self.shapes.push(shape);
+    }
+    pub fn clear(&mut self) {
+        self.shapes.clear();
+    }
+
+    pub(crate) fn create_pipeline(
+        gfx: &GfxRenderData,
+        shader: &Shader,
+    ) -> Result<Rc<wgpu::RenderPipeline>, LayoutError> {
+        Self::create_pipelines_with(gfx, shader, |gfx, desc| {
+            gfx.pipelines.get(&gfx.device, &desc.transparent())
+        })
+    }
+    // Checks the shader against the shape layouts and hands the descriptor to `build`.
+    pub(crate) fn create_pipelines_with<P>(
+        gfx: &GfxRenderData,
+        shader: &Shader,
+        build: impl Fn(&GfxRenderData, PipelineDescriptor) -> P,
+    ) -> Result<P, LayoutError> {
+        let vertex_layouts = [Vertex::layout(), ShapeRaw::layout()];
+        let reflection = shader.reflection();
+        reflection.check_vertex_layouts("vs_main", &vertex_layouts)?;
+        reflection.check_bind_group_layouts(&[view_layout().entries()])?;
+        let bind_group_layouts = [gfx.view.layout()];
+        let desc = PipelineDescriptor::new(gfx, shader, &vertex_layouts, &bind_group_layouts);
+        Ok(build(gfx, desc))
+    }
+}
+impl Renderer for ShapeRenderer {
+    fn prepare(&mut self, data: &GfxRenderData) {
+        if self.shader.poll(data) {
+            match Self::create_pipeline(data, &self.shader) {
+                Ok(pipeline) => self.pipeline = pipeline,
+                Err(err) => eprintln!("{}: {}", self.shader.name(), err),
+            }
+        }
+        let mut shapes = self.shapes.iter().collect::<Vec<_>>();
+        sort_for_depth(&mut shapes, data.depth.is_some(), |_| false, |shape| shape.z);
+        let instances = shapes.into_iter().map(ShapeRaw::from).collect::<Vec<_>>();
+        let bytes: &[u8] = bytemuck::cast_slice(&instances);
+        if bytes.len() as wgpu::BufferAddress > self.instance_buffer.size() {
+            let size = (bytes.len() as wgpu::BufferAddress).next_power_of_two();
+            self.instance_buffer = instance_buffer(&data.device, size);
+        }
+        data.queue.write_buffer(&self.instance_buffer, 0, bytes);
+    }
+    fn render<'a, 'b>(
+        &'a self,
+        _data: &'a GfxRenderData,
+        _render_pass: &mut wgpu::RenderPass<'b>,
+    )
+    where
+        'a: 'b
+    {
+    }
+    fn render_transparent<'a, 'b>(
+        &'a self,
+        data: &'a GfxRenderData,
+        render_pass: &mut wgpu::RenderPass<'b>,
+    )
+    where
+        'a: 'b
+    {
+        if self.shapes.is_empty() {
+            return;
+        }
+        render_pass.set_pipeline(&self.pipeline);
+        data.quad_geometry.set_buffers(render_pass);
+        render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
+        render_pass.set_bind_group(0, data.view.bind_group(), &[]);
+        let instances = 0..self.shapes.len() as u32;
+        render_pass.draw_indexed(0..data.quad_geometry.index_count(), 0, instances);
+    }
+}
+
+fn instance_buffer(device: &wgpu::Device, size: wgpu::BufferAddress) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("shape_instances"),
+        size: size.max(256),
+        usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reflect::check_shader;
+
+    #[test]
+    fn shapes_pack_into_instances() {
+        let arc = Shape::arc([1.0, 2.0], 10.0, 2.0, 1.0, -0.5, Color::WHITE);
+        let raw = ShapeRaw::from(&arc);
+        assert_eq!(raw.pos, [1.0, 2.0, 0.0]);
+        assert_eq!(raw.size, [22.0, 22.0]);
+        assert_eq!(raw.params, [2.0, 0.5, 0.5]);
+        let ring = ShapeRaw::from(&Shape::ring([0.0, 0.0], 5.0, 3.0, Color::WHITE));
+        assert_eq!(ring.params, [2.0, 0.0, TAU]);
+        // Degenerate outlines stay outlines rather than filling the shape.
+        let empty = ShapeRaw::from(&Shape::ring([0.0, 0.0], 5.0, 6.0, Color::WHITE));
+        assert_eq!(empty.params[0], 0.0);
+        let circle = ShapeRaw::from(&Shape::circle([0.0, 0.0], 5.0, Color::WHITE));
+        assert_eq!(circle.params[0], -1.0);
+    }
+
+    // Batcher masks are drawn with the ALPHA_MASK variant, which has to match the same layouts.
+    #[test]
+    fn shape_shader_matches_layouts() {
+        let vertex_layouts = [Vertex::layout(), ShapeRaw::layout()];
+        let view = view_layout();
+        let groups = [view.entries()];
+        for defines in [&[][..], &[("ALPHA_MASK", "")]] {
+            check_shader("shape.wgsl", SHAPE_SHADER, defines, &vertex_layouts, &groups);
+        }
+    }
+}
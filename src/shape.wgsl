@@ -0,0 +1,86 @@
+#define INSTANCE_COLOR
+#include "quad_transform"
+
+struct ShapeOutput {
+    @builtin(position) pos: vec4<f32>,
+    @location(0) color: vec4<f32>,
+    // Offset from the shape's center, in pixels.
+    @location(1) local: vec2<f32>,
+    @location(2) @interpolate(flat) radii: vec2<f32>,
+    // Stroke thickness in pixels, or negative when filled, then the start and sweep of the
+    // sector.
+    @location(3) @interpolate(flat) params: vec3<f32>,
+};
+
+const TAU: f32 = 6.28318530718;
+
+@vertex
+fn vs_main(
+    @location(0) vin: vec2<f32>,
+    @location(1) uv: vec2<f32>,
+    instance: Instance,
+    @location(8) params: vec3<f32>,
+) -> ShapeOutput {
+    // Grown by a pixel on each side to fit the anti-aliased edge.
+    let margin = 1.0 / view.zoom;
+    let half_size = instance.size * 0.5 + margin;
+    var out: ShapeOutput;
+    out.pos = quad_to_clip(vin, instance.pos, half_size * 2.0);
+    out.color = instance.color;
+    out.local = vec2<f32>(vin.x, -vin.y) * half_size * view.zoom;
+    out.radii = instance.size * 0.5 * view.zoom;
+    out.params = vec3<f32>(params.x * view.zoom, params.yz);
+    return out;
+}
+
+// Distance to an ellipse, exact for circles and within a pixel or so of the edge otherwise.
+fn ellipse(p: vec2<f32>, radii: vec2<f32>) -> f32 {
+    let r = max(radii, vec2<f32>(1e-4));
+    let k = length(p / r);
+    let gradient = length(p / (r * r));
+    if gradient <= 0.0 {
+        return -min(r.x, r.y);
+    }
+    return k * (k - 1.0) / gradient;
+}
+
+// Distance to the sector `sweep` radians wide starting at `start`.
+fn sector(p: vec2<f32>, start: f32, sweep: f32) -> f32 {
+    if sweep >= TAU {
+        return -1e9;
+    }
+    // Folds the sector onto its bisector along +x.
+    let bisector = start + sweep * 0.5;
+    let axis = vec2<f32>(cos(bisector), sin(bisector));
+    let q = vec2<f32>(dot(p, axis), abs(axis.x * p.y - axis.y * p.x));
+    let half = max(sweep, 0.0) * 0.5;
+    let edge = vec2<f32>(cos(half), sin(half));
+    let distance = length(q - edge * max(dot(q, edge), 0.0));
+    return select(distance, -distance, edge.y * q.x - edge.x * q.y > 0.0);
+}
+
+@fragment
+fn fs_main(in: ShapeOutput) -> @location(0) vec4<f32> {
+    var distance = ellipse(in.local, in.radii);
+    var thickness = 1.0;
+    if in.params.x >= 0.0 {
+        // Strokes thinner than a pixel cover it by their width.
+        thickness = min(in.params.x, 1.0);
+        let inner = in.radii - in.params.x;
+        if all(inner > vec2<f32>(0.0)) {
+            distance = max(distance, -ellipse(in.local, inner));
+        }
+    }
+    distance = max(distance, sector(in.local, in.params.y, in.params.z));
+#ifdef ALPHA_MASK
+    // Drawn into the stencil buffer only, covering the shape up to the middle of its edge.
+    if distance > 0.0 || in.color.a < 0.5 {
+        discard;
+    }
+#endif
+    let coverage = min(saturate(0.5 - distance), thickness);
+    if coverage <= 0.0 {
+        discard;
+    }
+    return vec4<f32>(in.color.rgb, in.color.a * coverage);
+}
@@ -1,70 +1,304 @@
-use crate::gfx::Gfx;
+use crate::bind_group::BindGroupBuilder;
+use crate::gfx::{Gfx, GfxRenderData};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct TextureHandle(pub(crate) usize);
 
 pub struct Texture {
+    pub texture: wgpu::Texture,
+    pub view: wgpu::TextureView,
+    pub sampler: wgpu::Sampler,
     pub bind_group: wgpu::BindGroup,
+    pub format: wgpu::TextureFormat,
+    pub filterable: bool,
+    // False if any texel may be translucent. Quads using the texture are then blended.
+    pub opaque: bool,
+    // Bumped whenever the contents are rewritten in place, for code that copies textures.
+    pub(crate) revision: u32,
 }
 impl Texture {
     pub fn from_file(gfx: &mut Gfx, path: &str) -> Self {
+        Self::from_file_with_format(gfx, path, default_format(path.as_ref()))
+    }
+    pub fn from_file_with_format(gfx: &mut Gfx, path: &str, format: wgpu::TextureFormat) -> Self {
+        let bytes = std::fs::read(path).expect("Failed to load texture.");
+        let image = image::load_from_memory(&bytes).unwrap();
+        Self::from_image(gfx, &image, format).unwrap_or_else(|err| panic!("{}: {}", path, err))
+    }
+    // Fails if the image can't be converted to `format`.
+    pub fn from_image(
+        gfx: &mut Gfx,
+        image: &image::DynamicImage,
+        format: wgpu::TextureFormat,
+    ) -> Result<Self, String> {
         let data = gfx.data.borrow_mut();
-        let diffuse_bytes = std::fs::read(path).expect("Failed to load texture.");
-        let diffuse_image = image::load_from_memory(&diffuse_bytes).unwrap();
-        let diffuse_rgba = diffuse_image.to_rgba8();
-        use image::GenericImageView;
-        let dimensions = diffuse_image.dimensions();
+        let bytes = image_bytes(image, format)?;
+        Ok(Self::create(&data, image.width(), image.height(), format, &bytes))
+    }
+    pub fn from_raw(
+        gfx: &mut Gfx,
+        width: u32,
+        height: u32,
+        format: wgpu::TextureFormat,
+        bytes: &[u8],
+    ) -> Self {
+        let data = gfx.data.borrow_mut();
+        Self::create(&data, width, height, format, bytes)
+    }
+    pub fn size(&self) -> (u32, u32) {
+        (self.texture.width(), self.texture.height())
+    }
+    // Writes into the existing texture when the size and format still match, otherwise the
+    // texture and its bind group are recreated.
+    pub(crate) fn replace(
+        data: &mut GfxRenderData,
+        handle: TextureHandle,
+        image: &image::DynamicImage,
+        format: wgpu::TextureFormat,
+    ) -> Result<(), String> {
+        let bytes = image_bytes(image, format)?;
+        let texture = data.texture(handle);
+        if texture.format == format && texture.size() == (image.width(), image.height()) {
+            texture.write(&data.queue, &bytes);
+            let texture = data.texture_mut(handle);
+            texture.opaque = is_opaque(format, &bytes);
+            texture.revision += 1;
+        } else {
+            let texture = Self::create(data, image.width(), image.height(), format, &bytes);
+            *data.texture_mut(handle) = texture;
+        }
+        Ok(())
+    }
+    pub(crate) fn write(&self, queue: &wgpu::Queue, bytes: &[u8]) {
+        let (width, height) = self.size();
+        queue.write_texture(
+            self.texture.as_image_copy(),
+            bytes,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(self.format.block_copy_size(None).unwrap() * width),
+                rows_per_image: Some(height),
+            },
+            self.texture.size(),
+        );
+    }
+
+    pub(crate) fn create(
+        data: &GfxRenderData,
+        width: u32,
+        height: u32,
+        format: wgpu::TextureFormat,
+        bytes: &[u8],
+    ) -> Self {
+        let mut texture = Self::empty(data, width, height, format, wgpu::TextureUsages::empty());
+        texture.opaque = is_opaque(format, bytes);
+        texture.write(&data.queue, bytes);
+        texture
+    }
+    // A texture that can be drawn into as well as sampled.
+    pub(crate) fn render_target(
+        data: &GfxRenderData,
+        width: u32,
+        height: u32,
+        format: wgpu::TextureFormat,
+    ) -> Self {
+        let usage = wgpu::TextureUsages::RENDER_ATTACHMENT;
+        Self::empty(data, width, height, format, usage)
+    }
+    // Uninitialized and assumed translucent. `usage` is added to the usages every texture has.
+    fn empty(
+        data: &GfxRenderData,
+        width: u32,
+        height: u32,
+        format: wgpu::TextureFormat,
+        usage: wgpu::TextureUsages,
+    ) -> Self {
         let texture_size = wgpu::Extent3d {
-            width: dimensions.0,
-            height: dimensions.1,
+            width,
+            height,
             depth_or_array_layers: 1,
         };
-        let diffuse_texture = data.device.create_texture(&wgpu::TextureDescriptor {
+        let texture = data.device.create_texture(&wgpu::TextureDescriptor {
             label: None,
             size: texture_size,
             mip_level_count: 1,
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Rgba8UnormSrgb,
-            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            format,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_DST
+                | wgpu::TextureUsages::COPY_SRC
+                | usage,
             view_formats: &[],
         });
-        data.queue.write_texture(
-            wgpu::ImageCopyTexture {
-                texture: &diffuse_texture,
-                mip_level: 0,
-                origin: wgpu::Origin3d::ZERO,
-                aspect: wgpu::TextureAspect::All,
-            },
-            &diffuse_rgba,
-            wgpu::ImageDataLayout {
-                offset: 0,
-                bytes_per_row: Some(4 * dimensions.0),
-                rows_per_image: Some(dimensions.1),
-            },
-            texture_size,
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let filterable = matches!(
+            format.sample_type(None, Some(data.device.features())),
+            Some(wgpu::TextureSampleType::Float { filterable: true })
         );
-        let texture_view = diffuse_texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = data.device.create_sampler(&if filterable {
+            wgpu::SamplerDescriptor {
+                address_mode_u: wgpu::AddressMode::ClampToEdge,
+                address_mode_v: wgpu::AddressMode::ClampToEdge,
+                address_mode_w: wgpu::AddressMode::ClampToEdge,
+                mag_filter: wgpu::FilterMode::Linear,
+                min_filter: wgpu::FilterMode::Nearest,
+                mipmap_filter: wgpu::FilterMode::Nearest,
+                ..Default::default()
+            }
+        } else {
+            wgpu::SamplerDescriptor {
+                address_mode_u: wgpu::AddressMode::ClampToEdge,
+                address_mode_v: wgpu::AddressMode::ClampToEdge,
+                address_mode_w: wgpu::AddressMode::ClampToEdge,
+                ..Default::default()
+            }
+        });
+        let bind_group = BindGroupBuilder::new()
+            .texture_view(&view)
+            .sampler(&sampler)
+            .build(
+                &data.device,
+                data.texture_bind_group_layout(filterable),
+                Some("diffuse_bind_group"),
+            );
+        Self {
+            texture,
+            view,
+            sampler,
+            bind_group,
+            format,
+            filterable,
+            opaque: false,
+            revision: 0,
+        }
+    }
+}
+
+// A 3D texture sampled with linear filtering, e.g. a color lookup table.
+pub struct Texture3d {
+    pub texture: wgpu::Texture,
+    pub view: wgpu::TextureView,
+    pub sampler: wgpu::Sampler,
+    pub format: wgpu::TextureFormat,
+}
+impl Texture3d {
+    pub fn from_raw(
+        gfx: &mut Gfx,
+        size: [u32; 3],
+        format: wgpu::TextureFormat,
+        bytes: &[u8],
+    ) -> Self {
+        let data = gfx.data.borrow_mut();
+        Self::create(&data, size, format, bytes)
+    }
+    pub fn size(&self) -> [u32; 3] {
+        let size = self.texture.size();
+        [size.width, size.height, size.depth_or_array_layers]
+    }
+
+    pub(crate) fn create(
+        data: &GfxRenderData,
+        size: [u32; 3],
+        format: wgpu::TextureFormat,
+        bytes: &[u8],
+    ) -> Self {
+        let [width, height, depth] = size;
+        let texture_size = wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: depth,
+        };
+        let texture = data.device.create_texture(&wgpu::TextureDescriptor {
+            label: None,
+            size: texture_size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D3,
+            format,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
         let sampler = data.device.create_sampler(&wgpu::SamplerDescriptor {
             address_mode_u: wgpu::AddressMode::ClampToEdge,
             address_mode_v: wgpu::AddressMode::ClampToEdge,
             address_mode_w: wgpu::AddressMode::ClampToEdge,
             mag_filter: wgpu::FilterMode::Linear,
-            min_filter: wgpu::FilterMode::Nearest,
-            mipmap_filter: wgpu::FilterMode::Nearest,
+            min_filter: wgpu::FilterMode::Linear,
             ..Default::default()
         });
-        let bind_group = data.device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &data.texture_bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&texture_view),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::Sampler(&sampler),
-                },
-            ],
-            label: Some("diffuse_bind_group"),
-        });
-        Self { bind_group }
+        data.queue.write_texture(
+            texture.as_image_copy(),
+            bytes,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(format.block_copy_size(None).unwrap() * width),
+                rows_per_image: Some(height),
+            },
+            texture_size,
+        );
+        Self {
+            texture,
+            view,
+            sampler,
+            format,
+        }
+    }
+}
+
+// Radiance and OpenEXR files are loaded as half floats, everything else as 8-bit sRGB.
+pub fn default_format(path: &std::path::Path) -> wgpu::TextureFormat {
+    match path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_ascii_lowercase())
+        .as_deref()
+    {
+        Some("hdr") | Some("exr") => wgpu::TextureFormat::Rgba16Float,
+        _ => wgpu::TextureFormat::Rgba8UnormSrgb,
     }
 }
+
+// Formats that aren't recognised are assumed to be translucent.
+fn is_opaque(format: wgpu::TextureFormat, bytes: &[u8]) -> bool {
+    match format {
+        wgpu::TextureFormat::R8Unorm | wgpu::TextureFormat::R32Float => true,
+        wgpu::TextureFormat::Rgba8UnormSrgb | wgpu::TextureFormat::Rgba8Unorm => {
+            bytes.chunks_exact(4).all(|texel| texel[3] == u8::MAX)
+        }
+        wgpu::TextureFormat::Rgba16Float => bytes
+            .chunks_exact(8)
+            .all(|texel| half::f16::from_le_bytes([texel[6], texel[7]]).to_f32() >= 1.0),
+        wgpu::TextureFormat::Rgba32Float => bytes
+            .chunks_exact(16)
+            .all(|texel| f32::from_le_bytes([texel[12], texel[13], texel[14], texel[15]]) >= 1.0),
+        _ => false,
+    }
+}
+
+// Converts `image` to the texel layout of `format`, one of the formats images can be loaded as.
+pub(crate) fn image_bytes(
+    image: &image::DynamicImage,
+    format: wgpu::TextureFormat,
+) -> Result<Vec<u8>, String> {
+    let bytes = match format {
+        wgpu::TextureFormat::Rgba8UnormSrgb | wgpu::TextureFormat::Rgba8Unorm => {
+            image.to_rgba8().into_raw()
+        }
+        wgpu::TextureFormat::R8Unorm => image.to_luma8().into_raw(),
+        wgpu::TextureFormat::Rgba16Float => image
+            .to_rgba32f()
+            .iter()
+            .flat_map(|c| half::f16::from_f32(*c).to_le_bytes())
+            .collect(),
+        wgpu::TextureFormat::Rgba32Float => {
+            bytemuck::cast_slice(&image.to_rgba32f().into_raw()).to_vec()
+        }
+        wgpu::TextureFormat::R32Float => {
+            bytemuck::cast_slice(&image.to_luma32f().into_raw()).to_vec()
+        }
+        _ => return Err(format!("Unsupported texture format {:?}", format)),
+    };
+    Ok(bytes)
+}
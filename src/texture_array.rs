@@ -0,0 +1,256 @@
+use crate::bind_group::{BindGroupBuilder, BindGroupLayoutBuilder};
+use crate::gfx::GfxRenderData;
+use crate::texture::TextureHandle;
+
+// Features needed to index a binding array of textures per instance.
+pub const BINDLESS_FEATURES: wgpu::Features = wgpu::Features::TEXTURE_BINDING_ARRAY.union(
+    wgpu::Features::SAMPLED_TEXTURE_AND_STORAGE_BUFFER_ARRAY_NON_UNIFORM_INDEXING,
+);
+
+// Textures that can be drawn together in one call, each addressed by a per-instance index.
+pub(crate) enum TextureStorage {
+    Layers(TextureArray),
+    Bindless(BindlessTextures),
+}
+impl TextureStorage {
+    // Returns the index to draw `handle` with, or None if it has to be drawn on its own.
+    pub(crate) fn slot(
+        &mut self,
+        data: &GfxRenderData,
+        encoder: &mut wgpu::CommandEncoder,
+        handle: TextureHandle,
+    ) -> Option<u32> {
+        match self {
+            Self::Layers(array) => array.layer(data, encoder, handle),
+            Self::Bindless(bindless) => bindless.slot(data, handle),
+        }
+    }
+    // Called after every slot for the frame has been handed out.
+    pub(crate) fn update(&mut self, data: &GfxRenderData) {
+        if let Self::Bindless(bindless) = self {
+            bindless.update(data);
+        }
+    }
+    pub(crate) fn bind_group(&self) -> Option<&wgpu::BindGroup> {
+        match self {
+            Self::Layers(array) => Some(&array.bind_group),
+            Self::Bindless(bindless) => bindless.bind_group.as_ref(),
+        }
+    }
+    pub(crate) fn layout(&self) -> &wgpu::BindGroupLayout {
+        match self {
+            Self::Layers(array) => &array.layout,
+            Self::Bindless(bindless) => &bindless.layout,
+        }
+    }
+    pub(crate) fn layout_builder(&self) -> BindGroupLayoutBuilder {
+        match self {
+            Self::Layers(array) => TextureArray::layout_builder(array.filterable),
+            Self::Bindless(bindless) => BindlessTextures::layout_builder(bindless.capacity),
+        }
+    }
+    pub(crate) fn defines(&self) -> Vec<(String, String)> {
+        match self {
+            Self::Layers(_) => vec![("TEXTURE_ARRAY".to_string(), String::new())],
+            Self::Bindless(bindless) => {
+                vec![("BINDLESS".to_string(), bindless.capacity.to_string())]
+            }
+        }
+    }
+}
+
+// One array texture whose layers are copies of registered textures of the same size and
+// format. Layers are handed out first come first served and never freed.
+pub(crate) struct TextureArray {
+    texture: wgpu::Texture,
+    layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    filterable: bool,
+    // Texture copied into each layer, with the GPU texture and revision it was copied from.
+    layers: Vec<(TextureHandle, wgpu::Id<wgpu::Texture>, u32)>,
+}
+impl TextureArray {
+    pub(crate) fn new(
+        data: &GfxRenderData,
+        width: u32,
+        height: u32,
+        format: wgpu::TextureFormat,
+        layers: u32,
+    ) -> Self {
+        let texture = data.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("texture_array"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: layers,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::D2Array),
+            ..Default::default()
+        });
+        let filterable = matches!(
+            format.sample_type(None, Some(data.device.features())),
+            Some(wgpu::TextureSampleType::Float { filterable: true })
+        );
+        let sampler = data.device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: if filterable {
+                wgpu::FilterMode::Linear
+            } else {
+                wgpu::FilterMode::Nearest
+            },
+            ..Default::default()
+        });
+        let layout = Self::layout_builder(filterable).build(&data.device, Some("texture_array"));
+        let bind_group = BindGroupBuilder::new()
+            .texture_view(&view)
+            .sampler(&sampler)
+            .build(&data.device, &layout, Some("texture_array"));
+        Self {
+            texture,
+            layout,
+            bind_group,
+            filterable,
+            layers: vec![],
+        }
+    }
+    pub(crate) fn layout_builder(filterable: bool) -> BindGroupLayoutBuilder {
+        BindGroupLayoutBuilder::new()
+            .texture(
+                wgpu::ShaderStages::FRAGMENT,
+                wgpu::TextureSampleType::Float { filterable },
+                wgpu::TextureViewDimension::D2Array,
+            )
+            .sampler(wgpu::ShaderStages::FRAGMENT, filterable)
+    }
+    // Copies the texture into its layer on first use and again whenever it was reloaded.
+    fn layer(
+        &mut self,
+        data: &GfxRenderData,
+        encoder: &mut wgpu::CommandEncoder,
+        handle: TextureHandle,
+    ) -> Option<u32> {
+        let texture = data.texture(handle);
+        if texture.format != self.texture.format()
+            || texture.size() != (self.texture.width(), self.texture.height())
+        {
+            return None;
+        }
+        let source = (handle, texture.texture.global_id(), texture.revision);
+        let layer = match self.layers.iter().position(|layer| layer.0 == handle) {
+            Some(layer) if self.layers[layer] == source => return Some(layer as u32),
+            Some(layer) => layer,
+            None if self.layers.len() < self.texture.depth_or_array_layers() as usize => {
+                self.layers.push(source);
+                self.layers.len() - 1
+            }
+            None => return None,
+        };
+        self.layers[layer] = source;
+        encoder.copy_texture_to_texture(
+            texture.texture.as_image_copy(),
+            wgpu::ImageCopyTexture {
+                texture: &self.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d {
+                    x: 0,
+                    y: 0,
+                    z: layer as u32,
+                },
+                aspect: wgpu::TextureAspect::All,
+            },
+            texture.texture.size(),
+        );
+        Some(layer as u32)
+    }
+}
+
+// A binding array of filterable textures of any size, sampled with one shared sampler. Slots
+// are handed out first come first served and never freed.
+pub(crate) struct BindlessTextures {
+    capacity: u32,
+    layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    slots: Vec<TextureHandle>,
+    // GPU textures the bind group was built from, so reloaded textures are picked up.
+    bound: Vec<wgpu::Id<wgpu::Texture>>,
+    bind_group: Option<wgpu::BindGroup>,
+}
+impl BindlessTextures {
+    // None if the device wasn't created with `BINDLESS_FEATURES`.
+    pub(crate) fn new(data: &GfxRenderData) -> Option<Self> {
+        if !data.device.features().contains(BINDLESS_FEATURES) {
+            return None;
+        }
+        let capacity = data.device.limits().max_sampled_textures_per_shader_stage.min(256);
+        let layout = Self::layout_builder(capacity).build(&data.device, Some("bindless_textures"));
+        let sampler = data.device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        Some(Self {
+            capacity,
+            layout,
+            sampler,
+            slots: vec![],
+            bound: vec![],
+            bind_group: None,
+        })
+    }
+    pub(crate) fn layout_builder(capacity: u32) -> BindGroupLayoutBuilder {
+        BindGroupLayoutBuilder::new()
+            .texture_2d(wgpu::ShaderStages::FRAGMENT, true)
+            .count(capacity)
+            .sampler(wgpu::ShaderStages::FRAGMENT, true)
+    }
+    fn slot(&mut self, data: &GfxRenderData, handle: TextureHandle) -> Option<u32> {
+        if !data.texture(handle).filterable {
+            return None;
+        }
+        if let Some(slot) = self.slots.iter().position(|slot| *slot == handle) {
+            return Some(slot as u32);
+        }
+        if self.slots.len() >= self.capacity as usize {
+            return None;
+        }
+        self.slots.push(handle);
+        Some(self.slots.len() as u32 - 1)
+    }
+    fn update(&mut self, data: &GfxRenderData) {
+        let textures = self
+            .slots
+            .iter()
+            .map(|handle| data.texture(*handle))
+            .collect::<Vec<_>>();
+        let ids = textures
+            .iter()
+            .map(|texture| texture.texture.global_id())
+            .collect::<Vec<_>>();
+        if textures.is_empty() || ids == self.bound {
+            return;
+        }
+        // Every element of the array has to be bound, so unused slots repeat the first texture.
+        let views = (0..self.capacity as usize)
+            .map(|i| &textures.get(i).unwrap_or(&textures[0]).view)
+            .collect::<Vec<_>>();
+        self.bind_group = Some(
+            BindGroupBuilder::new()
+                .texture_view_array(&views)
+                .sampler(&self.sampler)
+                .build(&data.device, &self.layout, Some("bindless_textures")),
+        );
+        self.bound = ids;
+    }
+}
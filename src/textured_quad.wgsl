@@ -1,15 +1,8 @@
-struct VertexOutput {
-    @builtin(position) pos: vec4<f32>,
-    @location(0) uv: vec2<f32>,
-};
-
-struct Instance {
-    @location(4) pos: vec2<f32>,
-    @location(5) size: vec2<f32>,
-};
-
-@group(0) @binding(0)
-var<uniform> aspect_ratio: vec2<u32>;
+#define INSTANCE_LAYER
+#include "quad_transform"
+#ifdef LIGHTING
+#include "lighting"
+#endif
 
 @vertex
 fn vs_main(
@@ -17,23 +10,70 @@ fn vs_main(
     @location(1) uv: vec2<f32>,
     instance: Instance,
 ) -> VertexOutput {
-    var scale: vec2<f32>;
-    var pos: vec2<f32>;
     var out: VertexOutput;
-    scale = instance.size / vec2<f32>(aspect_ratio);
-    pos = (instance.pos / vec2<f32>(aspect_ratio) * vec2<f32>(2.0, -2.0)) + vec2<f32>(-1.0, 1.0);
-    out.pos = vec4<f32>(vin, 0.0, 1.0) * vec4<f32>(scale, 0.0, 1.0) + vec4<f32>(pos, 0.0, 0.0);
+    out.pos = quad_to_clip(vin, instance.pos, instance.size);
+    out.color = vec4<f32>(1.0, 1.0, 1.0, 1.0);
     out.uv = uv;
+    out.layer = instance.layer;
+#ifdef LIGHTING
+    out.world = instance.pos.xy + vec2<f32>(vin.x, -vin.y) * instance.size * 0.5;
+#endif
     return out;
 }
 
+// TEXTURE_ARRAY samples layer `layer` of an array texture, BINDLESS (defined as the array
+// length) indexes a binding array of textures.
+#ifdef TEXTURE_ARRAY
+@group(1) @binding(0)
+var tex: texture_2d_array<f32>;
+#else
+#ifdef BINDLESS
+@group(1) @binding(0)
+var tex: binding_array<texture_2d<f32>, BINDLESS>;
+#else
 @group(1) @binding(0)
 var tex: texture_2d<f32>;
+#endif
+#endif
 
 @group(1) @binding(1)
 var samp: sampler;
 
+#ifdef LIGHTING
+// A flat normal map stands in for quads without one.
+@group(2) @binding(0)
+var normal_map: texture_2d<f32>;
+@group(2) @binding(1)
+var normal_sampler: sampler;
+#endif
+
+fn sample_texture(uv: vec2<f32>, layer: u32) -> vec4<f32> {
+#ifdef TEXTURE_ARRAY
+    return textureSample(tex, samp, uv, layer);
+#else
+#ifdef BINDLESS
+    return textureSample(tex[layer], samp, uv);
+#else
+    return textureSample(tex, samp, uv);
+#endif
+#endif
+}
+
 @fragment
 fn fs_main(vin: VertexOutput) -> @location(0) vec4<f32> {
-    return textureSample(tex, samp, vin.uv);
+#ifdef ALPHA_MASK
+    // Drawn into the stencil buffer only, covering the texels that are mostly opaque.
+    if sample_texture(vin.uv, vin.layer).a < 0.5 {
+        discard;
+    }
+#endif
+    var color = sample_texture(vin.uv, vin.layer);
+#ifdef TINT
+    color *= TINT;
+#endif
+#ifdef LIGHTING
+    let normal = normalize(textureSample(normal_map, normal_sampler, vin.uv).xyz * 2.0 - 1.0);
+    color = vec4<f32>(color.rgb * light_at(vin.world, normal), color.a);
+#endif
+    return color;
 }
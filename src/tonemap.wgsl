@@ -0,0 +1,36 @@
+#include "post_process"
+
+struct Tonemap {
+    exposure: f32,
+    bloom_intensity: f32,
+    // 0 for Reinhard, 1 for ACES.
+    curve: u32,
+};
+
+@group(1) @binding(0)
+var<uniform> effect_uniforms: Tonemap;
+
+@group(2) @binding(0)
+var bloom: texture_2d<f32>;
+@group(2) @binding(1)
+var bloom_sampler: sampler;
+
+fn reinhard(color: vec3<f32>) -> vec3<f32> {
+    return color / (1.0 + color);
+}
+
+// Narkowicz's fit of the ACES filmic curve.
+fn aces(color: vec3<f32>) -> vec3<f32> {
+    let mapped = (color * (2.51 * color + 0.03)) / (color * (2.43 * color + 0.59) + 0.14);
+    return clamp(mapped, vec3<f32>(0.0), vec3<f32>(1.0));
+}
+
+fn effect(in: PostInput) -> vec4<f32> {
+    let scene = sample_source(in.uv).rgb;
+    let glow = textureSample(bloom, bloom_sampler, in.uv).rgb;
+    let color = (scene + glow * effect_uniforms.bloom_intensity) * effect_uniforms.exposure;
+    if effect_uniforms.curve == 1u {
+        return vec4<f32>(aces(color), 1.0);
+    }
+    return vec4<f32>(reinhard(color), 1.0);
+}
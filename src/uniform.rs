@@ -0,0 +1,216 @@
+use std::marker::PhantomData;
+
+use wgpu::util::DeviceExt;
+
+use crate::bind_group::{BindGroupBuilder, BindGroupLayoutBuilder};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UniformSizeError {
+    pub size: usize,
+}
+impl std::fmt::Display for UniformSizeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "uniform types must be 4 or 8 bytes or a multiple of 16 bytes, found {} bytes",
+            self.size
+        )
+    }
+}
+impl std::error::Error for UniformSizeError {}
+
+// Scalars and vec2s may be bound directly, anything larger has to be padded out to a multiple
+// of 16 bytes like a std140 struct. Whether the fields line up is checked separately by
+// `check_uniform_layout`.
+pub fn check_uniform_size(size: usize) -> Result<(), UniformSizeError> {
+    match size {
+        4 | 8 => Ok(()),
+        size if size > 0 && size % 16 == 0 => Ok(()),
+        size => Err(UniformSizeError { size }),
+    }
+}
+
+// Uniform types bound to WGSL structs list where their scalars are, so the layout can be checked
+// against the shader's. WGSL aligns `vec3`s, `vec4`s and nested structs to 16 bytes where
+// `#[repr(C)]` doesn't, which would otherwise shift every later field. Implemented for `f32`,
+// `u32`, `i32` and arrays of them, and for structs by `impl_uniform!`.
+pub trait Uniform: bytemuck::Pod {
+    // Pushes the byte offset of every scalar, padding fields left out, starting at `base`.
+    fn scalars(base: u32, scalars: &mut Vec<u32>);
+}
+macro_rules! impl_uniform_scalar {
+    ($($ty:ty),*) => {
+        $(impl Uniform for $ty {
+            fn scalars(base: u32, scalars: &mut Vec<u32>) {
+                scalars.push(base);
+            }
+        })*
+    };
+}
+impl_uniform_scalar!(f32, u32, i32);
+impl<T: Uniform, const N: usize> Uniform for [T; N]
+where
+    [T; N]: bytemuck::Pod,
+{
+    fn scalars(base: u32, scalars: &mut Vec<u32>) {
+        for i in 0..N {
+            T::scalars(base + (i * std::mem::size_of::<T>()) as u32, scalars);
+        }
+    }
+}
+
+// Implements `Uniform` for a `#[repr(C)]` struct from its non-padding fields, in order:
+// `impl_uniform!(Vignette { strength, radius });`
+#[macro_export]
+macro_rules! impl_uniform {
+    ($ty:ty { $($field:ident),* $(,)? }) => {
+        impl $crate::uniform::Uniform for $ty {
+            fn scalars(base: u32, scalars: &mut Vec<u32>) {
+                $($crate::uniform::field_scalars(
+                    |value: &$ty| &value.$field,
+                    base + std::mem::offset_of!($ty, $field) as u32,
+                    scalars,
+                );)*
+            }
+        }
+    };
+}
+// Lets `impl_uniform!` name a field's type through a closure.
+#[doc(hidden)]
+pub fn field_scalars<S, T: Uniform>(_: fn(&S) -> &T, base: u32, scalars: &mut Vec<u32>) {
+    T::scalars(base, scalars);
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UniformLayoutError {
+    // Byte offsets of the scalars in WGSL and in the Rust type.
+    pub shader: Vec<u32>,
+    pub rust: Vec<u32>,
+}
+impl std::fmt::Display for UniformLayoutError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mismatch = self.shader.iter().zip(&self.rust).position(|(a, b)| a != b);
+        match mismatch {
+            Some(i) => write!(
+                f,
+                "scalar {} is at byte {} in WGSL but at byte {} in the Rust type",
+                i, self.shader[i], self.rust[i]
+            ),
+            None => write!(
+                f,
+                "WGSL has {} scalars but the Rust type has {}",
+                self.shader.len(),
+                self.rust.len()
+            ),
+        }
+    }
+}
+impl std::error::Error for UniformLayoutError {}
+
+// Checks that `T` puts its scalars at the offsets `shader` reflected for the WGSL type.
+pub fn check_uniform_layout<T: Uniform>(shader: &[u32]) -> Result<(), UniformLayoutError> {
+    let mut rust = vec![];
+    T::scalars(0, &mut rust);
+    if rust == shader {
+        Ok(())
+    } else {
+        Err(UniformLayoutError {
+            shader: shader.to_vec(),
+            rust,
+        })
+    }
+}
+
+// A uniform buffer holding a single `T` in a bind group of its own at binding 0.
+pub struct UniformBuffer<T: bytemuck::Pod> {
+    buffer: wgpu::Buffer,
+    layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    _marker: PhantomData<T>,
+}
+impl<T: bytemuck::Pod> UniformBuffer<T> {
+    pub fn new(
+        device: &wgpu::Device,
+        visibility: wgpu::ShaderStages,
+        value: &T,
+        label: Option<&str>,
+    ) -> Self {
+        if let Err(err) = check_uniform_size(std::mem::size_of::<T>()) {
+            panic!("{}: {}", std::any::type_name::<T>(), err);
+        }
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label,
+            contents: bytemuck::bytes_of(value),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let layout = Self::layout_builder(visibility).build(device, label);
+        let bind_group = BindGroupBuilder::new()
+            .buffer(&buffer)
+            .build(device, &layout, label);
+        Self {
+            buffer,
+            layout,
+            bind_group,
+            _marker: PhantomData,
+        }
+    }
+    pub fn layout_builder(visibility: wgpu::ShaderStages) -> BindGroupLayoutBuilder {
+        BindGroupLayoutBuilder::new().uniform(visibility)
+    }
+    pub fn set(&self, queue: &wgpu::Queue, value: &T) {
+        queue.write_buffer(&self.buffer, 0, bytemuck::bytes_of(value));
+    }
+    pub fn buffer(&self) -> &wgpu::Buffer {
+        &self.buffer
+    }
+    pub fn layout(&self) -> &wgpu::BindGroupLayout {
+        &self.layout
+    }
+    pub fn bind_group(&self) -> &wgpu::BindGroup {
+        &self.bind_group
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn requires_std140_padding() {
+        assert!(check_uniform_size(std::mem::size_of::<[u32; 2]>()).is_ok());
+        assert!(check_uniform_size(std::mem::size_of::<[f32; 4]>()).is_ok());
+        assert!(check_uniform_size(std::mem::size_of::<[f32; 8]>()).is_ok());
+        assert_eq!(
+            check_uniform_size(std::mem::size_of::<[f32; 5]>()),
+            Err(UniformSizeError { size: 20 })
+        );
+        assert!(check_uniform_size(0).is_err());
+    }
+
+    #[repr(C)]
+    #[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+    struct Misaligned {
+        a: [f32; 2],
+        b: f32,
+        c: [f32; 3],
+        d: f32,
+        _padding: f32,
+    }
+    impl_uniform!(Misaligned { a, b, c, d });
+
+    #[test]
+    fn checks_scalar_offsets_against_wgsl() {
+        let reflection = crate::reflect::reflect_wgsl(
+            "struct U { a: vec2<f32>, b: f32, c: vec3<f32>, d: f32 };
+            @group(0) @binding(0) var<uniform> u: U;
+            @fragment fn main() -> @location(0) vec4<f32> { return vec4<f32>(u.d); }",
+        )
+        .unwrap();
+        let shader = &reflection.binding("u").unwrap().scalars;
+        assert_eq!(shader, &[0, 4, 8, 16, 20, 24, 28]);
+        assert!(check_uniform_size(std::mem::size_of::<Misaligned>()).is_ok());
+        let err = check_uniform_layout::<Misaligned>(shader).unwrap_err();
+        assert_eq!(err.rust, [0, 4, 8, 12, 16, 20, 24]);
+        assert!(check_uniform_layout::<[f32; 2]>(&[0, 4]).is_ok());
+    }
+}
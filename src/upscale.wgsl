@@ -0,0 +1,21 @@
+#include "post_process"
+
+struct Upscale {
+    // Window position of the low resolution frame's top-left corner, may be negative.
+    offset: vec2<f32>,
+    // Window pixels per low resolution pixel.
+    scale: f32,
+};
+
+@group(1) @binding(0)
+var<uniform> effect_uniforms: Upscale;
+
+// Nearest neighbor, with black bars around the frame.
+fn effect(in: PostInput) -> vec4<f32> {
+    let texel = floor((in.frag_coord - effect_uniforms.offset) / effect_uniforms.scale);
+    let size = vec2<f32>(textureDimensions(source));
+    if any(texel < vec2<f32>(0.0)) || any(texel >= size) {
+        return vec4<f32>(0.0, 0.0, 0.0, 1.0);
+    }
+    return textureLoad(source, vec2<i32>(texel), 0);
+}
@@ -0,0 +1,18 @@
+#include "post_process"
+
+struct Vignette {
+    strength: f32,
+    // Distance from the center, with the corners at 1, where darkening starts.
+    radius: f32,
+};
+
+@group(1) @binding(0)
+var<uniform> effect_uniforms: Vignette;
+
+fn effect(in: PostInput) -> vec4<f32> {
+    let color = sample_source(in.uv);
+    let distance = length(in.uv - 0.5) * sqrt(2.0);
+    let radius = effect_uniforms.radius;
+    let darken = smoothstep(radius, max(radius, 1.0) + 0.25, distance) * effect_uniforms.strength;
+    return vec4<f32>(color.rgb * (1.0 - darken), color.a);
+}
@@ -0,0 +1,188 @@
+use std::rc::Rc;
+
+use crate::gfx::{texture_layout, GfxRenderData};
+use crate::pipeline::PipelineDescriptor;
+use crate::post::begin_pass;
+use crate::reflect::LayoutError;
+use crate::shader::Shader;
+use crate::texture::Texture;
+use crate::uniform::UniformBuffer;
+
+const UPSCALE_SHADER: &str = include_str!("upscale.wgsl");
+
+// Where a low resolution frame lands in the window when scaled up by a whole number. The
+// frame is centered, with black bars around it, and cropped if the window is smaller.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Letterbox {
+    // Window pixels per low resolution pixel, at least 1.
+    pub scale: u32,
+    // Window position of the frame's top-left corner.
+    pub offset: [f32; 2],
+}
+impl Letterbox {
+    pub fn new(
+        virtual_size: winit::dpi::PhysicalSize<u32>,
+        window_size: winit::dpi::PhysicalSize<u32>,
+    ) -> Self {
+        let scale = (window_size.width / virtual_size.width.max(1))
+            .min(window_size.height / virtual_size.height.max(1))
+            .max(1);
+        // Whole pixel offsets so every low resolution pixel covers the same window pixels.
+        let offset = |window: u32, frame: u32| (window as i64 - (frame * scale) as i64) / 2;
+        Self {
+            scale,
+            offset: [
+                offset(window_size.width, virtual_size.width) as f32,
+                offset(window_size.height, virtual_size.height) as f32,
+            ],
+        }
+    }
+    pub fn window_to_virtual(&self, point: [f32; 2]) -> [f32; 2] {
+        [
+            (point[0] - self.offset[0]) / self.scale as f32,
+            (point[1] - self.offset[1]) / self.scale as f32,
+        ]
+    }
+    pub fn virtual_to_window(&self, point: [f32; 2]) -> [f32; 2] {
+        [
+            point[0] * self.scale as f32 + self.offset[0],
+            point[1] * self.scale as f32 + self.offset[1],
+        ]
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct UpscaleUniforms {
+    offset: [f32; 2],
+    scale: f32,
+    _padding: f32,
+}
+
+struct UpscalePass {
+    shader: Shader,
+    pipeline: Rc<wgpu::RenderPipeline>,
+    uniforms: UniformBuffer<UpscaleUniforms>,
+}
+impl UpscalePass {
+    fn new(data: &GfxRenderData) -> Self {
+        let shader = Shader::new(data, "upscale.wgsl", UPSCALE_SHADER, &[]);
+        let uniforms = UniformBuffer::new(
+            &data.device,
+            wgpu::ShaderStages::FRAGMENT,
+            &bytemuck::Zeroable::zeroed(),
+            Some("upscale"),
+        );
+        let pipeline = Self::create_pipeline(data, &shader, &uniforms)
+            .unwrap_or_else(|err| panic!("upscale.wgsl: {}", err));
+        Self {
+            shader,
+            pipeline,
+            uniforms,
+        }
+    }
+    fn create_pipeline(
+        data: &GfxRenderData,
+        shader: &Shader,
+        uniforms: &UniformBuffer<UpscaleUniforms>,
+    ) -> Result<Rc<wgpu::RenderPipeline>, LayoutError> {
+        let reflection = shader.reflection();
+        reflection.check_vertex_layouts("vs_main", &[])?;
+        reflection.check_bind_group_layouts(&[
+            texture_layout(true).entries(),
+            UniformBuffer::<UpscaleUniforms>::layout_builder(wgpu::ShaderStages::FRAGMENT)
+                .entries(),
+        ])?;
+        let layouts = [data.texture_bind_group_layout(true), uniforms.layout()];
+        let desc = PipelineDescriptor {
+            format: data.surface_format,
+            depth_format: None,
+            depth_write: false,
+            ..PipelineDescriptor::new(data, shader, &[], &layouts)
+        };
+        Ok(data.pipelines.get(&data.device, &desc))
+    }
+}
+
+// The low resolution frame everything is drawn into, in the surface format, and the pass
+// scaling it up onto the surface. Built on the first frame, like `HdrOutput`.
+pub(crate) struct VirtualScreen {
+    pub(crate) size: winit::dpi::PhysicalSize<u32>,
+    pass: Option<UpscalePass>,
+    frame: Option<Texture>,
+}
+impl VirtualScreen {
+    pub(crate) fn new(size: winit::dpi::PhysicalSize<u32>) -> Self {
+        Self {
+            size,
+            pass: None,
+            frame: None,
+        }
+    }
+    pub(crate) fn letterbox(&self, window_size: winit::dpi::PhysicalSize<u32>) -> Letterbox {
+        Letterbox::new(self.size, window_size)
+    }
+    // Returns the frame to draw into.
+    pub(crate) fn prepare(&mut self, data: &GfxRenderData) -> &Texture {
+        let letterbox = self.letterbox(data.window_size);
+        let pass = self.pass.get_or_insert_with(|| UpscalePass::new(data));
+        if pass.shader.poll(data) {
+            match UpscalePass::create_pipeline(data, &pass.shader, &pass.uniforms) {
+                Ok(pipeline) => pass.pipeline = pipeline,
+                Err(err) => eprintln!("{}: {}", pass.shader.name(), err),
+            }
+        }
+        pass.uniforms.set(
+            &data.queue,
+            &UpscaleUniforms {
+                offset: letterbox.offset,
+                scale: letterbox.scale as f32,
+                _padding: 0.0,
+            },
+        );
+        let size = self.size;
+        self.frame.get_or_insert_with(|| {
+            Texture::render_target(data, size.width, size.height, data.surface_format)
+        })
+    }
+    pub(crate) fn render(&self, encoder: &mut wgpu::CommandEncoder, output: &wgpu::TextureView) {
+        let (Some(pass), Some(frame)) = (self.pass.as_ref(), self.frame.as_ref()) else {
+            return;
+        };
+        let mut render_pass = begin_pass(encoder, output, wgpu::LoadOp::Clear(wgpu::Color::BLACK));
+        render_pass.set_pipeline(&pass.pipeline);
+        render_pass.set_bind_group(0, &frame.bind_group, &[]);
+        render_pass.set_bind_group(1, pass.uniforms.bind_group(), &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reflect::check_shader;
+    use winit::dpi::PhysicalSize;
+
+    #[test]
+    fn letterbox_scales_by_whole_numbers() {
+        let letterbox = Letterbox::new(PhysicalSize::new(320, 180), PhysicalSize::new(1000, 700));
+        assert_eq!(letterbox.scale, 3);
+        assert_eq!(letterbox.offset, [20.0, 80.0]);
+        assert_eq!(letterbox.window_to_virtual([20.0, 80.0]), [0.0, 0.0]);
+        assert_eq!(letterbox.window_to_virtual([980.0, 620.0]), [320.0, 180.0]);
+        assert_eq!(letterbox.virtual_to_window([10.0, 10.0]), [50.0, 110.0]);
+
+        let cropped = Letterbox::new(PhysicalSize::new(320, 180), PhysicalSize::new(300, 200));
+        assert_eq!(cropped.scale, 1);
+        assert_eq!(cropped.offset, [-10.0, 10.0]);
+    }
+
+    #[test]
+    fn upscale_shader_matches_layouts() {
+        let texture = texture_layout(true);
+        let uniforms =
+            UniformBuffer::<UpscaleUniforms>::layout_builder(wgpu::ShaderStages::FRAGMENT);
+        let groups = [texture.entries(), uniforms.entries()];
+        check_shader("upscale.wgsl", UPSCALE_SHADER, &[], &[], &groups);
+    }
+}